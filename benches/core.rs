@@ -0,0 +1,149 @@
+//! Timings of the core board operations and the search on a few well-known positions, for judging changes that are
+//! meant to make things faster. Criterion keeps the results of the last run and reports the difference.
+//!
+//! Run with `cargo bench --no-default-features --bench core`, optionally followed by a filter like `-- move_gen`.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{Rng, SeedableRng};
+
+use chess::chess::*;
+use chess::computer_player::{ComputerPlayer, SearchLimits};
+use chess::player::Player;
+
+const POSITIONS: [(&str, &str); 4] = [
+    ("start", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+    ("kiwipete", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"),
+    ("middlegame", "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N2N2/PP2BPPP/R2QKB1R w KQ - 0 8"),
+    ("endgame", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"),
+];
+
+fn move_gen(c: &mut Criterion) {
+    let mut group = c.benchmark_group("move_gen");
+    let mut move_gen = MoveGenerator::new();
+    for (name, fen) in POSITIONS {
+        let board = Board::from_fen(fen).unwrap();
+        group.bench_function(name, |b| b.iter(|| {
+            let mut count = 0;
+            move_gen.generate_moves(black_box(&board), &mut |_| count += 1, false);
+            count
+        }));
+    }
+    group.finish();
+}
+
+fn count_moves(c: &mut Criterion) {
+    let mut group = c.benchmark_group("count_moves");
+    let mut move_gen = MoveGenerator::new();
+    for (name, fen) in POSITIONS {
+        let board = Board::from_fen(fen).unwrap();
+        group.bench_function(name, |b| b.iter(|| move_gen.count_moves(black_box(&board))));
+    }
+    group.finish();
+}
+
+fn make_unmake(c: &mut Criterion) {
+    let mut group = c.benchmark_group("make_unmake");
+    for (name, fen) in POSITIONS {
+        let mut board = Board::from_fen(fen).unwrap();
+        let moves = board.legal_moves();
+        group.throughput(Throughput::Elements(moves.len() as u64));
+        group.bench_function(name, |b| b.iter(|| {
+            for &mov in &moves {
+                board.make_move(black_box(mov), true);
+                board.unmake_move(mov, true);
+            }
+        }));
+    }
+    group.finish();
+}
+
+fn copy_make(c: &mut Criterion) {
+    let mut group = c.benchmark_group("copy_make");
+    for (name, fen) in POSITIONS {
+        let board = Board::from_fen(fen).unwrap();
+        let moves = board.legal_moves();
+        group.throughput(Throughput::Elements(moves.len() as u64));
+        group.bench_function(name, |b| b.iter(|| {
+            for &mov in &moves {
+                let mut state = *board.state();
+                state.make_move(black_box(mov));
+                black_box(state);
+            }
+        }));
+    }
+    group.finish();
+}
+
+fn in_check(c: &mut Criterion) {
+    let mut group = c.benchmark_group("in_check");
+    for (name, fen) in POSITIONS {
+        let board = Board::from_fen(fen).unwrap();
+        // Nothing is cached, so every call looks for attackers on the king
+        group.bench_function(name, |b| b.iter(|| black_box(&board).in_check(board.side_to_move())));
+    }
+    group.finish();
+}
+
+fn magic(c: &mut Criterion) {
+    let mut group = c.benchmark_group("magic");
+    // Sparse random boards, like in real games
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let lookups = (0..1024).map(|_| {
+        (Position::from_index(rng.random_range(0..64)), bitboards::BitBoard(rng.random::<u64>() & rng.random::<u64>() & rng.random::<u64>()))
+    }).collect::<Vec<_>>();
+    group.throughput(Throughput::Elements(lookups.len() as u64));
+    group.bench_function("orthogonal", |b| b.iter(|| {
+        lookups.iter().fold(0, |acc, &(pos, blockers)| acc ^ magic::get_orthogonal_attacks(black_box(pos), black_box(blockers)).0)
+    }));
+    group.bench_function("diagonal", |b| b.iter(|| {
+        lookups.iter().fold(0, |acc, &(pos, blockers)| acc ^ magic::get_diagonal_attacks(black_box(pos), black_box(blockers)).0)
+    }));
+    group.finish();
+}
+
+fn zobrist(c: &mut Criterion) {
+    let mut group = c.benchmark_group("zobrist");
+    let board = Board::new();
+    let pieces = (0..64).map(|index| (board.get(Position::from_index(index)), Position::from_index(index))).collect::<Vec<_>>();
+    group.bench_function("state", |b| b.iter(|| {
+        let mut hash = zobrist::ZobristHash::default();
+        hash.update_with_castle_state(black_box(board::CastleState::new(true, true, true, true)));
+        hash.update_with_en_passant_file(black_box(Some(4)));
+        hash.update_with_side_to_move();
+        hash
+    }));
+    group.throughput(Throughput::Elements(pieces.len() as u64));
+    group.bench_function("piece_pos", |b| b.iter(|| {
+        let mut hash = zobrist::ZobristHash::default();
+        for &(piece, pos) in &pieces {
+            hash.update_with_piece_pos(black_box(piece), black_box(pos));
+        }
+        hash
+    }));
+    group.finish();
+}
+
+fn search(c: &mut Criterion) {
+    const DEPTH: usize = 4;
+
+    let mut group = c.benchmark_group("search");
+    group.sample_size(10);
+    for (name, fen) in POSITIONS {
+        let game = Game::from_fen(fen).unwrap();
+        let mut player = ComputerPlayer::with_limits(game.side_to_move(), SearchLimits { depth: DEPTH, move_time: None });
+        player.set_verbose(false);
+        // Moves are shuffled before ordering, so the node count varies a little from search to search
+        player.begin_turn(&game);
+        group.throughput(Throughput::Elements(player.nodes() as u64));
+        group.bench_with_input(BenchmarkId::new(name, DEPTH), &game, |b, game| b.iter(|| {
+            player.begin_turn(game);
+            player.poll_move()
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, move_gen, count_moves, make_unmake, copy_make, in_check, magic, zobrist, search);
+criterion_main!(benches);
@@ -0,0 +1,45 @@
+//! Move generation speed in nodes per second, measured with perft on a few well-known positions.
+//!
+//! Run with `cargo bench --no-default-features --bench perft`.
+
+use std::time::Instant;
+
+use chess::chess::*;
+
+const POSITIONS: [(&str, &str, usize); 3] = [
+    ("start", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 5),
+    ("kiwipete", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 4),
+    ("endgame", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 6),
+];
+
+/// Counts the leaves in bulk, as engines usually do for perft. Moves are made on copies of the position, which
+/// takes them back for free.
+fn perft(state: &BoardState, move_gen: &mut MoveGenerator, depth: usize) -> usize {
+    if depth == 1 {
+        return move_gen.count_moves(state);
+    }
+    let mut moves = Vec::with_capacity(64);
+    move_gen.generate_moves(state, &mut |mov| moves.push(mov), false);
+    let mut nodes = 0;
+    for mov in moves {
+        let mut next = *state;
+        next.make_move(mov);
+        nodes += perft(&next, move_gen, depth - 1);
+    }
+    nodes
+}
+
+fn main() {
+    let mut move_gen = MoveGenerator::new();
+    let (mut total_nodes, mut total_secs) = (0, 0.0);
+    for (name, fen, depth) in POSITIONS {
+        let board = Board::from_fen(fen).unwrap();
+        let start = Instant::now();
+        let nodes = perft(board.state(), &mut move_gen, depth);
+        let secs = start.elapsed().as_secs_f64();
+        println!("{name:<10} depth {depth}: {nodes:>10} nodes in {secs:>6.2}s, {:>10.0} nps", nodes as f64 / secs);
+        total_nodes += nodes;
+        total_secs += secs;
+    }
+    println!("{:<18} {total_nodes:>10} nodes in {total_secs:>6.2}s, {:>10.0} nps", "total", total_nodes as f64 / total_secs);
+}
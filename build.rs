@@ -1,4 +1,104 @@
+use std::fmt::Write;
+
+#[path = "src/chess/magic/black_magic.rs"]
+mod black_magic;
+
+/// Writes the sliding attack table of every square and blocker subset, laid out by the black magics, as a Rust
+/// static to `$OUT_DIR/sliding_attacks.rs`.
+fn write_sliding_attacks() {
+    let mut table: Vec<Option<u64>> = Vec::new();
+    for ortho in [true, false] {
+        for square in 0..64 {
+            let mask = black_magic::relevant_blockers(square, ortho);
+            let mut blockers = 0u64;
+            // Visits every subset of the mask, ending with the empty set again
+            loop {
+                let index = black_magic::magic_index(square, !mask, blockers, ortho);
+                let attacks = black_magic::sliding_attacks(square, blockers, ortho);
+                if index >= table.len() {
+                    table.resize(index + 1, None);
+                }
+                match table[index] {
+                    Some(existing) => assert_eq!(existing, attacks, "Magic collision on square {square}"),
+                    None => table[index] = Some(attacks),
+                }
+                blockers = blockers.wrapping_sub(mask) & mask;
+                if blockers == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut source = format!("static SLIDING_ATTACKS: [u64; {}] = [\n", table.len());
+    for row in table.chunks(8) {
+        for attacks in row {
+            write!(source, "0x{:x},", attacks.unwrap_or(0)).unwrap();
+        }
+        source.push('\n');
+    }
+    source.push_str("];\n");
+
+    let path = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("sliding_attacks.rs");
+    std::fs::write(path, source).unwrap();
+}
+
+/// Software version of the BMI2 `pext` instruction: gathers the bits of `value` selected by `mask` into the low bits.
+fn pext(value: u64, mut mask: u64) -> u64 {
+    let mut result = 0;
+    let mut bit = 0;
+    while mask != 0 {
+        if value & mask & mask.wrapping_neg() != 0 {
+            result |= 1 << bit;
+        }
+        mask &= mask - 1;
+        bit += 1;
+    }
+    result
+}
+
+/// Writes the sliding attack table indexed by `pext` of the blockers, with the offset of each square's section, to
+/// `$OUT_DIR/pext_attacks.rs`. Rooks come first, then bishops.
+fn write_pext_attacks() {
+    let mut table = Vec::new();
+    let mut offsets = Vec::new();
+    for ortho in [true, false] {
+        for square in 0..64 {
+            let mask = black_magic::relevant_blockers(square, ortho);
+            let offset = table.len();
+            offsets.push(offset);
+            table.resize(offset + (1 << mask.count_ones()), 0);
+            let mut blockers = 0u64;
+            loop {
+                table[offset + pext(blockers, mask) as usize] = black_magic::sliding_attacks(square, blockers, ortho);
+                blockers = blockers.wrapping_sub(mask) & mask;
+                if blockers == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut source = format!("static PEXT_OFFSETS: [u32; 128] = {offsets:?};\n");
+    writeln!(source, "static PEXT_ATTACKS: [u64; {}] = [", table.len()).unwrap();
+    for row in table.chunks(8) {
+        for attacks in row {
+            write!(source, "0x{attacks:x},").unwrap();
+        }
+        source.push('\n');
+    }
+    source.push_str("];\n");
+
+    let path = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("pext_attacks.rs");
+    std::fs::write(path, source).unwrap();
+}
 
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
-}
\ No newline at end of file
+    println!("cargo:rerun-if-changed=src/chess/magic/black_magic.rs");
+    println!("cargo:rerun-if-changed=src/chess/magic/magics.rs");
+    write_sliding_attacks();
+    if std::env::var_os("CARGO_FEATURE_PEXT").is_some() {
+        write_pext_attacks();
+    }
+}
@@ -1,4 +0,0 @@
-text = input("Insert text:")
-words = text.split(", ")
-nums = [hex(int(word)) for word in words]
-print("[" + ", ".join(nums) + "]")
\ No newline at end of file
@@ -0,0 +1,78 @@
+use std::io::Write;
+
+use anyhow::{anyhow, bail, Context};
+use rand::SeedableRng;
+
+use chess::chess::magic::search::*;
+
+const USAGE: &str = "\
+Usage: chess-magics [options]
+
+Searches black magic numbers for rook and bishop attacks on every square, checks that none of them has a destructive
+collision and prints them with their packed table offsets, in the format of src/chess/magic/magics.rs. The size of
+the resulting attack table is reported next to the current one, so a new table only needs to be kept if it's smaller.
+
+Options:
+    --tries <n>          Magics to find per square before keeping the one with the smallest span (default: 10)
+    --seed <n>           Seed for the random numbers, to make a run repeatable (default: random)
+    --keep-current       Keep the magic in use for a square unless a smaller one turns up
+    --output <file>      Write the table to a file instead of standard output
+";
+
+fn main() -> anyhow::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print!("{USAGE}");
+        return Ok(());
+    }
+
+    let mut tries = 10;
+    let mut seed = rand::random::<u64>();
+    let mut output = None;
+    let mut keep_current = false;
+
+    let mut i = 0;
+    while i < args.len() {
+        let flag = args[i].as_str();
+        if flag == "--keep-current" {
+            keep_current = true;
+            i += 1;
+            continue;
+        }
+        let value = args.get(i + 1).ok_or_else(|| anyhow!("Missing value for {flag}"))?;
+        i += 2;
+        match flag {
+            "--tries" => tries = value.parse().with_context(|| format!("Invalid number of tries `{value}`"))?,
+            "--seed" => seed = value.parse().with_context(|| format!("Invalid seed `{value}`"))?,
+            "--output" => output = Some(value.clone()),
+            _ => bail!("Unknown option `{flag}`\n\n{USAGE}"),
+        }
+    }
+    if tries == 0 {
+        bail!("Need at least one try per square");
+    }
+
+    let current = current_magics();
+    let current_size = current.iter().map(|(magic, offset)| *offset as usize + magic.entries.last().unwrap().0 + 1).max().unwrap();
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let mut magics = Vec::new();
+    for (current, _) in current {
+        let (square, ortho) = (current.square, current.ortho);
+        let magic = find_magic(square, ortho, tries, keep_current.then_some(current), &mut rng);
+        eprintln!("{} {square:2}: 0x{:016X}, span {}", if ortho { "Rook  " } else { "Bishop" }, magic.magic, magic.span());
+        magics.push(magic);
+    }
+
+    let (offsets, table_size) = pack(&magics);
+    let table = magics.iter().zip(offsets).map(|(magic, offset)| (magic.magic, offset)).collect::<Vec<_>>();
+    let header = format!("Generated by `cargo run --release --bin chess-magics -- --tries {tries} --seed {seed}{}`", if keep_current { " --keep-current" } else { "" });
+    let source = to_source(&table[..64], &table[64..], table_size, &header);
+    eprintln!("Attack table size: {table_size} entries ({} KiB), currently {current_size}", table_size * 8 / 1024);
+
+    match output {
+        Some(path) => std::fs::write(&path, source).with_context(|| format!("Couldn't write {path}"))?,
+        None => std::io::stdout().write_all(source.as_bytes())?,
+    }
+    Ok(())
+}
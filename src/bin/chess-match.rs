@@ -0,0 +1,135 @@
+use std::io::Write;
+
+use anyhow::{anyhow, bail, Context};
+
+use chess::match_runner::*;
+
+const USAGE: &str = "\
+Usage: chess-match --engine <settings>... --engine <settings>... [options]
+
+Plays a match between two engines and reports the Elo difference of the first one.
+
+Engine settings are key=value pairs:
+    name=<name>          Name used in the PGN and the report
+    cmd=<command line>   Start a UCI engine instead of the built-in one
+    depth=<plies>        Maximum search depth
+    movetime=<ms>        Time per move
+    option.<name>=<val>  UCI option to set after the handshake
+
+Options:
+    --openings <file>    FEN per line, each played twice with colors swapped (default: standard position)
+    --games <n>          Number of games (default: 100)
+    --max-plies <n>      Adjudicate a draw after this many plies (default: 400)
+    --timeout <s>        Forfeit a player that takes longer for a move (default: 60)
+    --tc <time control>  Play on a clock, e.g. 10+0.1 or 40/60:30+0.5 (seconds, as in the PGN TimeControl tag)
+    --pgn <file>         Append the games to a PGN file
+    --sprt [settings]    Stop early with an SPRT verdict. Settings: elo0, elo1, alpha, beta (default: 0, 10, 0.05, 0.05)
+";
+
+fn parse_sprt<'a>(settings: impl IntoIterator<Item = &'a str>) -> anyhow::Result<Sprt> {
+    let mut sprt = Sprt::default();
+    for setting in settings {
+        let (key, value) = setting.split_once('=').ok_or_else(|| anyhow!("Expected key=value, got `{setting}`"))?;
+        let value = value.parse().with_context(|| format!("Invalid SPRT setting `{setting}`"))?;
+        match key {
+            "elo0" => sprt.elo0 = value,
+            "elo1" => sprt.elo1 = value,
+            "alpha" => sprt.alpha = value,
+            "beta" => sprt.beta = value,
+            _ => bail!("Unknown SPRT setting `{key}`"),
+        }
+    }
+    Ok(sprt)
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.is_empty() || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print!("{USAGE}");
+        return Ok(());
+    }
+
+    let mut engines = Vec::new();
+    let mut openings = vec![chess::chess::Board::new().to_fen()];
+    let mut settings = MatchSettings::default();
+    let mut pgn_path = None;
+
+    let mut i = 0;
+    while i < args.len() {
+        let flag = args[i].as_str();
+        i += 1;
+
+        // Settings of a flag are all following arguments that look like key=value
+        let start = i;
+        while i < args.len() && !args[i].starts_with("--") && args[i].contains('=') {
+            i += 1;
+        }
+        let values = args[start..i].iter().map(String::as_str);
+
+        let mut value = || -> anyhow::Result<&str> {
+            let value = args.get(i).ok_or_else(|| anyhow!("Missing value for `{flag}`"))?;
+            i += 1;
+            Ok(value)
+        };
+        match flag {
+            "--engine" => engines.push(EngineConfig::parse(values)?),
+            "--sprt" => settings.sprt = Some(parse_sprt(values)?),
+            "--openings" => openings = load_openings(value()?)?,
+            "--games" => settings.games = value()?.parse().context("Invalid number of games")?,
+            "--max-plies" => settings.max_plies = value()?.parse().context("Invalid number of plies")?,
+            "--timeout" => {
+                let text = value()?;
+                settings.move_timeout = text.parse().ok()
+                    .and_then(|secs| std::time::Duration::try_from_secs_f64(secs).ok())
+                    .filter(|timeout| !timeout.is_zero())
+                    .ok_or_else(|| anyhow!("Invalid timeout `{text}`, expected a positive number of seconds\n\n{USAGE}"))?;
+            },
+            "--tc" => settings.time_control = Some(chess::chess::TimeControl::parse(value()?)?),
+            "--pgn" => pgn_path = Some(value()?.to_string()),
+            _ => bail!("Unknown argument `{flag}`\n\n{USAGE}"),
+        }
+    }
+
+    let engines: [EngineConfig; 2] = engines.try_into().map_err(|_| anyhow!("Exactly two engines are needed\n\n{USAGE}"))?;
+
+    let mut pgn_file = match &pgn_path {
+        Some(path) => Some(std::fs::OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("Failed to open `{path}`"))?),
+        None => None,
+    };
+
+    println!("{} vs {}, {} games from {} openings", engines[0].name, engines[1].name, settings.games, openings.len());
+
+    let mut write_error = None;
+    let score = run_match(&engines, &openings, &settings, |game, round, score| {
+        let outcome = game.outcome().unwrap();
+        println!(
+            "Game {round}: {} - {} {} ({outcome}), score {score}",
+            game.tag("White").unwrap_or("?"), game.tag("Black").unwrap_or("?"), outcome.result_str(),
+        );
+        if let Some(sprt) = settings.sprt {
+            let (lower, upper) = sprt.bounds();
+            println!("    LLR {:.2} ({:.2}, {:.2})", sprt.llr(score), lower, upper);
+        }
+        if let Some(file) = pgn_file.as_mut() {
+            if let Err(err) = writeln!(file, "{}", game.to_pgn()) {
+                write_error.get_or_insert(err);
+            }
+        }
+    })?;
+    if let Some(err) = write_error {
+        return Err(err).context("Failed to write PGN");
+    }
+
+    println!();
+    println!("Score of {} vs {}: {score}", engines[0].name, engines[1].name);
+    if let Some(sprt) = settings.sprt {
+        let verdict = match sprt.verdict(&score) {
+            SprtVerdict::AcceptH1 => "H1 accepted",
+            SprtVerdict::AcceptH0 => "H0 accepted",
+            SprtVerdict::Continue => "inconclusive",
+        };
+        println!("SPRT [{}, {}]: LLR {:.2}, {verdict}", sprt.elo0, sprt.elo1, sprt.llr(&score));
+    }
+
+    Ok(())
+}
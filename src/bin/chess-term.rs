@@ -0,0 +1,259 @@
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context};
+
+use chess::chess::*;
+use chess::computer_player::{ComputerPlayer, SearchLimits};
+use chess::player::Player;
+
+const USAGE: &str = "\
+Usage: chess-term [options]
+
+Plays chess in the terminal, against the built-in engine or with two people at the same keyboard. Needs no display,
+so it also works over SSH. Moves are entered in SAN (Nf3, exd5, O-O, e8=Q) or in coordinates (g1f3, e7e8q).
+
+Options:
+    --computer <side>    Side the computer plays: white, black or none (default: black)
+    --depth <plies>      Maximum search depth of the computer (default: 5)
+    --movetime <ms>      Time the computer may spend on a move
+    --variant <name>     Variant to play (default: standard)
+    --fen <fen>          Start from this position instead of the initial one
+    --ascii              Draw the pieces as letters instead of chess symbols
+";
+
+const HELP: &str = "\
+Commands:
+    <move>               Play a move, in SAN or coordinates
+    new                  Start a new game from the initial position
+    undo                 Take back the last move, or your last move and the computer's reply
+    fen [<fen>]          Show the position as FEN, or set up a position
+    go                   Let the computer play the side to move from now on
+    flip                 Turn the board around
+    eval                 Show the static evaluation of the position
+    moves                List the legal moves
+    help                 Show this help
+    quit                 Leave the game
+";
+
+/// Chess symbols for each color, in the order of [`PieceKind`].
+const SYMBOLS: [[char; 6]; 2] = [['♙', '♘', '♗', '♖', '♕', '♔'], ['♟', '♞', '♝', '♜', '♛', '♚']];
+
+struct Session {
+    game: Game,
+    variant: Variant,
+    computer: Option<PieceColor>,
+    limits: SearchLimits,
+    flipped: bool,
+    ascii: bool,
+}
+
+impl Session {
+    fn piece_char(&self, piece: Piece) -> char {
+        if self.ascii {
+            piece.into_char()
+        } else {
+            SYMBOLS[piece.color as usize][piece.kind as usize - 1]
+        }
+    }
+
+    /// Prints the board with White at the bottom, unless flipped, followed by the pockets and whose turn it is.
+    fn draw(&self) {
+        let board = self.game.board();
+        let files = if self.flipped { [7, 6, 5, 4, 3, 2, 1, 0] } else { [0, 1, 2, 3, 4, 5, 6, 7] };
+        let ranks = files.map(|file| 7 - file);
+
+        println!();
+        for rank in ranks {
+            print!("{} ", rank + 1);
+            for file in files {
+                let pos = Position::new(rank, file);
+                print!(" {}", board.get(pos).map_or('.', |piece| self.piece_char(piece)));
+            }
+            println!();
+        }
+        print!("  ");
+        for file in files {
+            print!(" {}", (b'a' + file) as char);
+        }
+        println!();
+
+        if board.variant().has_pockets() {
+            for color in [PieceColor::White, PieceColor::Black] {
+                let pocket = board.pocket(color);
+                let pieces = Pocket::KINDS.into_iter()
+                    .flat_map(|kind| std::iter::repeat_n(self.piece_char(Piece::new(kind, color)), pocket.count(kind) as usize))
+                    .collect::<String>();
+                println!("{} pocket: {pieces}", if color.is_white() { "White" } else { "Black" });
+            }
+        }
+
+        if let Some(record) = self.game.last_record() {
+            println!("Last move: {}", record.san);
+        }
+        match self.game.outcome() {
+            Some(outcome) => println!("{outcome} ({})", outcome.result_str()),
+            None => {
+                let side = if board.side_to_move().is_white() { "White" } else { "Black" };
+                let check = if board.in_check(board.side_to_move()) { ", in check" } else { "" };
+                println!("{side} to move{check}");
+            },
+        }
+    }
+
+    fn computer_move(&mut self) {
+        let mut player = ComputerPlayer::with_limits(self.game.side_to_move(), self.limits);
+        player.set_verbose(false);
+        let start = Instant::now();
+        player.begin_turn(&self.game);
+        let Some(mov) = player.poll_move() else {
+            // Only happens without legal moves, which ends the game before the computer is asked
+            println!("The computer found no move");
+            self.computer = None;
+            return;
+        };
+        println!("Computer plays {} ({} nodes in {:.1}s)", mov.to_san(self.game.board()), player.nodes(), start.elapsed().as_secs_f64());
+        self.game.make_move(mov, None);
+    }
+
+    /// Handles one line of input. Returns whether the position changed, or `None` to quit.
+    fn command(&mut self, line: &str) -> Option<bool> {
+        let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let argument = argument.trim();
+        match command {
+            "" => {},
+            "quit" | "exit" => return None,
+            "help" => print!("{HELP}"),
+            "new" => {
+                self.game = Game::from_board(Board::with_variant(self.variant));
+                return Some(true);
+            },
+            "undo" => {
+                if self.game.undo().is_none() {
+                    println!("Nothing to take back");
+                    return Some(false);
+                }
+                // Take back the computer's reply as well, so that it's the player's turn again
+                if Some(self.game.side_to_move()) == self.computer {
+                    self.game.undo();
+                }
+                return Some(true);
+            },
+            "fen" if argument.is_empty() => println!("{}", self.game.board().to_fen()),
+            "fen" => match Board::from_variant_fen(self.variant, argument) {
+                Ok(board) => {
+                    self.game = Game::from_board(board);
+                    return Some(true);
+                },
+                Err(error) => println!("Invalid FEN: {error}"),
+            },
+            "go" => {
+                self.computer = Some(self.game.side_to_move());
+                return Some(true);
+            },
+            "flip" => {
+                self.flipped = !self.flipped;
+                return Some(true);
+            },
+            "eval" => {
+                let board = self.game.board();
+                let evaluation = ComputerPlayer::evaluate(board) * if board.side_to_move().is_white() { 1 } else { -1 };
+                println!("Evaluation: {:+.2} for White", evaluation as f64 / 100.0);
+            },
+            "moves" => {
+                let board = self.game.board();
+                let moves = board.legal_moves().iter().map(|mov| mov.to_san(board)).collect::<Vec<_>>();
+                println!("{}", moves.join(" "));
+            },
+            _ => {
+                if self.game.outcome().is_some() {
+                    println!("The game is over, start a new one with `new` or take back moves with `undo`");
+                } else if let Some(mov) = Move::parse(line, self.game.board()) {
+                    self.game.make_move(mov, None);
+                    return Some(true);
+                } else {
+                    println!("Unknown command or illegal move `{line}`, type `help` for a list of commands");
+                }
+            },
+        }
+        Some(false)
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print!("{USAGE}");
+        return Ok(());
+    }
+
+    let mut computer = Some(PieceColor::Black);
+    let mut limits = SearchLimits::default();
+    let mut variant = Variant::Standard;
+    let mut fen = None;
+    let mut ascii = false;
+
+    let mut i = 0;
+    while i < args.len() {
+        let flag = args[i].as_str();
+        if flag == "--ascii" {
+            ascii = true;
+            i += 1;
+            continue;
+        }
+        let value = args.get(i + 1).ok_or_else(|| anyhow!("Missing value for {flag}"))?;
+        i += 2;
+        match flag {
+            "--computer" => computer = match value.as_str() {
+                "white" => Some(PieceColor::White),
+                "black" => Some(PieceColor::Black),
+                "none" => None,
+                _ => bail!("Expected white, black or none for --computer, got `{value}`"),
+            },
+            "--depth" => limits.depth = value.parse().with_context(|| format!("Invalid depth `{value}`"))?,
+            "--movetime" => limits.move_time = Some(Duration::from_millis(value.parse().with_context(|| format!("Invalid move time `{value}`"))?)),
+            "--variant" => variant = Variant::from_name(value).ok_or_else(|| anyhow!("Unknown variant `{value}`"))?,
+            "--fen" => fen = Some(value.clone()),
+            _ => bail!("Unknown option `{flag}`\n\n{USAGE}"),
+        }
+    }
+
+    let board = match fen {
+        Some(fen) => Board::from_variant_fen(variant, &fen)?,
+        None => Board::with_variant(variant),
+    };
+    let mut session = Session {
+        game: Game::from_board(board),
+        variant,
+        computer,
+        limits,
+        flipped: computer == Some(PieceColor::White),
+        ascii,
+    };
+
+    println!("Type `help` for a list of commands");
+    let mut lines = std::io::stdin().lock().lines();
+    let mut redraw = true;
+    loop {
+        if session.game.outcome().is_none() && Some(session.game.side_to_move()) == session.computer {
+            session.computer_move();
+            redraw = true;
+            continue;
+        }
+        if redraw {
+            session.draw();
+        }
+
+        print!("> ");
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            println!();
+            break;
+        };
+        match session.command(line?.trim()) {
+            Some(changed) => redraw = changed,
+            None => break,
+        }
+    }
+    Ok(())
+}
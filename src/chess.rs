@@ -6,13 +6,20 @@ pub mod moves;
 pub mod zobrist;
 pub mod move_gen;
 pub mod magic;
+pub mod notation;
+pub mod game;
+pub mod clock;
+pub mod variant;
 mod util;
 
 pub use pieces::*;
 pub use position::Position;
-pub use board::Board;
-pub use moves::{Move, PromotionKind, MoveKind};
-pub use move_gen::{MoveGenerator, MoveTree};
+pub use board::{Board, BoardState, PlayedMove};
+pub use moves::{Move, PromotionKind, MoveKind, IllegalReason};
+pub use move_gen::{MoveGenerator, MoveTree, MoveStage, CheckInfo};
+pub use game::{Game, GameOutcome, MoveRecord};
+pub use clock::{Clock, Increment, TimeControl, TimeControlStage};
+pub use variant::{Variant, Pocket};
 
 pub trait IntoPacked: Sized + Copy + Clone {
     type Packed: Sized + Copy + Clone;
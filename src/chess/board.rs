@@ -1,25 +1,57 @@
 use super::*;
 use position::Position;
 use pieces::{Piece, PieceColor, PieceKind};
-use moves::{Move, MoveKind};
+use moves::{Move, MoveKind, PromotionKind};
+use arrayvec::ArrayVec;
 
+/// A position together with the moves that led to it. Derefs to its current [`BoardState`], which has all the
+/// queries about the position.
 #[derive(Debug, Clone, Default)]
 pub struct Board {
+    state: BoardState,
+    /// The state before each move played in the game, along with the move.
+    game_history: Vec<(BoardState, Move)>,
+    /// The states before each search or null move, which are always made after the game moves.
+    search_history: Vec<BoardState>,
+}
+
+/// Everything that makes up a position, without the history of how it was reached. It's `Copy` and small enough that
+/// search can make a move on a copy and drop the copy to take it back.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BoardState {
     piece_board: bitboards::PieceBoard,
-    white_king_pos: Option<Position>,
-    black_king_pos: Option<Position>,
-    all_pieces: bitboards::BitBoard,
-    all_white_pieces: bitboards::BitBoard,
-    all_black_pieces: bitboards::BitBoard,
-    white_pieces: bitboards::PieceWiseBitBoard,
-    black_pieces: bitboards::PieceWiseBitBoard,
-    white_in_check: std::sync::OnceLock<bool>,
-    black_in_check: std::sync::OnceLock<bool>,
+    /// Pieces of each kind of both colors, indexed by the kind's value minus one.
+    kind_pieces: [bitboards::BitBoard; 6],
+    /// Pieces of each color, white first.
+    color_pieces: [bitboards::BitBoard; 2],
     side_to_move: PieceColor,
+    castle_state: CastleState,
+    en_passant_file: Option<u8>,
+    fifty_move_counter: u8,
+    zobrist_hash: zobrist::ZobristHash,
+    /// Checks given by white and black in Three-check.
+    checks_given: [u8; 2],
+    /// Kind of the piece captured by the move that led to this state.
+    captured_piece_kind: Option<PieceKind>,
+    /// Crazyhouse pockets, indexed by color.
+    pockets: [Pocket; 2],
+    /// Pieces that were promoted from pawns and go back to being pawns when captured in Crazyhouse.
+    promoted: bitboards::BitBoard,
     ply_count: usize,
-    game_state_history: Vec<GameState>,
-    repeat_pos_history: Vec<zobrist::ZobristHash>,
-    all_game_moves: Vec<Move>    
+    castling_rooks: CastlingRooks,
+    chess960: bool,
+    variant: Variant,
+}
+
+// Copied on every move, so it should stay small
+const _: () = assert!(std::mem::size_of::<BoardState>() <= 256);
+
+impl std::ops::Deref for Board {
+    type Target = BoardState;
+
+    fn deref(&self) -> &BoardState {
+        &self.state
+    }
 }
 
 impl Board {
@@ -50,43 +82,170 @@ impl Board {
 
     pub fn reset_to(&mut self, board: [Option<Piece>; 64]) {
         *self = Default::default();
-        self.game_state_history.push(GameState::default());
 
         for (i, piece) in board.into_iter().enumerate() {
             let pos = Position::new((i >> 3) as u8, (i & 0x7) as u8);
-            self.set(pos, piece);
+            self.state.set(pos, piece);
         }
+        self.state.rehash();
     }
 
     pub fn reset(&mut self) {
         self.reset_to(Self::STANDARD_STARTING_BOARD);
     }
 
+    /// Index of the standard starting position among the Chess960 starting positions.
+    pub const STANDARD_CHESS960_INDEX: u16 = 518;
+
+    /// Piece placement of the Chess960 starting position with the given Scharnagl index (0..960).
+    pub const fn chess960_starting_board(index: u16) -> [Option<Piece>; 64] {
+        // Knight placements among the five squares left after placing bishops and queen
+        const KNIGHT_TABLE: [(u8, u8); 10] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)];
+
+        let mut back_rank: [Option<PieceKind>; 8] = [None; 8];
+        let n = index % 960;
+
+        back_rank[((n % 4) * 2 + 1) as usize] = Some(PieceKind::Bishop);
+        back_rank[(((n / 4) % 4) * 2) as usize] = Some(PieceKind::Bishop);
+
+        let mut n = n / 16;
+        let queen_slot = n % 6;
+        n /= 6;
+        let (knight_slot_a, knight_slot_b) = KNIGHT_TABLE[n as usize];
+
+        // Fill the remaining empty squares from the left: queen, knights, then rook, king, rook
+        let mut slot = 0u8;
+        let mut file = 0usize;
+        while file < 8 {
+            if back_rank[file].is_none() {
+                if slot as u16 == queen_slot {
+                    back_rank[file] = Some(PieceKind::Queen);
+                }
+                slot += 1;
+            }
+            file += 1;
+        }
+        let mut slot = 0u8;
+        let mut file = 0usize;
+        while file < 8 {
+            if back_rank[file].is_none() {
+                if slot == knight_slot_a || slot == knight_slot_b {
+                    back_rank[file] = Some(PieceKind::Knight);
+                }
+                slot += 1;
+            }
+            file += 1;
+        }
+        let remaining = [PieceKind::Rook, PieceKind::King, PieceKind::Rook];
+        let mut slot = 0usize;
+        let mut file = 0usize;
+        while file < 8 {
+            if back_rank[file].is_none() {
+                back_rank[file] = Some(remaining[slot]);
+                slot += 1;
+            }
+            file += 1;
+        }
+
+        let mut board = [None; 64];
+        let mut file = 0usize;
+        while file < 8 {
+            let kind = back_rank[file].unwrap();
+            board[file] = Some(Piece::new(kind, PieceColor::White));
+            board[8 + file] = Some(Piece::WHITE_PAWN);
+            board[48 + file] = Some(Piece::BLACK_PAWN);
+            board[56 + file] = Some(Piece::new(kind, PieceColor::Black));
+            file += 1;
+        }
+        board
+    }
+
+    /// Creates a board set up with the Chess960 starting position with the given Scharnagl index.
+    pub fn chess960(index: u16) -> Self {
+        let mut this = Self::default();
+        this.reset_to(Self::chess960_starting_board(index));
+        this.state.chess960 = true;
+        for kind in CastleKind::ALL {
+            let file = this.outermost_rook_file(kind.color(), kind.is_kingside()).unwrap();
+            this.state.castling_rooks.set_file(kind, file);
+        }
+        this
+    }
+
+    /// Creates a board set up with the starting position of `variant`.
+    pub fn with_variant(variant: Variant) -> Self {
+        let mut this = Self::new();
+        this.state.variant = variant;
+        if !variant.has_castling() {
+            this.state.castle_state = CastleState::new(false, false, false, false);
+        }
+        this.state.rehash();
+        this
+    }
+
     pub fn from_fen(fen: &str) -> anyhow::Result<Self> {
+        Self::from_variant_fen(Variant::Standard, fen)
+    }
+
+    /// Parses a FEN of a `variant` position. Crazyhouse pockets are read from brackets after the piece placement or
+    /// a ninth rank, promoted pieces are marked with `~`, and the checks left in Three-check are given as `3+3`
+    /// after the en passant square or as checks given like `+0+0` at the end.
+    pub fn from_variant_fen(variant: Variant, fen: &str) -> anyhow::Result<Self> {
         let mut this = Self::default();
-        this.game_state_history.push(GameState::default());
+        this.state.variant = variant;
+        this.state.castle_state = CastleState::new(false, false, false, false);
         let mut rank = 7u8;
         let mut file = 0u8;
-        let mut fen_iter = fen.splitn(6, ' ');
+        let mut fen_iter = fen.split_whitespace();
+
+        let mut placement = fen_iter.next().unwrap_or_default();
+        let mut pocket = None;
+        if let Some((board_part, pocket_part)) = placement.split_once('[') {
+            placement = board_part;
+            pocket = Some(pocket_part.trim_end_matches(']'));
+        } else if placement.matches('/').count() == 8 {
+            let (board_part, pocket_part) = placement.rsplit_once('/').unwrap();
+            placement = board_part;
+            pocket = Some(pocket_part);
+        }
+
+        // Pieces in hand
+        if let Some(pocket) = pocket {
+            if !variant.has_pockets() {
+                anyhow::bail!("{variant} has no pockets");
+            }
+            for c in pocket.chars().filter(|&c| c != '-') {
+                match Piece::from_char(c) {
+                    Some(piece) if piece.kind != PieceKind::King => this.state.pockets[piece.color.is_black() as usize].add(piece.kind),
+                    _ => anyhow::bail!("Unexpected character '{c}' in pocket")
+                }
+            }
+        }
 
         // Piece placement
-        for c in fen_iter.next().unwrap().chars() {
-            let pos = Position::new(rank, file);
+        for c in placement.chars() {
             if let Some(piece) = Piece::from_char(c) {
-                // println!("{piece} -> {pos} ({rank} {file})");
-                this.set(pos, Some(piece));
+                if file >= 8 {
+                    anyhow::bail!("Rank {} of the FEN string has more than 8 squares", rank + 1);
+                }
+                this.state.set(Position::new(rank, file), Some(piece));
                 file += 1;
             } else {
                 match c {
-                    '1' => file += 1,
-                    '2' => file += 2,
-                    '3' => file += 3,
-                    '4' => file += 4,
-                    '5' => file += 5,
-                    '6' => file += 6,
-                    '7' => file += 7,
-                    '8' => file += 8,
+                    '~' if file > 0 => this.state.promoted.set(Position::new(rank, file - 1)),
+                    '1'..='8' => {
+                        file += c as u8 - b'0';
+                        if file > 8 {
+                            anyhow::bail!("Rank {} of the FEN string has more than 8 squares", rank + 1);
+                        }
+                    },
                     '/' => {
+                        if file != 8 {
+                            anyhow::bail!("Rank {} of the FEN string has {file} squares instead of 8", rank + 1);
+                        }
+                        if rank == 0 {
+                            anyhow::bail!("FEN string has more than 8 ranks");
+                        }
                         rank -= 1;
                         file = 0;
                     },
@@ -94,52 +253,337 @@ impl Board {
                 }
             }
         }
+        if rank != 0 || file != 8 {
+            anyhow::bail!("FEN string doesn't describe all 64 squares");
+        }
+        if variant.has_royal_king() {
+            for color in [PieceColor::White, PieceColor::Black] {
+                let kings = this.pieces_of(color, PieceKind::King).count();
+                if kings != 1 {
+                    anyhow::bail!("{} has {kings} kings instead of one", if color.is_white() { "White" } else { "Black" });
+                }
+            }
+        }
+
+        let mut field = |name: &str| fen_iter.next().ok_or_else(|| anyhow::anyhow!("FEN string is missing the {name}"));
 
         // Side to move
-        match fen_iter.next().unwrap() {
+        match field("side to move")? {
             "w" => {
-                this.side_to_move = PieceColor::White;
+                this.state.side_to_move = PieceColor::White;
             },
             "b" => {
-                this.side_to_move = PieceColor::Black;
+                this.state.side_to_move = PieceColor::Black;
             },
             s => anyhow::bail!("Invalid side to move parameter '{s}'")
         }
 
-        // Castling rights
-        for c in fen_iter.next().unwrap().chars() {
-            let gamestate = this.current_game_state_mut();
-            match c {
-                'K' => gamestate.castle_state.set(CastleKind::WhiteKingside),
-                'Q' => gamestate.castle_state.set(CastleKind::WhiteQueenside),
-                'k' => gamestate.castle_state.set(CastleKind::BlackKingside),
-                'q' => gamestate.castle_state.set(CastleKind::BlackQueenside),
-                '-' => {}, // Empty set
-                c => anyhow::bail!("Unexpected character '{c}' in castling rights parameter")
+        // Castling rights, as in standard FEN, X-FEN or Shredder-FEN
+        for c in field("castling rights")?.chars() {
+            if c == '-' {
+                continue; // Empty set
+            }
+            let color = if c.is_ascii_uppercase() { PieceColor::White } else { PieceColor::Black };
+            let Some(king_pos) = this.king_pos_checked(color).filter(|pos| pos.rank() == if color.is_white() { 0 } else { 7 }) else {
+                anyhow::bail!("Castling right '{c}' without a king on the back rank")
+            };
+            let (kingside, rook_file) = match c.to_ascii_lowercase() {
+                'k' => (true, this.outermost_rook_file(color, true)),
+                'q' => (false, this.outermost_rook_file(color, false)),
+                c @ 'a'..='h' => {
+                    let file = c as u8 - b'a';
+                    this.state.chess960 = true;
+                    (file > king_pos.file(), Some(file))
+                },
+                _ => anyhow::bail!("Unexpected character '{c}' in castling rights parameter")
+            };
+            let kind = CastleKind::new(color, kingside);
+            match rook_file {
+                Some(file) if this.get(Position::new(king_pos.rank(), file)) == Some(Piece::new(PieceKind::Rook, color)) => {
+                    this.state.castling_rooks.set_file(kind, file);
+                    this.state.castle_state.set(kind);
+                    // Castling from anywhere but the standard squares can only happen in Chess960
+                    this.state.chess960 |= king_pos.file() != 4 || file != if kingside { 7 } else { 0 };
+                },
+                _ => anyhow::bail!("Castling right '{c}' without a rook to castle with")
             }
         }
 
         // En passant target square
         {
-            let mut chars = fen_iter.next().unwrap().chars();
-            let rchar = chars.next().unwrap();
-            if rchar != '-' {
-                let fchar = chars.next().unwrap();
-                if let Some(pos) = Position::from_chars(rchar, fchar) {
-                    let gamestate = this.current_game_state_mut();
-                    gamestate.en_passant_file.replace(pos.file());
-                }
-            } 
+            let en_passant = field("en passant square")?;
+            if en_passant != "-" {
+                let mut chars = en_passant.chars();
+                let pos = match (chars.next(), chars.next(), chars.next()) {
+                    (Some(fchar), Some(rchar), None) => Position::from_chars(rchar, fchar),
+                    _ => None,
+                };
+                let Some(pos) = pos else {
+                    anyhow::bail!("Invalid en passant square '{en_passant}'")
+                };
+                this.state.en_passant_file = Some(pos.file());
+            }
+        }
+
+        // Remaining checks
+        let mut next = fen_iter.next();
+        if variant == Variant::ThreeCheck {
+            if let Some((white, black)) = next.and_then(|field| field.split_once('+')).filter(|(white, _)| !white.is_empty()) {
+                let remaining = [white.parse::<u8>()?, black.parse::<u8>()?];
+                this.state.checks_given = remaining.map(|left| Variant::CHECKS_TO_WIN.saturating_sub(left));
+                next = fen_iter.next();
+            }
         }
 
         // Halfmove counter
-        this.current_game_state_mut().fifty_move_counter = fen_iter.next().unwrap().parse::<u8>()?;
+        this.state.fifty_move_counter = next.ok_or_else(|| anyhow::anyhow!("FEN string is missing the halfmove clock"))?.parse::<u8>()?;
+
+        // Fullmove counter
+        if let Some(fullmove) = fen_iter.next() {
+            let fullmove = fullmove.trim().parse::<usize>()?.max(1);
+            this.state.ply_count = (fullmove - 1) * 2 + this.state.side_to_move.is_black() as usize;
+        }
 
-        // Fullmove counter doesn't have a use at the moment
+        // Checks given
+        if let Some(checks) = fen_iter.next().filter(|_| variant == Variant::ThreeCheck) {
+            let mut counts = checks.trim_start_matches('+').split('+');
+            let mut parse_count = || counts.next().ok_or_else(|| anyhow::anyhow!("Invalid check counts '{checks}'"))?.parse::<u8>().map_err(anyhow::Error::from);
+            this.state.checks_given = [parse_count()?, parse_count()?];
+        }
 
+        this.state.rehash();
         Ok(this)
     }
 
+    /// Starts a board at `state`, with no moves played yet.
+    pub fn from_state(state: BoardState) -> Self {
+        Self { state, ..Default::default() }
+    }
+
+    /// The current position, to copy and play moves on without touching the board.
+    pub fn state(&self) -> &BoardState {
+        &self.state
+    }
+
+    /// Moves played on this board outside of search, oldest first.
+    pub fn move_history(&self) -> impl DoubleEndedIterator<Item = PlayedMove> + ExactSizeIterator + '_ {
+        self.game_history.iter().enumerate().map(move |(i, (before, mov))| {
+            let mover = before.side_to_move;
+            // The state a move left behind was saved before the next move, unless it's still the current one
+            let after = self.game_history.get(i + 1).map(|(state, _)| state)
+                .or(self.search_history.first())
+                .unwrap_or(&self.state);
+            PlayedMove {
+                mov: *mov,
+                mover,
+                captured_piece: after.captured_piece_kind.map(|kind| Piece::new(kind, !mover)),
+                castle_state: after.castle_state,
+                en_passant_file: after.en_passant_file,
+            }
+        })
+    }
+
+    /// The most recent move played on this board outside of search.
+    pub fn last_move(&self) -> Option<PlayedMove> {
+        self.move_history().next_back()
+    }
+
+    /// How many times the current position has occurred, counting the current occurrence. Positions are compared
+    /// by side to move, piece placement, castling rights and en passant file.
+    pub fn repetition_count(&self) -> usize {
+        let current = &self.state;
+        let previous = self.game_history.iter().map(|(state, _)| state).chain(&self.search_history);
+
+        // Positions before the last pawn move or capture can't repeat
+        1 + previous
+            .rev()
+            .take(current.fifty_move_counter as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|state| state.zobrist_hash == current.zobrist_hash)
+            .count()
+    }
+
+    /// Plays `move_to_make`, which has to be legal. Game moves show up in the move history, while search moves are
+    /// only kept until they are unmade.
+    pub fn make_move(&mut self, move_to_make: Move, in_search: bool) {
+        if in_search {
+            self.search_history.push(self.state);
+        } else {
+            debug_assert!(self.search_history.is_empty(), "Played a game move on top of search moves");
+            self.game_history.push((self.state, move_to_make));
+        }
+        self.state.make_move(move_to_make);
+    }
+
+    /// Takes back `move_to_unmake`, which has to be the last move made.
+    pub fn unmake_move(&mut self, move_to_unmake: Move, in_search: bool) {
+        if in_search {
+            self.state = self.search_history.pop().unwrap();
+        } else {
+            let (state, last_move) = self.game_history.pop().unwrap();
+            debug_assert_eq!(last_move, move_to_unmake, "Unmade a move that wasn't the last one played");
+            self.state = state;
+        }
+    }
+
+    /// Passes the turn as [`BoardState::make_null_move`] does. Like search moves, null moves don't show up in the
+    /// move history.
+    pub fn make_null_move(&mut self) -> Result<(), moves::IllegalReason> {
+        let before = self.state;
+        self.state.make_null_move()?;
+        self.search_history.push(before);
+        Ok(())
+    }
+
+    /// Takes back a null move, which has to be the last move made.
+    pub fn unmake_null_move(&mut self) {
+        self.state = self.search_history.pop().unwrap();
+    }
+}
+
+impl BoardState {
+    /// Whether castling follows the Chess960 rules, which changes how castling rights and castling moves are written.
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Starting square of the rook that castles with the given castling right.
+    pub fn castling_rook(&self, kind: CastleKind) -> Position {
+        Position::new(match kind.color() {
+            PieceColor::White => 0,
+            PieceColor::Black => 7,
+        }, self.castling_rooks.file(kind))
+    }
+
+    /// The outermost rook of `color` on its back rank on the given side of its king.
+    fn outermost_rook_file(&self, color: PieceColor, kingside: bool) -> Option<u8> {
+        let king_pos = self.king_pos_checked(color)?;
+        let files = self.pieces_of(color, PieceKind::Rook).iter_positions()
+            .filter(|pos| pos.rank() == king_pos.rank())
+            .map(|pos| pos.file())
+            .filter(|&file| (file > king_pos.file()) == kingside);
+        if kingside { files.max() } else { files.min() }
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// The winner, if a rule of the board's variant ended the game.
+    pub fn variant_winner(&self) -> Option<PieceColor> {
+        self.variant.winner(self)
+    }
+
+    /// Pieces `color` can drop in Crazyhouse.
+    pub fn pocket(&self, color: PieceColor) -> Pocket {
+        self.pockets[color.is_black() as usize]
+    }
+
+    /// Checks `color` has given so far in Three-check.
+    pub fn checks_given(&self, color: PieceColor) -> u8 {
+        self.checks_given[color.is_black() as usize]
+    }
+
+    /// Writes the position as FEN. Castling rights of Chess960 positions are written as in X-FEN, using the rook's
+    /// file only when another rook stands between it and the corner.
+    pub fn to_fen(&self) -> String {
+        self.fen(false)
+    }
+
+    /// Writes the position as Shredder-FEN, which always uses the rooks' files for the castling rights.
+    pub fn to_shredder_fen(&self) -> String {
+        self.fen(true)
+    }
+
+    fn fen(&self, shredder: bool) -> String {
+        let mut fen = String::new();
+
+        // Piece placement
+        for rank in (0u8..8).rev() {
+            let mut empty = 0u8;
+            for file in 0u8..8 {
+                if let Some(piece) = self.get(Position::new(rank, file)) {
+                    if empty > 0 {
+                        fen.push((b'0' + empty) as char);
+                        empty = 0;
+                    }
+                    fen.push(piece.into_char());
+                    if self.variant.has_pockets() && self.promoted.get(Position::new(rank, file)) {
+                        fen.push('~');
+                    }
+                } else {
+                    empty += 1;
+                }
+            }
+            if empty > 0 {
+                fen.push((b'0' + empty) as char);
+            }
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        // Pieces in hand
+        if self.variant.has_pockets() {
+            fen.push('[');
+            for color in [PieceColor::White, PieceColor::Black] {
+                let pocket = self.pocket(color);
+                for kind in Pocket::KINDS {
+                    for _ in 0..pocket.count(kind) {
+                        fen.push(Piece::new(kind, color).into_char());
+                    }
+                }
+            }
+            fen.push(']');
+        }
+
+        // Side to move
+        fen.push_str(match self.side_to_move {
+            PieceColor::White => " w ",
+            PieceColor::Black => " b ",
+        });
+
+        // Castling rights
+        let castle_state = self.castle_state;
+        let mut any_castle = false;
+        for kind in CastleKind::ALL {
+            if castle_state.get(kind) {
+                let file = self.castling_rooks.file(kind);
+                let c = if shredder || self.outermost_rook_file(kind.color(), kind.is_kingside()) != Some(file) {
+                    (b'a' + file) as char
+                } else if kind.is_kingside() { 'k' } else { 'q' };
+                fen.push(if kind.color().is_white() { c.to_ascii_uppercase() } else { c });
+                any_castle = true;
+            }
+        }
+        if !any_castle {
+            fen.push('-');
+        }
+
+        // En passant target square
+        if let Some(file) = self.en_passant_file() {
+            let rank = match self.side_to_move {
+                PieceColor::White => 5,
+                PieceColor::Black => 2,
+            };
+            fen.push_str(&format!(" {}", Position::new(rank, file)));
+        } else {
+            fen.push_str(" -");
+        }
+
+        // Remaining checks
+        if self.variant == Variant::ThreeCheck {
+            let remaining = |color| Variant::CHECKS_TO_WIN.saturating_sub(self.checks_given(color));
+            fen.push_str(&format!(" {}+{}", remaining(PieceColor::White), remaining(PieceColor::Black)));
+        }
+
+        // Halfmove and fullmove counters
+        fen.push_str(&format!(" {} {}", self.halfmove_clock(), self.fullmove_number()));
+
+        fen
+    }
+
     pub fn get(&self, pos: Position) -> Option<Piece> {
         self.piece_board.get(pos)
     }
@@ -150,81 +594,51 @@ impl Board {
 
     pub fn set(&mut self, pos: Position, piece: Option<Piece>) -> Option<Piece> {
         let old_piece = self.piece_board.get(pos);
-        match old_piece.map(|piece| (piece.color, piece.kind)) {
-            Some((PieceColor::White, kind)) => {
-                self.all_pieces.clear(pos);
-                self.all_white_pieces.clear(pos);
-                self.white_pieces.clear(pos, kind);
-                if kind == PieceKind::King {
-                    self.white_king_pos.take();
-                }
-            },
-            Some((PieceColor::Black, kind)) => {
-                self.all_pieces.clear(pos);
-                self.all_black_pieces.clear(pos);
-                self.black_pieces.clear(pos, kind);
-                if kind == PieceKind::King {
-                    self.black_king_pos.take();
-                }
-            },
-            None => {},
+        if let Some(old_piece) = old_piece {
+            self.kind_pieces[old_piece.kind as usize - 1].clear(pos);
+            self.color_pieces[old_piece.color.is_black() as usize].clear(pos);
         }
         self.piece_board.set(pos, piece);
-        match piece.map(|piece| (piece.color, piece.kind)) {
-            Some((PieceColor::White, kind)) => {
-                self.all_pieces.set(pos);
-                self.all_white_pieces.set(pos);
-                self.white_pieces.set(pos, kind);
-                if kind == PieceKind::King {
-                    self.white_king_pos.replace(pos);
-                }
-            },
-            Some((PieceColor::Black, kind)) => {
-                self.all_pieces.set(pos);
-                self.all_black_pieces.set(pos);
-                self.black_pieces.set(pos, kind);
-                if kind == PieceKind::King {
-                    self.black_king_pos.replace(pos);
-                }
-            },
-            None => {},
+        if let Some(piece) = piece {
+            self.kind_pieces[piece.kind as usize - 1].set(pos);
+            self.color_pieces[piece.color.is_black() as usize].set(pos);
         }
         old_piece
     }
 
     pub fn all_pieces(&self) -> bitboards::BitBoard {
-        self.all_pieces
+        self.color_pieces[0] | self.color_pieces[1]
     }
 
     pub fn pieces(&self, color: PieceColor) -> bitboards::BitBoard {
-        match color {
-            PieceColor::White => self.all_white_pieces,
-            PieceColor::Black => self.all_black_pieces,
-        }
+        self.color_pieces[color.is_black() as usize]
     }
 
     pub fn pieces_of(&self, color: PieceColor, kind: PieceKind) -> bitboards::BitBoard {
-        match color {
-            PieceColor::White => self.white_pieces.bitboard_of(kind),
-            PieceColor::Black => self.black_pieces.bitboard_of(kind),
-        }
+        self.kind_pieces[kind as usize - 1] & self.pieces(color)
     }
 
     pub fn pieces_of_color(&self, color: PieceColor) -> bitboards::PieceWiseBitBoard {
-        match color {
-            PieceColor::White => self.white_pieces,
-            PieceColor::Black => self.black_pieces,
+        bitboards::PieceWiseBitBoard {
+            pawns: self.pieces_of(color, PieceKind::Pawn),
+            knights: self.pieces_of(color, PieceKind::Knight),
+            bishops: self.pieces_of(color, PieceKind::Bishop),
+            rooks: self.pieces_of(color, PieceKind::Rook),
+            queens: self.pieces_of(color, PieceKind::Queen),
+            king: self.pieces_of(color, PieceKind::King),
         }
     }
 
-
     pub fn king_pos(&self, color: PieceColor) -> Position {
-        match color {
-            PieceColor::White => self.white_king_pos.unwrap(),
-            PieceColor::Black => self.black_king_pos.unwrap(),
-        }
+        self.king_pos_checked(color).unwrap()
     }
 
+    fn king_pos_checked(&self, color: PieceColor) -> Option<Position> {
+        let kings = self.pieces_of(color, PieceKind::King).0;
+        (kings != 0).then(|| Position::from_index(kings.trailing_zeros() as u8))
+    }
+
+    /// Value of the pieces of `color` on the board and in its pocket.
     pub fn total_value(&self, color: PieceColor) -> i32 {
         let mut sum = 0;
         for pos in self.pieces(color).iter_positions() {
@@ -232,68 +646,160 @@ impl Board {
                 sum += piece.value();
             }
         }
+        let pocket = self.pocket(color);
+        for kind in Pocket::KINDS {
+            sum += kind.value() * pocket.count(kind) as i32;
+        }
         sum
     }
 
+    /// Whether `color` has enough material to checkmate: more than a lone king or a king with a single bishop or
+    /// knight, counting pieces in the pocket. The rare mates a lone minor piece gets help with from the opponent's
+    /// own pieces are ignored.
+    pub fn has_mating_material(&self, color: PieceColor) -> bool {
+        let pocket = self.pocket(color);
+        if Pocket::KINDS.into_iter().any(|kind| pocket.count(kind) > 0) {
+            return true;
+        }
+        let pieces = self.pieces(color);
+        let minors = self.pieces_of(color, PieceKind::Bishop) | self.pieces_of(color, PieceKind::Knight);
+        pieces.count() > 2 || (pieces & !minors).count() > 1
+    }
+
     pub fn side_to_move(&self) -> PieceColor {
         self.side_to_move
     }
 
+    /// Number of half-moves played since the start of the game, including those before the starting FEN.
+    pub fn ply_count(&self) -> usize {
+        self.ply_count
+    }
+
+    pub fn fullmove_number(&self) -> usize {
+        (self.ply_count / 2) + 1
+    }
+
+    pub fn can_castle(&self, kind: CastleKind) -> bool {
+        self.castle_state.get(kind)
+    }
+
     pub fn can_kingside_castle(&self, color: PieceColor) -> bool {
-        self.current_game_state().castle_state.get(match color {
-            PieceColor::White => CastleKind::WhiteKingside,
-            PieceColor::Black => CastleKind::BlackKingside,
-        })
+        self.can_castle(CastleKind::new(color, true))
     }
 
     pub fn can_queenside_castle(&self, color: PieceColor) -> bool {
-        self.current_game_state().castle_state.get(match color {
-            PieceColor::White => CastleKind::WhiteQueenside,
-            PieceColor::Black => CastleKind::BlackQueenside,
-        })
+        self.can_castle(CastleKind::new(color, false))
     }
 
     pub fn en_passant_file(&self) -> Option<u8> {
-        self.current_game_state().en_passant_file
+        self.en_passant_file
     }
 
-    fn current_game_state(&self) -> &GameState {
-        self.game_state_history.last().unwrap()
+    pub fn halfmove_clock(&self) -> u8 {
+        self.fifty_move_counter
     }
 
-    fn current_game_state_mut(&mut self) -> &mut GameState {
-        self.game_state_history.last_mut().unwrap()
+    /// Hash of the position, kept up to date as moves are made.
+    pub fn zobrist_hash(&self) -> zobrist::ZobristHash {
+        self.zobrist_hash
     }
 
-    pub fn make_move(&mut self, move_to_make: Move, in_search: bool) {
+    /// Hashes the position from scratch: its pieces, castling rights, en passant file and side to move, plus the
+    /// pockets or given checks in variants that have them. Should always equal [`BoardState::zobrist_hash`].
+    pub fn compute_zobrist_from_scratch(&self) -> zobrist::ZobristHash {
+        let mut hash = zobrist::ZobristHash::default();
+        for pos in self.all_pieces().iter_positions() {
+            hash.update_with_piece_pos(self.get(pos), pos);
+        }
+        hash.update_with_castle_state(self.castle_state);
+        hash.update_with_en_passant_file(self.en_passant_file);
+        if self.side_to_move.is_black() {
+            hash.update_with_side_to_move();
+        }
+        for color in [PieceColor::White, PieceColor::Black] {
+            if self.variant.has_pockets() {
+                for kind in Pocket::KINDS {
+                    hash.update_with_pocket_count(color, kind, self.pocket(color).count(kind));
+                }
+            }
+            if self.variant == Variant::ThreeCheck {
+                hash.update_with_checks_given(color, self.checks_given(color));
+            }
+        }
+        hash
+    }
+
+    /// Sets the hash of a freshly set up position.
+    fn rehash(&mut self) {
+        self.zobrist_hash = self.compute_zobrist_from_scratch();
+    }
+
+    /// Plays `move_to_make`, which has to be legal. There's no way to take it back, so keep a copy of the state
+    /// from before the move for that.
+    pub fn make_move(&mut self, move_to_make: Move) {
         let src = move_to_make.src();
         let dst = move_to_make.dst();
         let move_kind = move_to_make.kind();
 
-        let piece = self.get(src).unwrap();
+        let piece = move_to_make.piece(self);
         let captured_piece = if move_kind == MoveKind::EnPassant {
             Some(Piece::new(PieceKind::Pawn, !self.side_to_move))
         } else {
             self.get(dst).and_then(|piece| if piece.color != self.side_to_move { Some(piece) } else { None })
         };
 
-        let current_game_state = *self.current_game_state();
-
-        let prev_castle_state = current_game_state.castle_state;
-        let prev_en_passant_file = current_game_state.en_passant_file;
-        let mut new_zobrist_hash = current_game_state.zobrist_hash;
-        let mut new_castle_state = current_game_state.castle_state;
+        let prev_castle_state = self.castle_state;
+        let prev_en_passant_file = self.en_passant_file;
+        let mut new_zobrist_hash = self.zobrist_hash;
+        let mut new_castle_state = self.castle_state;
         let mut new_en_passant_file = None;
+        let mut new_checks_given = self.checks_given;
 
-        self.remove(src);
-        self.set(dst, Some(piece));
+        if self.variant.has_pockets() {
+            // Captured pieces change sides, with promoted ones going back to being pawns
+            let captured_promoted = captured_piece.is_some() && self.promoted.get(dst);
+            if let Some(captured_piece) = captured_piece {
+                self.update_pocket(self.side_to_move, if captured_promoted { PieceKind::Pawn } else { captured_piece.kind }, true, &mut new_zobrist_hash);
+            }
+            self.promoted.clear(dst);
+            if self.promoted.get(src) {
+                self.promoted.clear(src);
+                self.promoted.set(dst);
+            }
+            if move_to_make.is_promotion() {
+                self.promoted.set(dst);
+            }
+        }
+
+        if let Some(kind) = move_to_make.drop_kind() {
+            self.update_pocket(self.side_to_move, kind, false, &mut new_zobrist_hash);
+            self.set(dst, Some(piece));
+        } else if move_kind == MoveKind::Castle {
+            // The king "captures" its own rook, after which both go to their castled squares
+            let rook = Piece::new(PieceKind::Rook, self.side_to_move);
+            let (king_dst, rook_dst) = move_to_make.castle_destinations();
+            self.remove(src);
+            self.remove(dst);
+            self.set(king_dst, Some(piece));
+            self.set(rook_dst, Some(rook));
+
+            new_zobrist_hash.update_with_piece_pos(Some(piece), src);
+            new_zobrist_hash.update_with_piece_pos(Some(piece), king_dst);
+            new_zobrist_hash.update_with_piece_pos(Some(rook), dst);
+            new_zobrist_hash.update_with_piece_pos(Some(rook), rook_dst);
+        } else {
+            self.remove(src);
+            self.set(dst, Some(piece));
+
+            new_zobrist_hash.update_with_piece_pos(Some(piece), src);
+        }
 
         if captured_piece.is_some() {
             let mut capture_pos = dst;
             if move_kind == MoveKind::EnPassant {
                 capture_pos = Position::new(dst.rank().wrapping_add_signed(match self.side_to_move {
-                    PieceColor::White =>  1i8,
-                    PieceColor::Black => -1i8,
+                    PieceColor::White => -1i8,
+                    PieceColor::Black =>  1i8,
                 }), dst.file());
                 self.remove(capture_pos);
             }
@@ -302,70 +808,54 @@ impl Board {
         }
 
         if piece.kind == PieceKind::King {
-            match self.side_to_move {
-                PieceColor::White => {
-                    new_castle_state.clear(CastleKind::WhiteKingside);
-                    new_castle_state.clear(CastleKind::WhiteQueenside);
-                },
-                PieceColor::Black => {
-                    new_castle_state.clear(CastleKind::BlackKingside);
-                    new_castle_state.clear(CastleKind::BlackQueenside);
-                },
-            }
-
-            if move_kind == MoveKind::Castle {
-                let rook = Piece::new(PieceKind::Rook, self.side_to_move);
-                let kingside = dst.file() >= 4;
-                let rook_src = Position::new(dst.rank(), if kingside {
-                    dst.file() + 1
-                } else {
-                    dst.file() - 2
-                });
-                let rook_dst = Position::new(dst.rank(), if kingside {
-                    dst.file() - 1
-                } else {
-                    dst.file() + 1
-                });
-                self.remove(rook_src);
-                self.set(rook_dst, Some(rook));
-
-                new_zobrist_hash.update_with_piece_pos(Some(rook), rook_src);
-                new_zobrist_hash.update_with_piece_pos(Some(rook), rook_dst);
-            }
+            new_castle_state.clear(CastleKind::new(self.side_to_move, true));
+            new_castle_state.clear(CastleKind::new(self.side_to_move, false));
         }
 
         if let Some(promotion_kind) = move_to_make.promotion_kind() {
-            let promoted_piece = Piece::new(match promotion_kind {
-                moves::PromotionKind::Queen => PieceKind::Queen,
-                moves::PromotionKind::Knight => PieceKind::Knight,
-                moves::PromotionKind::Rook => PieceKind::Rook,
-                moves::PromotionKind::Bishop => PieceKind::Bishop,
-            }, self.side_to_move);
+            let promoted_piece = Piece::new(promotion_kind.piece_kind(), self.side_to_move);
 
             self.set(dst, Some(promoted_piece));
         }
 
+        if move_kind != MoveKind::Castle {
+            new_zobrist_hash.update_with_piece_pos(self.get(dst), dst);
+        }
+
+        if self.variant == Variant::Atomic && captured_piece.is_some() {
+            // The capturing piece explodes along with every piece but pawns around it
+            let mut blast = magic::get_king_moves(dst);
+            blast.set(dst);
+            for pos in (blast & self.all_pieces()).iter_positions() {
+                let exploded_piece = self.get(pos).unwrap();
+                if exploded_piece.kind == PieceKind::Pawn && pos != dst {
+                    continue;
+                }
+                self.remove(pos);
+                new_zobrist_hash.update_with_piece_pos(Some(exploded_piece), pos);
+
+                for kind in CastleKind::ALL {
+                    if self.castling_rook(kind) == pos || (exploded_piece.kind == PieceKind::King && exploded_piece.color == kind.color()) {
+                        new_castle_state.clear(kind);
+                    }
+                }
+            }
+        }
+
         if move_kind == MoveKind::PawnDoublePush {
             new_en_passant_file = Some(src.file());
             new_zobrist_hash.update_with_en_passant_file(new_en_passant_file);
         }
 
-        {
-            if dst == Position::H1 || src == Position::H1 {
-                new_castle_state.clear(CastleKind::WhiteKingside);
-            } else if dst == Position::A1 || src == Position::A1 {
-                new_castle_state.clear(CastleKind::WhiteQueenside);
-            }
-            if dst == Position::H8 || src == Position::H8 {
-                new_castle_state.clear(CastleKind::BlackKingside);
-            } else if dst == Position::A8 || src == Position::A8 {
-                new_castle_state.clear(CastleKind::BlackQueenside);
+        // Moving or capturing a castling rook loses its castling right
+        for kind in CastleKind::ALL {
+            let rook_pos = self.castling_rook(kind);
+            if dst == rook_pos || src == rook_pos {
+                new_castle_state.clear(kind);
             }
         }
 
         new_zobrist_hash.update_with_side_to_move();
-        new_zobrist_hash.update_with_piece_pos(Some(piece), src);
-        new_zobrist_hash.update_with_piece_pos(self.get(dst), dst);
         new_zobrist_hash.update_with_en_passant_file(prev_en_passant_file);
 
         if new_castle_state != prev_castle_state {
@@ -373,143 +863,334 @@ impl Board {
             new_zobrist_hash.update_with_castle_state(new_castle_state);
         }
 
-        self.side_to_move = !self.side_to_move;
-        self.ply_count += 1;
+        if self.variant == Variant::ThreeCheck && self.calculate_checked_state(!self.side_to_move) {
+            let checks = &mut new_checks_given[self.side_to_move.is_black() as usize];
+            new_zobrist_hash.update_with_checks_given(self.side_to_move, *checks);
+            *checks += 1;
+            new_zobrist_hash.update_with_checks_given(self.side_to_move, *checks);
+        }
 
-        let mut new_fifty_move_counter = current_game_state.fifty_move_counter + 1;
+        let mut new_fifty_move_counter = self.fifty_move_counter + 1;
 
         if piece.kind == PieceKind::Pawn || captured_piece.is_some() {
-            if !in_search {
-                self.repeat_pos_history.clear();
-            }
             new_fifty_move_counter = 0;
         }
 
-        let new_state = GameState {
-            captured_piece_kind: captured_piece.map(|piece| piece.kind),
-            en_passant_file: new_en_passant_file,
-            castle_state: new_castle_state,
-            fifty_move_counter: new_fifty_move_counter,
-            zobrist_hash: new_zobrist_hash,
-        };
-        self.game_state_history.push(new_state);
-        
-        match self.side_to_move {
-            PieceColor::White => &mut self.white_in_check,
-            PieceColor::Black => &mut self.black_in_check,
-        }.take();
+        self.captured_piece_kind = captured_piece.map(|piece| piece.kind);
+        self.en_passant_file = new_en_passant_file;
+        self.castle_state = new_castle_state;
+        self.fifty_move_counter = new_fifty_move_counter;
+        self.zobrist_hash = new_zobrist_hash;
+        self.checks_given = new_checks_given;
+        self.side_to_move = !self.side_to_move;
+        self.ply_count += 1;
 
-        if !in_search {
-            self.repeat_pos_history.push(new_zobrist_hash);
-            self.all_game_moves.push(move_to_make);
-        }
+        debug_assert_eq!(self.zobrist_hash(), self.compute_zobrist_from_scratch(), "Hash out of sync after {move_to_make}");
     }
 
-    pub fn unmake_move(&mut self, move_to_unmake: Move, in_search: bool) {
-        self.side_to_move = !self.side_to_move;
+    /// Passes the turn, for null move pruning or to see what the opponent threatens. Refused while in check, since
+    /// the king could then be captured.
+    pub fn make_null_move(&mut self) -> Result<(), moves::IllegalReason> {
+        if self.in_check(self.side_to_move) {
+            return Err(moves::IllegalReason::InCheck);
+        }
 
-        let current_game_state = *self.current_game_state();
+        self.zobrist_hash.update_with_en_passant_file(self.en_passant_file);
+        self.zobrist_hash.update_with_side_to_move();
+        self.en_passant_file = None;
+        self.captured_piece_kind = None;
+        self.fifty_move_counter += 1;
+        self.side_to_move = !self.side_to_move;
+        self.ply_count += 1;
 
-        let src = move_to_unmake.src();
-        let dst = move_to_unmake.dst();
-        let move_kind = move_to_unmake.kind();
+        debug_assert_eq!(self.zobrist_hash(), self.compute_zobrist_from_scratch(), "Hash out of sync after a null move");
+        Ok(())
+    }
 
-        let piece = if move_to_unmake.is_promotion() {
-            Piece::new(PieceKind::Pawn, self.side_to_move)
+    /// Adds a piece of `kind` to the pocket of `color`, or takes one out, keeping the hash up to date.
+    fn update_pocket(&mut self, color: PieceColor, kind: PieceKind, add: bool, zobrist_hash: &mut zobrist::ZobristHash) {
+        let pocket = &mut self.pockets[color.is_black() as usize];
+        zobrist_hash.update_with_pocket_count(color, kind, pocket.count(kind));
+        if add {
+            pocket.add(kind);
         } else {
-            self.get(dst).unwrap()
-        };
-        let captured_piece = current_game_state.captured_piece_kind.map(|piece_kind| Piece::new(piece_kind, !self.side_to_move));
+            pocket.remove(kind);
+        }
+        zobrist_hash.update_with_pocket_count(color, kind, pocket.count(kind));
+    }
 
-        self.remove(dst);
-        self.set(src, Some(piece));
+    pub fn in_check(&self, color: PieceColor) -> bool {
+        self.calculate_checked_state(color)
+    }
 
-        if let Some(captured_piece) = captured_piece {
-            let mut capture_pos = dst;
-            if move_kind == MoveKind::EnPassant {
-                capture_pos = Position::new(dst.rank().wrapping_add_signed(match self.side_to_move {
-                    PieceColor::White =>  1i8,
-                    PieceColor::Black => -1i8,
-                }), dst.file());
-            }
-            self.set(capture_pos, Some(captured_piece));
+    /// All legal moves of the side to move.
+    pub fn legal_moves(&self) -> ArrayVec<Move, { move_gen::MAX_MOVES }> {
+        let mut moves = ArrayVec::new();
+        MoveGenerator::new().generate_moves(self, &mut |mov| moves.push(mov), false);
+        moves
+    }
+
+    /// Legal moves of the piece on `pos`. Empty if it isn't a piece of the side to move.
+    pub fn legal_moves_from(&self, pos: Position) -> ArrayVec<Move, { move_gen::MAX_MOVES }> {
+        let mut moves = ArrayVec::new();
+        if self.pieces(self.side_to_move()).get(pos) {
+            MoveGenerator::new().generate_moves(self, &mut |mov| if mov.src() == pos && !mov.is_drop() { moves.push(mov) }, false);
         }
+        moves
+    }
 
-        if piece.kind == PieceKind::King {
-            if move_kind == MoveKind::Castle {
-                let rook = Piece::new(PieceKind::Rook, self.side_to_move);
-                let kingside = dst.file() >= 4;
-                let rook_src = Position::new(dst.rank(), if kingside {
-                    dst.file() + 1
-                } else {
-                    dst.file() - 2
-                });
-                let rook_dst = Position::new(dst.rank(), if kingside {
-                    dst.file() - 1
-                } else {
-                    dst.file() + 1
-                });
-                self.remove(rook_dst);
-                self.set(rook_src, Some(rook));
-            }
+    /// Whether `mov` is legal in this position. Safe to call with moves from untrusted sources like the network or
+    /// a PGN file.
+    pub fn is_legal(&self, mov: Move) -> bool {
+        self.is_pseudo_legal(mov) && if mov.is_drop() { self.legal_moves().contains(&mov) } else { self.legal_moves_from(mov.src()).contains(&mov) }
+    }
+
+    /// Whether `mov` follows the movement rules of the piece it moves, without checking whether it leaves the own
+    /// king in check or, in Antichess, skips a compulsory capture. Meant for cheaply validating moves from earlier
+    /// positions, e.g. from a transposition table.
+    pub fn is_pseudo_legal(&self, mov: Move) -> bool {
+        let color = self.side_to_move();
+        let (src, dst) = (mov.src(), mov.dst());
+
+        if let Some(kind) = mov.drop_kind() {
+            return self.variant.has_pockets() && self.pocket(color).count(kind) > 0 && self.get(dst).is_none()
+                && !(kind == PieceKind::Pawn && (dst.rank() == 0 || dst.rank() == 7));
         }
+        let Some(piece) = self.get(src).filter(|piece| piece.color == color) else {
+            return false;
+        };
 
-        if !in_search {
-            self.repeat_pos_history.pop();
-            self.all_game_moves.pop();
+        if mov.kind() == MoveKind::Castle {
+            if piece.kind != PieceKind::King || !self.variant.has_castling() || dst.rank() != src.rank() {
+                return false;
+            }
+            let kind = CastleKind::new(color, dst.file() > src.file());
+            if !self.can_castle(kind) || self.castling_rook(kind) != dst || self.get(dst) != Some(Piece::new(PieceKind::Rook, color)) {
+                return false;
+            }
+            let (king_dst, rook_dst) = mov.castle_destinations();
+            let mut blockers = self.all_pieces();
+            blockers.clear(src);
+            blockers.clear(dst);
+            return ((move_gen::rank_span(src, king_dst) | move_gen::rank_span(dst, rook_dst)) & blockers).is_empty();
+        }
+        if self.pieces(color).get(dst) {
+            return false;
         }
 
-        self.game_state_history.pop();
-        self.ply_count -= 1;
-        match self.side_to_move {
-            PieceColor::White => &mut self.white_in_check,
-            PieceColor::Black => &mut self.black_in_check,
-        }.take();
+        let attacks = match piece.kind {
+            PieceKind::Pawn => {
+                let (forward, start_rank, promotion_rank) = if color.is_white() { (position::Offset::N, 1, 7) } else { (position::Offset::S, 6, 0) };
+                let single_push = src.checked_offset(forward);
+                // Promotions are exactly the pawn moves to the last rank
+                if (dst.rank() == promotion_rank) != mov.is_promotion() || mov.promotion_kind() == Some(PromotionKind::King) && self.variant.has_royal_king() {
+                    return false;
+                }
+                return match mov.kind() {
+                    MoveKind::Basic | MoveKind::Promotion(_) => if dst.file() == src.file() {
+                        single_push == Some(dst) && self.get(dst).is_none()
+                    } else {
+                        magic::get_pawn_attacks(src, color).get(dst) && self.get(dst).is_some()
+                    },
+                    MoveKind::PawnDoublePush => src.rank() == start_rank && single_push.is_some_and(|pos| self.get(pos).is_none()
+                        && pos.checked_offset(forward) == Some(dst)) && self.get(dst).is_none(),
+                    MoveKind::EnPassant => magic::get_pawn_attacks(src, color).get(dst) && self.en_passant_file() == Some(dst.file())
+                        && dst.rank() == if color.is_white() { 5 } else { 2 },
+                    _ => false,
+                };
+            },
+            PieceKind::Knight => magic::get_knight_attacks(src),
+            PieceKind::Bishop => magic::get_diagonal_attacks(src, self.all_pieces()),
+            PieceKind::Rook => magic::get_orthogonal_attacks(src, self.all_pieces()),
+            PieceKind::Queen => magic::get_diagonal_attacks(src, self.all_pieces()) | magic::get_orthogonal_attacks(src, self.all_pieces()),
+            PieceKind::King => magic::get_king_moves(src),
+        };
+        mov.kind() == MoveKind::Basic && attacks.get(dst)
     }
 
-    pub fn in_check(&self, color: PieceColor) -> bool {
-        *match color {
-            PieceColor::White => &self.white_in_check,
-            PieceColor::Black => &self.black_in_check,
-        }.get_or_init(|| self.calculate_checked_state(color))
-    }
+    /// Finds the legal move of the piece on `src` to `dst`, or explains why there is none. Castling is entered
+    /// either as the king moving to its destination or as the king capturing its own rook. Positions that end the
+    /// game refuse every move, including the fifty-move rule, which draws without a claim. Only
+    /// [`Game::validate_move`] knows about repetitions and declared outcomes.
+    pub fn validate_move(&self, src: Position, dst: Position, promotion: Option<PromotionKind>) -> Result<Move, moves::IllegalReason> {
+        use moves::IllegalReason;
 
-    fn calculate_checked_state(&self, color: PieceColor) -> bool {
-        let king_pos = self.king_pos(color);
-        let enemy_pieces = self.pieces_of_color(!color);
-        let blockers = self.all_pieces();
-        let enemy_ortho_sliders = self.pieces_of(!color, PieceKind::Rook) | self.pieces_of(!color, PieceKind::Queen);
-        let enemy_diag_sliders = self.pieces_of(!color, PieceKind::Bishop) | self.pieces_of(!color, PieceKind::Queen);
-        
-        if !enemy_ortho_sliders.is_empty() {
-            let ortho_attacks = magic::get_orthogonal_attacks(king_pos, blockers);
-            if !(ortho_attacks & enemy_ortho_sliders).is_empty() {
-                return true;
-            }
+        // Checked first, since the king of the side to move may be gone in Atomic
+        if self.variant_winner().is_some() {
+            return Err(IllegalReason::GameOver);
         }
 
-        if !enemy_diag_sliders.is_empty() {
-            let diag_attacks = magic::get_diagonal_attacks(king_pos, blockers);
-            if !(diag_attacks & enemy_diag_sliders).is_empty() {
-                return true;
-            }
+        let color = self.side_to_move();
+        let mut move_generator = MoveGenerator::new();
+        let mut legal_moves = ArrayVec::<Move, { move_gen::MAX_MOVES }>::new();
+        move_generator.generate_moves(self, &mut |mov| legal_moves.push(mov), false);
+
+        if legal_moves.is_empty() {
+            return Err(if move_generator.in_check() { IllegalReason::InCheckmate } else { IllegalReason::GameOver });
+        }
+        if self.halfmove_clock() >= 100 {
+            return Err(IllegalReason::GameOver);
+        }
+        if src == dst {
+            return Err(IllegalReason::NoMove);
+        }
+        let Some(piece) = self.get(src) else {
+            return Err(IllegalReason::NoPiece);
+        };
+        if piece.color != color {
+            return Err(IllegalReason::NotYourTurn);
         }
 
-        if !enemy_pieces.knights.is_empty() {
-            let knight_attacks = magic::get_knight_attacks(king_pos);
-            if !(knight_attacks & enemy_pieces.knights).is_empty() {
-                return true;
-            }
+        let mut candidates = legal_moves.iter().copied().filter(|mov| mov.src() == src && !mov.is_drop());
+        let found = candidates.clone().filter(|mov| mov.dst() == dst).collect::<ArrayVec<Move, 5>>();
+        let found = if found.is_empty() {
+            candidates.find(|mov| mov.kind() == MoveKind::Castle && mov.castle_destinations().0 == dst).into_iter().collect()
+        } else {
+            found
+        };
+        if !found.is_empty() {
+            return found.into_iter().find(|mov| mov.promotion_kind() == promotion).ok_or(IllegalReason::InvalidPromotion);
         }
 
-        if !enemy_pieces.pawns.is_empty() {
-            let pawn_attacks = magic::get_pawn_attacks(king_pos, !color);
-            if !(pawn_attacks & enemy_pieces.pawns).is_empty() {
-                return true;
+        // Work out which move was meant, to tell what rule it breaks
+        let is_king = piece.kind == PieceKind::King;
+        let castle_kind = CastleKind::new(color, dst.file() > src.file());
+        let mov = if is_king && self.variant.has_castling() && self.get(dst) == Some(Piece::new(PieceKind::Rook, color)) {
+            Move::new(src, dst, MoveKind::Castle)
+        } else if is_king && self.variant.has_castling() && !self.chess960 && src.rank() == dst.rank() && src.file().abs_diff(dst.file()) == 2 {
+            Move::new(src, self.castling_rook(castle_kind), MoveKind::Castle)
+        } else if self.pieces(color).get(dst) {
+            return Err(IllegalReason::SameColor);
+        } else if piece.kind == PieceKind::Pawn {
+            let forward = if color.is_white() { 1 } else { -1 };
+            if dst.rank() == if color.is_white() { 7 } else { 0 } {
+                Move::new(src, dst, MoveKind::Promotion(promotion.unwrap_or(PromotionKind::Queen)))
+            } else if dst.rank() as i8 - src.rank() as i8 == 2 * forward {
+                Move::new(src, dst, MoveKind::PawnDoublePush)
+            } else if dst.file() != src.file() && self.get(dst).is_none() && self.en_passant_file() == Some(dst.file()) {
+                Move::new(src, dst, MoveKind::EnPassant)
+            } else {
+                Move::new(src, dst, MoveKind::Basic)
+            }
+        } else {
+            Move::new(src, dst, MoveKind::Basic)
+        };
+
+        if !self.is_pseudo_legal(mov) {
+            if mov.kind() == MoveKind::Castle {
+                return Err(if self.can_castle(castle_kind) { IllegalReason::Blocked } else { IllegalReason::NoCastlingRights });
+            }
+            if mov.promotion_kind() == Some(PromotionKind::King) && self.variant.has_royal_king() {
+                return Err(IllegalReason::InvalidPromotion);
             }
+            let blocked = match piece.kind {
+                PieceKind::Bishop => magic::get_diagonal_attacks(src, bitboards::BitBoard::EMPTY).get(dst),
+                PieceKind::Rook => magic::get_orthogonal_attacks(src, bitboards::BitBoard::EMPTY).get(dst),
+                PieceKind::Queen => (magic::get_diagonal_attacks(src, bitboards::BitBoard::EMPTY) | magic::get_orthogonal_attacks(src, bitboards::BitBoard::EMPTY)).get(dst),
+                // A push straight ahead onto or over another piece
+                PieceKind::Pawn => {
+                    let (forward, start_rank) = if color.is_white() { (1, 1) } else { (-1, 6) };
+                    let distance = (dst.rank() as i8 - src.rank() as i8) * forward;
+                    src.file() == dst.file() && (distance == 1 || distance == 2 && src.rank() == start_rank)
+                        && (1..=distance).any(|step| self.get(Position::new((src.rank() as i8 + step * forward) as u8, src.file())).is_some())
+                },
+                _ => false,
+            };
+            return Err(if blocked { IllegalReason::Blocked } else { IllegalReason::InvalidMove });
+        }
+
+        // The move follows the piece's movement rules, so it's illegal because of the own king
+        Err(if self.variant == Variant::Antichess {
+            IllegalReason::CaptureRequired
+        } else if mov.kind() == MoveKind::Castle {
+            if move_generator.in_check() { IllegalReason::InCheck } else { IllegalReason::CastlingThroughCheck }
+        } else if is_king {
+            IllegalReason::IntoCheck
+        } else if move_generator.in_check() {
+            IllegalReason::InCheck
+        } else if move_generator.pin_rays().get(src) {
+            IllegalReason::Pinned
+        } else {
+            IllegalReason::IntoCheck
+        })
+    }
+
+    fn calculate_checked_state(&self, color: PieceColor) -> bool {
+        if !self.variant.has_royal_king() {
+            return false;
+        }
+        let Some(king_pos) = self.king_pos_checked(color) else {
+            return false;
+        };
+        // Touching kings can't check each other in Atomic, since capturing would blow up both
+        if self.variant == Variant::Atomic && !(magic::get_king_moves(king_pos) & self.pieces_of(!color, PieceKind::King)).is_empty() {
+            return false;
         }
+        self.is_square_attacked(king_pos, !color)
+    }
+
+    /// Pieces of `color` attacking `pos`.
+    pub fn attackers_to(&self, pos: Position, color: PieceColor) -> bitboards::BitBoard {
+        self.attackers_to_occupied(pos, color, self.all_pieces())
+    }
+
+    /// Pieces of `color` in `occupied` that would attack `pos` if only the squares in `occupied` were taken. Removing
+    /// pieces from `occupied` reveals the sliders behind them, as needed for static exchange evaluation.
+    pub fn attackers_to_occupied(&self, pos: Position, color: PieceColor, occupied: bitboards::BitBoard) -> bitboards::BitBoard {
+        let ortho_sliders = self.pieces_of(color, PieceKind::Rook) | self.pieces_of(color, PieceKind::Queen);
+        let diag_sliders = self.pieces_of(color, PieceKind::Bishop) | self.pieces_of(color, PieceKind::Queen);
+        let attackers = (magic::get_orthogonal_attacks(pos, occupied) & ortho_sliders)
+            | (magic::get_diagonal_attacks(pos, occupied) & diag_sliders)
+            | (magic::get_knight_attacks(pos) & self.pieces_of(color, PieceKind::Knight))
+            | (magic::get_king_moves(pos) & self.pieces_of(color, PieceKind::King))
+            | (magic::get_pawn_attacks(pos, !color) & self.pieces_of(color, PieceKind::Pawn));
+        attackers & occupied
+    }
 
-        false
+    pub fn is_square_attacked(&self, pos: Position, by: PieceColor) -> bool {
+        !self.attackers_to(pos, by).is_empty()
+    }
+
+    /// Sliders of `color` that attack `pos` through exactly one piece, i.e. the pieces that would attack it once the
+    /// first piece in their way moves.
+    pub fn xray_attackers_to(&self, pos: Position, color: PieceColor) -> bitboards::BitBoard {
+        let occupied = self.all_pieces();
+        let ortho_sliders = self.pieces_of(color, PieceKind::Rook) | self.pieces_of(color, PieceKind::Queen);
+        let diag_sliders = self.pieces_of(color, PieceKind::Bishop) | self.pieces_of(color, PieceKind::Queen);
+
+        let ortho_attacks = magic::get_orthogonal_attacks(pos, occupied);
+        let ortho_xrays = magic::get_orthogonal_attacks(pos, occupied & !ortho_attacks) & !ortho_attacks;
+        let diag_attacks = magic::get_diagonal_attacks(pos, occupied);
+        let diag_xrays = magic::get_diagonal_attacks(pos, occupied & !diag_attacks) & !diag_attacks;
+        (ortho_xrays & ortho_sliders) | (diag_xrays & diag_sliders)
+    }
+
+    /// Enemy pieces giving check to the king of `color`.
+    pub fn checkers(&self, color: PieceColor) -> bitboards::BitBoard {
+        match self.king_pos_checked(color) {
+            Some(king_pos) if self.variant.has_royal_king() => self.attackers_to(king_pos, !color),
+            _ => bitboards::BitBoard::EMPTY,
+        }
+    }
+
+    /// Pieces of `color` that can't leave the line between their king and an enemy slider without exposing the king.
+    pub fn pinned(&self, color: PieceColor) -> bitboards::BitBoard {
+        let mut pinned = bitboards::BitBoard::EMPTY;
+        let Some(king_pos) = self.king_pos_checked(color).filter(|_| self.variant.has_royal_king()) else {
+            return pinned;
+        };
+        let own_pieces = self.pieces(color);
+        let occupied = self.all_pieces();
+        for pinner in self.xray_attackers_to(king_pos, !color).iter_positions() {
+            // The squares seen by both the king and the pinner along their shared line hold the pinned piece
+            let between = if pinner.rank() == king_pos.rank() || pinner.file() == king_pos.file() {
+                magic::get_orthogonal_attacks(king_pos, occupied) & magic::get_orthogonal_attacks(pinner, occupied)
+            } else {
+                magic::get_diagonal_attacks(king_pos, occupied) & magic::get_diagonal_attacks(pinner, occupied)
+            };
+            pinned |= between & own_pieces;
+        }
+        pinned
     }
 }
 
@@ -521,6 +1202,50 @@ pub enum CastleKind {
     BlackQueenside,
 }
 
+impl CastleKind {
+    pub const ALL: [Self; 4] = [Self::WhiteKingside, Self::WhiteQueenside, Self::BlackKingside, Self::BlackQueenside];
+
+    pub const fn new(color: PieceColor, kingside: bool) -> Self {
+        match (color, kingside) {
+            (PieceColor::White, true) => Self::WhiteKingside,
+            (PieceColor::White, false) => Self::WhiteQueenside,
+            (PieceColor::Black, true) => Self::BlackKingside,
+            (PieceColor::Black, false) => Self::BlackQueenside,
+        }
+    }
+
+    pub const fn color(&self) -> PieceColor {
+        match self {
+            Self::WhiteKingside | Self::WhiteQueenside => PieceColor::White,
+            Self::BlackKingside | Self::BlackQueenside => PieceColor::Black,
+        }
+    }
+
+    pub const fn is_kingside(&self) -> bool {
+        matches!(self, Self::WhiteKingside | Self::BlackKingside)
+    }
+}
+
+/// Starting files of the rooks belonging to each castling right. Only Chess960 positions use other files than a and h.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CastlingRooks([u8; 4]);
+
+impl Default for CastlingRooks {
+    fn default() -> Self {
+        Self([7, 0, 7, 0])
+    }
+}
+
+impl CastlingRooks {
+    pub fn file(&self, kind: CastleKind) -> u8 {
+        self.0[kind as usize]
+    }
+
+    pub fn set_file(&mut self, kind: CastleKind, file: u8) {
+        self.0[kind as usize] = file;
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct CastleState(u8);
 
@@ -592,15 +1317,328 @@ impl FromPacked for CastleState {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
-pub struct GameState {
-    pub captured_piece_kind: Option<PieceKind>,
-    pub en_passant_file: Option<u8>,
+/// A move from the board's history together with the state it left the board in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayedMove {
+    pub mov: Move,
+    pub mover: PieceColor,
+    pub captured_piece: Option<Piece>,
+    /// Castling rights after the move.
     pub castle_state: CastleState,
-    pub fifty_move_counter: u8,
-    pub zobrist_hash: zobrist::ZobristHash,
+    /// File of the pawn that can be captured en passant after the move.
+    pub en_passant_file: Option<u8>,
 }
 
-impl GameState {
+#[cfg(test)]
+mod board_test {
+    use crate::chess::*;
+
+    fn play(board: &mut Board, text: &str) {
+        let mut found = None;
+        MoveGenerator::new().generate_moves(board, &mut |mov| if mov.to_string() == text { found = Some(mov) }, false);
+        board.make_move(found.unwrap(), false);
+    }
+
+    #[test]
+    fn history_test() {
+        let mut board = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/8/5N2/PPPPPPPP/RNBQKB1R w KQkq - 0 2").unwrap();
+        assert_eq!(board.ply_count(), 2);
+        assert_eq!(board.fullmove_number(), 2);
+
+        for mov in ["f3e5", "d7d5", "h1g1"] {
+            play(&mut board, mov);
+        }
+        assert_eq!(board.fullmove_number(), 3);
+
+        let history = board.move_history().collect::<Vec<_>>();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].mover, PieceColor::White);
+        assert_eq!(history[0].captured_piece, Some(Piece::BLACK_PAWN));
+        assert_eq!(history[1].en_passant_file, Some(3));
+        assert_eq!(history[2].en_passant_file, None);
+        assert!(!history[2].castle_state.get(board::CastleKind::WhiteKingside));
+        assert!(history[2].castle_state.get(board::CastleKind::WhiteQueenside));
+
+        // Search moves don't show up in the history
+        let mut move_gen = MoveGenerator::new();
+        let mut moves = Vec::new();
+        move_gen.generate_moves(&board, &mut |mov| moves.push(mov), false);
+        board.make_move(moves[0], true);
+        assert_eq!(board.move_history().len(), 3);
+        assert_eq!(board.last_move().unwrap().mov.to_string(), "h1g1");
+
+        // Nor do null moves, and neither changes who made each move
+        assert_eq!(board.make_null_move(), Ok(()));
+        let movers = board.move_history().map(|played| played.mover).collect::<Vec<_>>();
+        assert_eq!(movers, [PieceColor::White, PieceColor::Black, PieceColor::White]);
+        board.unmake_null_move();
+        board.unmake_move(moves[0], true);
+    }
+
+    #[test]
+    fn state_copy_test() {
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        for mov in board.legal_moves() {
+            let mut state = *board.state();
+            state.make_move(mov);
+
+            let mut played = board.clone();
+            played.make_move(mov, false);
+            assert_eq!(state.to_fen(), played.to_fen(), "{mov}");
+            assert_eq!(state.zobrist_hash(), played.zobrist_hash(), "{mov}");
+        }
+        // Moves made on a copy leave the board alone
+        assert_eq!(board.to_fen(), "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+
+        let state = *board.state();
+        let board = Board::from_state(state);
+        assert_eq!(board.to_fen(), state.to_fen());
+        assert!(board.last_move().is_none());
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn legal_query_test() {
+        let board = Board::new();
+        assert_eq!(board.legal_moves().len(), 20);
+        assert_eq!(board.legal_moves_from(Position::G1).iter().map(|mov| mov.to_string()).collect::<Vec<_>>(), ["g1f3", "g1h3"]);
+        assert!(board.legal_moves_from(Position::G8).is_empty());
+        assert!(board.legal_moves_from(Position::E4).is_empty());
+
+        // The knight on d2 is pinned, so its moves are pseudo-legal only
+        let board = Board::from_fen("4k3/8/8/b7/8/8/3N4/4K3 w - - 0 1").unwrap();
+        let pinned = Move::new(Position::D2, Position::F3, MoveKind::Basic);
+        assert!(board.is_pseudo_legal(pinned) && !board.is_legal(pinned));
+        assert!(board.legal_moves_from(Position::D2).is_empty());
+
+        // Moves taken from other positions have to agree with the move generator
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "1k6/8/8/8/8/8/8/RK4R1 w GA - 0 1",
+        ];
+        let mut boards = Vec::new();
+        for fen in fens {
+            let mut board = Board::from_fen(fen).unwrap();
+            for mov in board.legal_moves() {
+                board.make_move(mov, true);
+                boards.push(board.clone());
+                board.unmake_move(mov, true);
+            }
+            boards.push(board);
+        }
+        let moves = boards.iter().flat_map(|board| board.legal_moves()).collect::<std::collections::HashSet<_>>();
+        for board in boards.iter() {
+            let legal_moves = board.legal_moves();
+            for &mov in moves.iter() {
+                assert_eq!(board.is_legal(mov), legal_moves.contains(&mov), "{mov} in {}", board.to_fen());
+                assert!(board.is_pseudo_legal(mov) || !legal_moves.contains(&mov), "{mov} in {}", board.to_fen());
+            }
+        }
+    }
+
+    #[test]
+    fn attack_query_test() {
+        let board = Board::from_fen("4k3/8/8/1b6/8/2N5/3R4/r3K2R w K - 0 1").unwrap();
+        assert_eq!(board.attackers_to(Position::D4, PieceColor::White).iter_positions().collect::<Vec<_>>(), [Position::D2]);
+        assert_eq!(board.attackers_to(Position::E2, PieceColor::White).iter_positions().collect::<Vec<_>>(), [Position::E1, Position::D2, Position::C3]);
+        assert!(board.is_square_attacked(Position::D1, PieceColor::Black));
+        assert!(!board.is_square_attacked(Position::D4, PieceColor::Black));
+        assert_eq!(board.checkers(PieceColor::White).iter_positions().collect::<Vec<_>>(), [Position::A1]);
+        assert!(board.checkers(PieceColor::Black).is_empty());
+        assert!(board.pinned(PieceColor::White).is_empty());
+        assert_eq!(board.xray_attackers_to(Position::E8, PieceColor::White).iter_positions().collect::<Vec<_>>(), []);
+        assert_eq!(board.xray_attackers_to(Position::H1, PieceColor::Black).iter_positions().collect::<Vec<_>>(), [Position::A1]);
+
+        // Removing the rook from the occupancy reveals the rook behind it
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/RR2K3 w - - 0 1").unwrap();
+        let mut occupied = board.all_pieces();
+        assert_eq!(board.attackers_to(Position::D1, PieceColor::White).iter_positions().collect::<Vec<_>>(), [Position::B1, Position::E1]);
+        occupied.clear(Position::B1);
+        assert_eq!(board.attackers_to_occupied(Position::D1, PieceColor::White, occupied).iter_positions().collect::<Vec<_>>(), [Position::A1, Position::E1]);
+
+        // Checkers and pins agree with the move generator
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ] {
+            let mut board = Board::from_fen(fen).unwrap();
+            for mov in board.legal_moves() {
+                board.make_move(mov, true);
+                let color = board.side_to_move();
+                let mut move_generator = MoveGenerator::new();
+                move_generator.generate_moves(&board, &mut |_| {}, false);
+                assert_eq!(!board.checkers(color).is_empty(), move_generator.in_check(), "{}", board.to_fen());
+                assert_eq!(board.checkers(color).count() > 1, move_generator.in_double_check(), "{}", board.to_fen());
+                assert_eq!(board.pinned(color), move_generator.pin_rays() & board.pieces(color), "{}", board.to_fen());
+                board.unmake_move(mov, true);
+            }
+        }
+    }
+
+    #[test]
+    fn validate_move_test() {
+        use moves::IllegalReason;
+
+        let board = Board::new();
+        assert_eq!(board.validate_move(Position::E2, Position::E4, None).map(|mov| mov.to_string()), Ok("e2e4".to_string()));
+        assert_eq!(board.validate_move(Position::E4, Position::E5, None), Err(IllegalReason::NoPiece));
+        assert_eq!(board.validate_move(Position::E7, Position::E5, None), Err(IllegalReason::NotYourTurn));
+        assert_eq!(board.validate_move(Position::E2, Position::E2, None), Err(IllegalReason::NoMove));
+        assert_eq!(board.validate_move(Position::D1, Position::D2, None), Err(IllegalReason::SameColor));
+        assert_eq!(board.validate_move(Position::C1, Position::E3, None), Err(IllegalReason::Blocked));
+        assert_eq!(board.validate_move(Position::G1, Position::G3, None), Err(IllegalReason::InvalidMove));
+        assert_eq!(board.validate_move(Position::E1, Position::G1, None), Err(IllegalReason::Blocked));
+
+        let board = Board::from_fen("r3k2r/8/8/8/1b6/8/3N4/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(board.validate_move(Position::D2, Position::F3, None), Err(IllegalReason::Pinned));
+        assert_eq!(board.validate_move(Position::E1, Position::D2, None), Err(IllegalReason::SameColor));
+        assert_eq!(board.validate_move(Position::E1, Position::G1, None).map(|mov| mov.kind()), Ok(MoveKind::Castle));
+        assert_eq!(board.validate_move(Position::E1, Position::H1, None).map(|mov| mov.kind()), Ok(MoveKind::Castle));
+        let board = Board::from_fen("r3k2r/8/8/8/1b6/8/8/R3K2R b KQk - 0 1").unwrap();
+        assert_eq!(board.validate_move(Position::E8, Position::C8, None), Err(IllegalReason::NoCastlingRights));
+        let board = Board::from_fen("4k3/8/8/8/8/8/5r2/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(board.validate_move(Position::E1, Position::F2, None).map(|mov| mov.to_string()), Ok("e1f2".to_string()));
+        let board = Board::from_fen("4k3/8/8/8/8/8/6r1/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(board.validate_move(Position::E1, Position::G1, None), Err(IllegalReason::CastlingThroughCheck));
+        assert_eq!(board.validate_move(Position::E1, Position::F2, None), Err(IllegalReason::IntoCheck));
+        let board = Board::from_fen("4k3/8/8/8/8/8/4r3/R3K2R w KQ - 0 1").unwrap();
+        assert_eq!(board.validate_move(Position::E1, Position::G1, None), Err(IllegalReason::InCheck));
+        assert_eq!(board.validate_move(Position::A1, Position::A8, None), Err(IllegalReason::InCheck));
+
+        let board = Board::from_fen("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(board.validate_move(Position::B7, Position::B8, None), Err(IllegalReason::InvalidPromotion));
+        assert_eq!(board.validate_move(Position::B7, Position::B8, Some(PromotionKind::King)), Err(IllegalReason::InvalidPromotion));
+        assert!(board.validate_move(Position::B7, Position::B8, Some(PromotionKind::Knight)).is_ok());
+
+        let board = Board::from_fen("R5k1/5ppp/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(board.validate_move(Position::G8, Position::H8, None), Err(IllegalReason::InCheckmate));
+        let board = Board::from_fen("7k/5Q2/8/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(board.validate_move(Position::H8, Position::H7, None), Err(IllegalReason::GameOver));
+        let board = Board::from_variant_fen(Variant::Antichess, "8/8/8/8/8/2p5/1P6/8 w - - 0 1").unwrap();
+        assert_eq!(board.validate_move(Position::B2, Position::B4, None), Err(IllegalReason::CaptureRequired));
+
+        // Games also end with moves left
+        let board = Board::from_fen("7k/8/8/8/8/8/8/4K2R w - - 100 80").unwrap();
+        assert_eq!(board.validate_move(Position::H1, Position::H2, None), Err(IllegalReason::GameOver));
+        let board = Board::from_variant_fen(Variant::KingOfTheHill, "7k/8/8/8/4K3/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(board.validate_move(Position::H8, Position::H7, None), Err(IllegalReason::GameOver));
+        let mut board = Board::from_variant_fen(Variant::Atomic, "4k3/4q3/8/8/8/8/8/4QK2 w - - 0 1").unwrap();
+        board.make_move(Move::parse("Qxe7", &board).unwrap(), false);
+        assert_eq!(board.validate_move(Position::E8, Position::E7, None), Err(IllegalReason::GameOver));
+    }
+
+    #[test]
+    fn chess960_test() {
+        assert_eq!(Board::chess960_starting_board(Board::STANDARD_CHESS960_INDEX), Board::STANDARD_STARTING_BOARD);
+        assert_eq!(Board::chess960(0).to_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1");
+        assert_eq!(Board::chess960(0).to_shredder_fen(), "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1");
+        assert_eq!(Board::chess960(959).to_fen(), "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1");
+        assert!(!Board::new().is_chess960());
+        assert!(Board::from_fen("bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1").unwrap().is_chess960());
+
+        // X-FEN names the rook by its file when another rook is closer to the corner
+        let fen = "rk2r3/8/8/8/8/8/8/RK2R2R w Ek - 0 1";
+        let board = Board::from_fen(fen).unwrap();
+        assert_eq!(board.castling_rook(board::CastleKind::WhiteKingside), Position::E1);
+        assert_eq!(board.to_fen(), fen);
+        assert_eq!(board.to_shredder_fen(), "rk2r3/8/8/8/8/8/8/RK2R2R w Ee - 0 1");
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4K3 w K - 0 1").is_err());
+    }
+
+    #[test]
+    fn chess960_castling_test() {
+        // The king can castle both ways, but the rook on c1 is in the way of castling queenside
+        let board = Board::from_fen("1k6/8/8/8/8/8/8/RKR5 w AC - 0 1").unwrap();
+        let mut castles = Vec::new();
+        MoveGenerator::new().generate_moves(&board, &mut |mov| if mov.kind() == MoveKind::Castle { castles.push(mov) }, false);
+        assert_eq!(castles.iter().map(|mov| mov.to_uci(true)).collect::<Vec<_>>(), ["b1c1"]);
+
+        let mut board = Board::from_fen("1k6/8/8/8/8/8/8/RK4R1 w GA - 0 1").unwrap();
+        let mut castles = Vec::new();
+        MoveGenerator::new().generate_moves(&board, &mut |mov| if mov.kind() == MoveKind::Castle { castles.push(mov) }, false);
+        assert_eq!(castles.iter().map(|mov| mov.to_uci(true)).collect::<Vec<_>>(), ["b1g1", "b1a1"]);
+
+        play(&mut board, "b1g1");
+        assert_eq!(board.get(Position::G1), Some(Piece::WHITE_KING));
+        assert_eq!(board.get(Position::F1), Some(Piece::WHITE_ROOK));
+        assert_eq!(board.get(Position::A1), Some(Piece::WHITE_ROOK));
+        assert_eq!(board.to_shredder_fen(), "1k6/8/8/8/8/8/8/R4RK1 b - - 1 1");
+
+        let mov = board.last_move().unwrap().mov;
+        board.unmake_move(mov, false);
+        assert_eq!(board.to_shredder_fen(), "1k6/8/8/8/8/8/8/RK4R1 w GA - 0 1");
+    }
+
+    #[test]
+    fn zobrist_test() {
+        // The keys are fixed, so hashes stay the same from run to run
+        assert_eq!(Board::new().zobrist_hash().value(), 0x3D56_9308_1F65_BBCF);
+
+        // Transpositions hash the same, and so does the same position read from FEN
+        let mut board = Board::new();
+        for mov in ["g1f3", "g8f6", "b1c3", "b8c6"] {
+            play(&mut board, mov);
+        }
+        let mut other = Board::new();
+        for mov in ["b1c3", "b8c6", "g1f3", "g8f6"] {
+            play(&mut other, mov);
+        }
+        assert_eq!(board.zobrist_hash(), other.zobrist_hash());
+        assert_eq!(board.zobrist_hash(), Board::from_fen(&board.to_fen()).unwrap().zobrist_hash());
+
+        // En passant and castling rights are part of the hash
+        play(&mut board, "e2e4");
+        assert_ne!(board.zobrist_hash(), Board::from_fen("r1bqkb1r/pppppppp/2n2n2/8/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq - 0 3").unwrap().zobrist_hash());
+        assert_eq!(board.zobrist_hash(), Board::from_fen("r1bqkb1r/pppppppp/2n2n2/8/4P3/2N2N2/PPPP1PPP/R1BQKB1R b KQkq e3 0 3").unwrap().zobrist_hash());
+        assert_ne!(board.zobrist_hash(), Board::from_fen("r1bqkb1r/pppppppp/2n2n2/8/4P3/2N2N2/PPPP1PPP/R1BQKB1R b Qkq e3 0 3").unwrap().zobrist_hash());
+    }
+
+    #[test]
+    fn invalid_fen_test() {
+        const FENS: [&str; 14] = [
+            "",
+            "8/8/8/8/8/8/8/8 w",
+            "4k3/8/8/8/8/8/8/4K3",
+            "4k3/8/8/8/8/8/8/4K3 w",
+            "4k3/8/8/8/8/8/8/4K3 w -",
+            "4k3/8/8/8/8/8/8/4K3 w - -",
+            "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - e 0 1",
+            "4k3/8/8/8/8/8/8/4K3/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/8 w - - 0 1",
+            "4k4/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k2/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/3KK3 w - - 0 1",
+            "8/8/8/8/8/8/8/4K3 w - - 0 1",
+        ];
+        for fen in FENS {
+            assert!(Board::from_fen(fen).is_err(), "{fen}");
+        }
+        assert!(Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0").is_ok());
+        // Kings are ordinary pieces in Antichess
+        assert!(Board::from_variant_fen(Variant::Antichess, "8/1p6/8/8/8/8/P7/8 w - - 0 1").is_ok());
+    }
+
+    #[test]
+    fn null_move_test() {
+        let mut board = Board::from_fen("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2").unwrap();
+        let before = board.to_fen();
+        assert_eq!(board.make_null_move(), Ok(()));
+        // The turn passes and the en passant square is gone
+        assert_eq!(board.to_fen(), "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 1 2");
+        assert_eq!(board.zobrist_hash(), Board::from_fen(&board.to_fen()).unwrap().zobrist_hash());
+        assert!(board.last_move().is_none());
+
+        board.unmake_null_move();
+        assert_eq!(board.to_fen(), before);
+        assert_eq!(board.zobrist_hash(), Board::from_fen(&before).unwrap().zobrist_hash());
+
+        // Passing while in check would let the king be captured
+        let mut board = Board::from_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        assert_eq!(board.make_null_move(), Err(moves::IllegalReason::InCheck));
+        assert_eq!(board.side_to_move(), PieceColor::White);
+    }
+}
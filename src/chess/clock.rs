@@ -0,0 +1,348 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+
+use super::*;
+
+/// How a player's clock is compensated for each move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Increment {
+    None,
+    /// Added after every move.
+    Fischer(Duration),
+    /// The time used for a move is given back after the move, up to this much.
+    Bronstein(Duration),
+    /// The clock only starts running once this much time has passed in a move.
+    Delay(Duration),
+}
+
+/// A period of the game, e.g. "40 moves in 90 minutes".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeControlStage {
+    /// Moves to be played in this stage, or `None` for the rest of the game.
+    pub moves: Option<u32>,
+    /// Time added to the clock at the start of this stage.
+    pub time: Duration,
+    pub increment: Increment,
+}
+
+/// A sequence of stages. If the last stage has a move count, it is repeated.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TimeControl {
+    stages: Vec<TimeControlStage>,
+}
+
+impl TimeControl {
+    pub fn new(stages: Vec<TimeControlStage>) -> anyhow::Result<Self> {
+        if stages.is_empty() {
+            return Err(anyhow!("A time control needs at least one stage"));
+        }
+        if stages[..stages.len() - 1].iter().any(|stage| stage.moves.is_none()) {
+            return Err(anyhow!("Only the last stage of a time control can be sudden death"));
+        }
+        if stages.iter().any(|stage| stage.moves == Some(0)) {
+            return Err(anyhow!("A stage of a time control needs at least one move"));
+        }
+        Ok(Self { stages })
+    }
+
+    fn single(time: Duration, increment: Increment) -> Self {
+        Self { stages: vec![TimeControlStage { moves: None, time, increment }] }
+    }
+
+    pub fn sudden_death(time: Duration) -> Self {
+        Self::single(time, Increment::None)
+    }
+
+    pub fn fischer(time: Duration, increment: Duration) -> Self {
+        Self::single(time, Increment::Fischer(increment))
+    }
+
+    pub fn bronstein(time: Duration, delay: Duration) -> Self {
+        Self::single(time, Increment::Bronstein(delay))
+    }
+
+    pub fn simple_delay(time: Duration, delay: Duration) -> Self {
+        Self::single(time, Increment::Delay(delay))
+    }
+
+    pub fn stages(&self) -> &[TimeControlStage] {
+        &self.stages
+    }
+
+    /// Parses a time control in the format of the PGN `TimeControl` tag, e.g. `300+2` or `40/5400+30:1800+30`.
+    ///
+    /// Times are in seconds. As an extension, `d` and `b` instead of `+` give a simple or Bronstein delay.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let parse_secs = |secs: &str| -> anyhow::Result<Duration> {
+            let value = secs.parse::<f64>().with_context(|| format!("Invalid time `{secs}`"))?;
+            Duration::try_from_secs_f64(value).with_context(|| format!("Invalid time `{secs}`"))
+        };
+
+        let mut stages = Vec::new();
+        for stage in text.split(':') {
+            let (moves, rest) = match stage.split_once('/') {
+                Some((moves, rest)) => (Some(moves.parse().with_context(|| format!("Invalid move count `{moves}`"))?), rest),
+                None => (None, stage),
+            };
+            let (time, increment) = if let Some((time, increment)) = rest.split_once('+') {
+                (time, Increment::Fischer(parse_secs(increment)?))
+            } else if let Some((time, delay)) = rest.split_once('d') {
+                (time, Increment::Delay(parse_secs(delay)?))
+            } else if let Some((time, delay)) = rest.split_once('b') {
+                (time, Increment::Bronstein(parse_secs(delay)?))
+            } else {
+                (rest, Increment::None)
+            };
+            stages.push(TimeControlStage { moves, time: parse_secs(time)?, increment });
+        }
+        Self::new(stages)
+    }
+
+    /// The stage that a player's `moves_played`-th move (counting from zero) falls into, and the moves left
+    /// in that stage including this one.
+    pub fn stage_at(&self, moves_played: u32) -> (usize, Option<u32>) {
+        let mut moves_played = moves_played;
+        let mut i = 0;
+        loop {
+            let stage = &self.stages[i];
+            match stage.moves {
+                Some(moves) if moves_played >= moves => {
+                    moves_played -= moves;
+                    // The last stage repeats
+                    i = (i + 1).min(self.stages.len() - 1);
+                },
+                Some(moves) => return (i, Some(moves - moves_played)),
+                None => return (i, None),
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, stage) in self.stages.iter().enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            if let Some(moves) = stage.moves {
+                write!(f, "{moves}/")?;
+            }
+            write!(f, "{}", stage.time.as_secs_f64())?;
+            match stage.increment {
+                Increment::None => {},
+                Increment::Fischer(increment) => write!(f, "+{}", increment.as_secs_f64())?,
+                Increment::Delay(delay) => write!(f, "d{}", delay.as_secs_f64())?,
+                Increment::Bronstein(delay) => write!(f, "b{}", delay.as_secs_f64())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A chess clock for both players. Times are passed in explicitly so that the clock can be driven by tests.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    time_control: TimeControl,
+    remaining: [Duration; 2],
+    moves_played: [u32; 2],
+    /// The player whose clock is running and when their move started.
+    running: Option<(PieceColor, Instant)>,
+}
+
+impl Clock {
+    pub fn new(time_control: TimeControl) -> Self {
+        let time = time_control.stages[0].time;
+        Self {
+            time_control,
+            remaining: [time; 2],
+            moves_played: [0; 2],
+            running: None,
+        }
+    }
+
+    /// A stopped clock showing the times recorded in `game`, which must have a time control.
+    pub fn for_game(game: &Game) -> Option<Self> {
+        let mut clock = Self::new(game.time_control()?.clone());
+        for color in [PieceColor::White, PieceColor::Black] {
+            clock.moves_played[color.is_black() as usize] = game.moves_played(color) as u32;
+            if let Some(remaining) = game.clock(color) {
+                clock.remaining[color.is_black() as usize] = remaining;
+            }
+        }
+        Some(clock)
+    }
+
+    pub fn time_control(&self) -> &TimeControl {
+        &self.time_control
+    }
+
+    pub fn running(&self) -> Option<PieceColor> {
+        self.running.map(|(color, _)| color)
+    }
+
+    /// Starts `color`'s clock, stopping the other one without finishing a move.
+    pub fn start(&mut self, color: PieceColor, now: Instant) {
+        self.stop(now);
+        self.running = Some((color, now));
+    }
+
+    /// Stops the running clock, charging the time used so far without any increment.
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((color, _)) = self.running {
+            self.remaining[color.is_black() as usize] = self.remaining(color, now);
+            self.running = None;
+        }
+    }
+
+    /// Increment or delay that applies to `color`'s next move.
+    pub fn increment(&self, color: PieceColor) -> Increment {
+        let (stage, _) = self.time_control.stage_at(self.moves_played[color.is_black() as usize]);
+        self.time_control.stages[stage].increment
+    }
+
+    /// Fischer increment `color` gets for their next move.
+    pub fn fischer_increment(&self, color: PieceColor) -> Duration {
+        match self.increment(color) {
+            Increment::Fischer(increment) => increment,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Moves `color` has to play before the next time control, if the current stage has a move count.
+    pub fn moves_to_go(&self, color: PieceColor) -> Option<u32> {
+        self.time_control.stage_at(self.moves_played[color.is_black() as usize]).1
+    }
+
+    /// Time `color` has left at `now`. A running clock doesn't go below zero.
+    pub fn remaining(&self, color: PieceColor, now: Instant) -> Duration {
+        let remaining = self.remaining[color.is_black() as usize];
+        match self.running {
+            Some((running, start)) if running == color => {
+                let elapsed = now.saturating_duration_since(start);
+                let charged = match self.increment(color) {
+                    Increment::Delay(delay) => elapsed.saturating_sub(delay),
+                    _ => elapsed,
+                };
+                remaining.saturating_sub(charged)
+            },
+            _ => remaining,
+        }
+    }
+
+    /// The player whose time has run out, if any.
+    pub fn flagged(&self, now: Instant) -> Option<PieceColor> {
+        self.running.map(|(color, _)| color).filter(|&color| self.remaining(color, now).is_zero())
+    }
+
+    /// Ends the running player's move: charges the time used, adds increments and the time of a new stage, and
+    /// starts the opponent's clock. Returns the time the mover has left.
+    pub fn press(&mut self, now: Instant) -> Option<Duration> {
+        let (color, start) = self.running?;
+        let elapsed = now.saturating_duration_since(start);
+        let index = color.is_black() as usize;
+
+        let mut remaining = self.remaining(color, now);
+        if !remaining.is_zero() {
+            match self.increment(color) {
+                Increment::Fischer(increment) => remaining += increment,
+                Increment::Bronstein(delay) => remaining += elapsed.min(delay),
+                Increment::None | Increment::Delay(_) => {},
+            }
+        }
+
+        // Reaching the move count of a stage adds the time of the next one
+        let (stage, moves_to_go) = self.time_control.stage_at(self.moves_played[index]);
+        self.moves_played[index] += 1;
+        if moves_to_go == Some(1) {
+            let (next_stage, _) = self.time_control.stage_at(self.moves_played[index]);
+            debug_assert!(next_stage >= stage);
+            remaining += self.time_control.stages[next_stage].time;
+        }
+
+        self.remaining[index] = remaining;
+        self.running = Some((!color, now));
+        Some(remaining)
+    }
+}
+
+#[cfg(test)]
+mod clock_test {
+    use super::*;
+
+    const fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn increment_test() {
+        let start = Instant::now();
+
+        let mut clock = Clock::new(TimeControl::fischer(secs(60), secs(2)));
+        clock.start(PieceColor::White, start);
+        assert_eq!(clock.remaining(PieceColor::White, start + secs(10)), secs(50));
+        assert_eq!(clock.press(start + secs(10)), Some(secs(52)));
+        assert_eq!(clock.running(), Some(PieceColor::Black));
+        assert_eq!(clock.press(start + secs(11)), Some(secs(61)));
+
+        let mut clock = Clock::new(TimeControl::bronstein(secs(60), secs(5)));
+        clock.start(PieceColor::White, start);
+        assert_eq!(clock.remaining(PieceColor::White, start + secs(3)), secs(57));
+        assert_eq!(clock.press(start + secs(3)), Some(secs(60)));
+        assert_eq!(clock.press(start + secs(13)), Some(secs(55)));
+
+        let mut clock = Clock::new(TimeControl::simple_delay(secs(60), secs(5)));
+        clock.start(PieceColor::White, start);
+        assert_eq!(clock.remaining(PieceColor::White, start + secs(3)), secs(60));
+        assert_eq!(clock.press(start + secs(3)), Some(secs(60)));
+        assert_eq!(clock.press(start + secs(13)), Some(secs(55)));
+    }
+
+    #[test]
+    fn flag_test() {
+        let start = Instant::now();
+        let mut clock = Clock::new(TimeControl::sudden_death(secs(60)));
+        clock.start(PieceColor::White, start);
+        assert_eq!(clock.flagged(start + secs(59)), None);
+        assert_eq!(clock.flagged(start + secs(60)), Some(PieceColor::White));
+        assert_eq!(clock.remaining(PieceColor::White, start + secs(70)), Duration::ZERO);
+    }
+
+    #[test]
+    fn stage_test() {
+        let time_control = TimeControl::parse("40/5400+30:1800+30").unwrap();
+        assert_eq!(time_control.to_string(), "40/5400+30:1800+30");
+        assert_eq!(time_control.stage_at(0), (0, Some(40)));
+        assert_eq!(time_control.stage_at(39), (0, Some(1)));
+        assert_eq!(time_control.stage_at(40), (1, None));
+
+        let start = Instant::now();
+        let mut clock = Clock::new(time_control);
+        let mut now = start;
+        clock.start(PieceColor::White, now);
+        for _ in 0..39 {
+            now += secs(60);
+            clock.press(now);
+            clock.press(now);
+        }
+        assert_eq!(clock.moves_to_go(PieceColor::White), Some(1));
+        assert_eq!(clock.remaining(PieceColor::White, now), secs(5400 - (39 * 30)));
+
+        // The 40th move adds the second stage's 30 minutes
+        now += secs(60);
+        assert_eq!(clock.press(now), Some(secs(5400 - (40 * 30) + 1800)));
+        assert_eq!(clock.moves_to_go(PieceColor::White), None);
+
+        let repeating = TimeControl::parse("40/7200").unwrap();
+        assert_eq!(repeating.stage_at(85), (0, Some(35)));
+        assert!(TimeControl::parse("300:40/7200").is_err());
+        assert!(TimeControl::parse("0/300").is_err());
+        assert!(TimeControl::parse("40/5400:0/1800:300").is_err());
+        assert_eq!(TimeControl::parse("300d3").unwrap(), TimeControl::simple_delay(secs(300), secs(3)));
+
+        // Times that don't fit a duration are errors rather than panics
+        for text in ["-5", "nan", "300+-1", "inf", "1e30", "40/nan"] {
+            assert!(TimeControl::parse(text).is_err(), "{text}");
+        }
+    }
+}
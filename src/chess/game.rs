@@ -0,0 +1,501 @@
+use std::time::{Duration, SystemTime};
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameOutcome {
+    Checkmate { winner: PieceColor },
+    Stalemate,
+    /// Fifty moves by each side without a capture or pawn move. Like repetitions, this ends the game right away
+    /// instead of waiting for a player to claim the draw.
+    FiftyMoveRule,
+    ThreefoldRepetition,
+    /// The loser can't continue, e.g. an engine crashed or played an illegal move.
+    Forfeit { winner: PieceColor },
+    /// The game was stopped and scored by an arbiter, e.g. a match runner's move limit.
+    Adjudication { winner: Option<PieceColor> },
+    /// A player ran out of time. It's a draw if the opponent couldn't checkmate, e.g. with a lone king or minor piece.
+    Timeout { winner: Option<PieceColor> },
+    /// The game was won by a rule of its variant, other than checkmate.
+    VariantWin { winner: PieceColor, variant: Variant },
+}
+
+impl GameOutcome {
+    pub fn winner(&self) -> Option<PieceColor> {
+        match self {
+            GameOutcome::Checkmate { winner } |
+            GameOutcome::Forfeit { winner } |
+            GameOutcome::VariantWin { winner, .. } => Some(*winner),
+            GameOutcome::Adjudication { winner } |
+            GameOutcome::Timeout { winner } => *winner,
+            GameOutcome::Stalemate |
+            GameOutcome::FiftyMoveRule |
+            GameOutcome::ThreefoldRepetition => None,
+        }
+    }
+
+    /// The PGN result token for this outcome.
+    pub fn result_str(&self) -> &'static str {
+        match self.winner() {
+            Some(PieceColor::White) => "1-0",
+            Some(PieceColor::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+impl std::fmt::Display for GameOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameOutcome::Checkmate { winner: PieceColor::White } => write!(f, "White wins by checkmate"),
+            GameOutcome::Checkmate { winner: PieceColor::Black } => write!(f, "Black wins by checkmate"),
+            GameOutcome::Stalemate => write!(f, "Draw by stalemate"),
+            GameOutcome::FiftyMoveRule => write!(f, "Draw by the fifty-move rule, no capture or pawn move in 50 moves"),
+            GameOutcome::ThreefoldRepetition => write!(f, "Draw by threefold repetition"),
+            GameOutcome::Forfeit { winner: PieceColor::White } => write!(f, "White wins by forfeit"),
+            GameOutcome::Forfeit { winner: PieceColor::Black } => write!(f, "Black wins by forfeit"),
+            GameOutcome::Adjudication { winner: Some(PieceColor::White) } => write!(f, "White wins by adjudication"),
+            GameOutcome::Adjudication { winner: Some(PieceColor::Black) } => write!(f, "Black wins by adjudication"),
+            GameOutcome::Adjudication { winner: None } => write!(f, "Draw by adjudication"),
+            GameOutcome::Timeout { winner: Some(PieceColor::White) } => write!(f, "White wins on time"),
+            GameOutcome::Timeout { winner: Some(PieceColor::Black) } => write!(f, "Black wins on time"),
+            GameOutcome::Timeout { winner: None } => write!(f, "Draw, time out with insufficient material"),
+            GameOutcome::VariantWin { winner, variant } => write!(f, "{} wins by {}", if winner.is_white() { "White" } else { "Black" }, variant.win_description()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveRecord {
+    pub mov: Move,
+    pub san: String,
+    /// Wall-clock time at which the move was played.
+    pub timestamp: SystemTime,
+    /// Time left on the mover's clock after the move, if the game is timed.
+    pub clock: Option<Duration>,
+}
+
+/// A game of chess: the starting position, every move played so far and a cursor into that history.
+///
+/// Moves after the cursor are kept around so that undone moves can be redone, and are discarded as soon
+/// as a different move is played.
+#[derive(Debug, Clone)]
+pub struct Game {
+    start_board: Board,
+    board: Board,
+    records: Vec<MoveRecord>,
+    ply: usize,
+    tags: Vec<(String, String)>,
+    time_control: Option<TimeControl>,
+    /// Outcome not decided by the position, see [`Game::declare_outcome`].
+    declared_outcome: Option<GameOutcome>,
+}
+
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game {
+    pub fn new() -> Self {
+        Self::from_board(Board::new())
+    }
+
+    pub fn from_fen(fen: &str) -> anyhow::Result<Self> {
+        Ok(Self::from_board(Board::from_fen(fen)?))
+    }
+
+    pub fn from_board(board: Board) -> Self {
+        Self {
+            start_board: board.clone(),
+            board,
+            records: Vec::new(),
+            ply: 0,
+            tags: Vec::new(),
+            time_control: None,
+            declared_outcome: None,
+        }
+    }
+
+    /// Restarts the game from its original starting position, discarding all moves.
+    pub fn reset(&mut self) {
+        self.board = self.start_board.clone();
+        self.records.clear();
+        self.ply = 0;
+        self.declared_outcome = None;
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn start_board(&self) -> &Board {
+        &self.start_board
+    }
+
+    pub fn side_to_move(&self) -> PieceColor {
+        self.board.side_to_move()
+    }
+
+    /// All moves recorded in this game, including undone moves that can still be redone.
+    pub fn records(&self) -> &[MoveRecord] {
+        &self.records
+    }
+
+    /// The moves leading up to the current position.
+    pub fn played_records(&self) -> &[MoveRecord] {
+        &self.records[..self.ply]
+    }
+
+    pub fn last_record(&self) -> Option<&MoveRecord> {
+        self.played_records().last()
+    }
+
+    pub fn time_control(&self) -> Option<&TimeControl> {
+        self.time_control.as_ref()
+    }
+
+    /// Sets the time control the game is played with, also recorded in the `TimeControl` PGN tag.
+    pub fn set_time_control(&mut self, time_control: Option<TimeControl>) {
+        match &time_control {
+            Some(time_control) => self.set_tag("TimeControl", &time_control.to_string()),
+            None => self.tags.retain(|(name, _)| name != "TimeControl"),
+        }
+        self.time_control = time_control;
+    }
+
+    fn records_by(&self, color: PieceColor) -> impl DoubleEndedIterator<Item = &MoveRecord> + '_ {
+        let first_mover = self.start_board.side_to_move();
+        self.played_records().iter().enumerate()
+            .filter(move |(i, _)| (if i % 2 == 0 { first_mover } else { !first_mover }) == color)
+            .map(|(_, record)| record)
+    }
+
+    /// Number of moves `color` has played from the starting position.
+    pub fn moves_played(&self, color: PieceColor) -> usize {
+        self.records_by(color).count()
+    }
+
+    /// Time left on `color`'s clock after their last move, or their starting time if they haven't moved yet.
+    /// `None` if the game is untimed.
+    pub fn clock(&self, color: PieceColor) -> Option<Duration> {
+        match self.records_by(color).next_back() {
+            Some(record) => record.clock,
+            None => self.time_control.as_ref().map(|time_control| time_control.stages()[0].time),
+        }
+    }
+
+    /// Ends the game because `color` ran out of time. It's a draw if the opponent couldn't checkmate anyway.
+    pub fn flag_fall(&mut self, color: PieceColor) {
+        let opponent_has_material = self.board.has_mating_material(!color);
+        self.declare_outcome(GameOutcome::Timeout { winner: Some(!color).filter(|_| opponent_has_material) });
+    }
+
+    /// Number of moves played from the starting position to the current position.
+    pub fn ply(&self) -> usize {
+        self.ply
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Plays `mov` in the current position. Any undone moves are discarded.
+    pub fn make_move(&mut self, mov: Move, clock: Option<Duration>) {
+        let san = mov.to_san(&self.board);
+        self.records.truncate(self.ply);
+        self.declared_outcome = None;
+        self.board.make_move(mov, false);
+        self.records.push(MoveRecord {
+            mov,
+            san,
+            timestamp: SystemTime::now(),
+            clock,
+        });
+        self.ply += 1;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.ply > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.ply < self.records.len()
+    }
+
+    /// Takes back the last move, returning it. The move can be replayed with [`Game::redo`].
+    pub fn undo(&mut self) -> Option<Move> {
+        if !self.can_undo() {
+            return None;
+        }
+        self.ply -= 1;
+        self.declared_outcome = None;
+        let mov = self.records[self.ply].mov;
+        self.board.unmake_move(mov, false);
+        Some(mov)
+    }
+
+    /// Replays the next undone move, returning it.
+    pub fn redo(&mut self) -> Option<Move> {
+        if !self.can_redo() {
+            return None;
+        }
+        let mov = self.records[self.ply].mov;
+        self.board.make_move(mov, false);
+        self.ply += 1;
+        Some(mov)
+    }
+
+    /// Moves the cursor to `ply` by undoing or redoing moves. Values past the end of the history are clamped.
+    pub fn jump_to(&mut self, ply: usize) {
+        let ply = ply.min(self.records.len());
+        while self.ply > ply {
+            self.undo();
+        }
+        while self.ply < ply {
+            self.redo();
+        }
+    }
+
+    /// Ends the game with an outcome that the rules don't decide, e.g. a forfeit. It is dropped again when
+    /// the position changes.
+    pub fn declare_outcome(&mut self, outcome: GameOutcome) {
+        self.declared_outcome = Some(outcome);
+    }
+
+    /// How the game ended, if it did. The fifty-move rule and threefold repetition are applied as soon as they
+    /// hold, the draw doesn't have to be claimed.
+    pub fn outcome(&self) -> Option<GameOutcome> {
+        if self.declared_outcome.is_some() {
+            return self.declared_outcome;
+        }
+
+        let variant = self.board.variant();
+        if let Some(winner) = self.board.variant_winner() {
+            return Some(GameOutcome::VariantWin { winner, variant });
+        }
+
+        let mut move_generator = MoveGenerator::new();
+        let mut has_move = false;
+        move_generator.generate_moves(&self.board, &mut |_| has_move = true, false);
+
+        if !has_move {
+            Some(match variant.winner_without_moves(self.side_to_move(), move_generator.in_check()) {
+                Some(winner) if variant == Variant::Antichess => GameOutcome::VariantWin { winner, variant },
+                Some(winner) => GameOutcome::Checkmate { winner },
+                None => GameOutcome::Stalemate,
+            })
+        } else if self.board.halfmove_clock() >= 100 {
+            Some(GameOutcome::FiftyMoveRule)
+        } else if self.board.repetition_count() >= 3 {
+            Some(GameOutcome::ThreefoldRepetition)
+        } else {
+            None
+        }
+    }
+
+    /// Finds the legal move of the piece on `src` to `dst` like [`BoardState::validate_move`], refusing every move
+    /// once the game is over, including by repetition or a declared outcome. Both draws apply without a claim, see
+    /// [`Game::outcome`].
+    pub fn validate_move(&self, src: Position, dst: Position, promotion: Option<PromotionKind>) -> Result<Move, IllegalReason> {
+        match self.outcome() {
+            Some(GameOutcome::Checkmate { .. }) => Err(IllegalReason::InCheckmate),
+            Some(_) => Err(IllegalReason::GameOver),
+            None => self.board.validate_move(src, dst, promotion),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Sets a PGN tag pair that will be written by [`Game::to_pgn`].
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        if let Some(tag) = self.tags.iter_mut().find(|(key, _)| key == name) {
+            tag.1 = value.to_string();
+        } else {
+            self.tags.push((name.to_string(), value.to_string()));
+        }
+    }
+
+    /// Exports the moves up to the current position as PGN.
+    pub fn to_pgn(&self) -> String {
+        const SEVEN_TAG_ROSTER: [&str; 6] = ["Event", "Site", "Date", "Round", "White", "Black"];
+
+        let result = self.outcome().map(|outcome| outcome.result_str()).unwrap_or("*");
+
+        let mut pgn = String::new();
+        for name in SEVEN_TAG_ROSTER {
+            pgn.push_str(&format!("[{name} \"{}\"]\n", self.tag(name).unwrap_or("?")));
+        }
+        pgn.push_str(&format!("[Result \"{result}\"]\n"));
+
+        let start_fen = self.start_board.to_fen();
+        if start_fen != Board::with_variant(self.start_board.variant()).to_fen() {
+            pgn.push_str("[SetUp \"1\"]\n");
+            pgn.push_str(&format!("[FEN \"{start_fen}\"]\n"));
+        }
+        if self.start_board.variant() != Variant::Standard {
+            pgn.push_str(&format!("[Variant \"{}\"]\n", self.start_board.variant()));
+        } else if self.start_board.is_chess960() {
+            pgn.push_str("[Variant \"Chess960\"]\n");
+        }
+        for (name, value) in self.tags.iter() {
+            if !SEVEN_TAG_ROSTER.contains(&name.as_str()) && name != "Result" && name != "SetUp" && name != "FEN" && name != "Variant" {
+                pgn.push_str(&format!("[{name} \"{value}\"]\n"));
+            }
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        let mut board = self.start_board.clone();
+        for (i, record) in self.played_records().iter().enumerate() {
+            let fullmove = (board.ply_count() / 2) + 1;
+            if board.side_to_move().is_white() {
+                tokens.push(format!("{fullmove}."));
+            } else if i == 0 {
+                tokens.push(format!("{fullmove}..."));
+            }
+            tokens.push(record.san.clone());
+            if let Some(clock) = record.clock {
+                let secs = clock.as_secs();
+                tokens.push(format!("{{[%clk {}:{:02}:{:02}]}}", secs / 3600, (secs / 60) % 60, secs % 60));
+            }
+            board.make_move(record.mov, true);
+        }
+        tokens.push(result.to_string());
+
+        // Wrap movetext at 80 columns
+        let mut line_len = 0;
+        for token in tokens {
+            if line_len > 0 && line_len + 1 + token.len() > 80 {
+                pgn.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                pgn.push(' ');
+                line_len += 1;
+            }
+            line_len += token.len();
+            pgn.push_str(&token);
+        }
+        pgn.push('\n');
+
+        pgn
+    }
+}
+
+#[cfg(test)]
+mod game_test {
+    use crate::chess::*;
+
+    fn play(game: &mut Game, text: &str) {
+        let mut found = None;
+        MoveGenerator::new().generate_moves(game.board(), &mut |mov| if mov.to_string() == text { found = Some(mov) }, false);
+        game.make_move(found.unwrap(), None);
+    }
+
+    #[test]
+    fn undo_redo_test() {
+        let mut game = Game::new();
+        let start_fen = game.board().to_fen();
+        for mov in ["e2e4", "d7d5", "e4d5", "g8f6"] {
+            play(&mut game, mov);
+        }
+        let end_fen = game.board().to_fen();
+        assert_eq!(game.played_records().iter().map(|record| record.san.as_str()).collect::<Vec<_>>(), ["e4", "d5", "exd5", "Nf6"]);
+
+        game.jump_to(0);
+        assert_eq!(game.board().to_fen(), start_fen);
+        assert!(!game.can_undo());
+        assert!(game.can_redo());
+
+        game.jump_to(4);
+        assert_eq!(game.board().to_fen(), end_fen);
+
+        game.undo();
+        game.undo();
+        play(&mut game, "e4e5");
+        assert_eq!(game.len(), 3);
+        assert!(!game.can_redo());
+    }
+
+    #[test]
+    fn outcome_test() {
+        let mut game = Game::new();
+        for mov in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            play(&mut game, mov);
+        }
+        assert_eq!(game.outcome(), Some(GameOutcome::Checkmate { winner: PieceColor::Black }));
+        assert!(game.to_pgn().ends_with("1. f3 e5 2. g4 Qh4# 0-1\n"));
+
+        game.undo();
+        assert_eq!(game.outcome(), None);
+    }
+
+    #[test]
+    fn variant_outcome_test() {
+        let mut game = Game::from_board(Board::from_variant_fen(Variant::KingOfTheHill, "4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap());
+        for mov in ["e1e2", "e8e7", "e2e3", "e7e6"] {
+            play(&mut game, mov);
+        }
+        assert_eq!(game.outcome(), None);
+        play(&mut game, "e3e4");
+        assert_eq!(game.outcome(), Some(GameOutcome::VariantWin { winner: PieceColor::White, variant: Variant::KingOfTheHill }));
+        assert!(game.to_pgn().contains("[Variant \"King of the Hill\"]"));
+
+        let mut game = Game::from_board(Board::from_variant_fen(Variant::Antichess, "8/8/8/8/8/8/p7/1R6 b - - 0 1").unwrap());
+        play(&mut game, "a2b1q");
+        assert_eq!(game.outcome(), Some(GameOutcome::VariantWin { winner: PieceColor::White, variant: Variant::Antichess }));
+    }
+
+    #[test]
+    fn flag_fall_test() {
+        // White flags against a lone minor piece, which can't mate, and against material that can
+        for (fen, winner) in [
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", None),
+            ("4k3/8/5n2/8/8/8/4P3/4K3 w - - 0 1", None),
+            ("4k3/8/5b2/8/8/8/4P3/4K3 w - - 0 1", None),
+            ("4k3/8/5bb1/8/8/8/4P3/4K3 w - - 0 1", Some(PieceColor::Black)),
+            ("4k3/5p2/8/8/8/8/4P3/4K3 w - - 0 1", Some(PieceColor::Black)),
+            ("4k3/8/5r2/8/8/8/4P3/4K3 w - - 0 1", Some(PieceColor::Black)),
+        ] {
+            let mut game = Game::from_fen(fen).unwrap();
+            game.flag_fall(PieceColor::White);
+            assert_eq!(game.outcome(), Some(GameOutcome::Timeout { winner }), "{fen}");
+        }
+
+        // Pieces in the pocket can still be dropped to mate
+        let board = Board::from_variant_fen(Variant::Crazyhouse, "4k3/8/8/8/8/8/8/4K3[n] w - - 0 1").unwrap();
+        let mut game = Game::from_board(board);
+        game.flag_fall(PieceColor::White);
+        assert_eq!(game.outcome(), Some(GameOutcome::Timeout { winner: Some(PieceColor::Black) }));
+    }
+
+    #[test]
+    fn repetition_test() {
+        let mut game = Game::new();
+        for _ in 0..2 {
+            for mov in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                assert_eq!(game.outcome(), None);
+                play(&mut game, mov);
+            }
+        }
+        assert_eq!(game.board().repetition_count(), 3);
+        assert_eq!(game.outcome(), Some(GameOutcome::ThreefoldRepetition));
+
+        assert_eq!(game.validate_move(Position::G1, Position::F3, None), Err(IllegalReason::GameOver));
+
+        game.undo();
+        assert_eq!(game.board().repetition_count(), 2);
+        assert_eq!(game.outcome(), None);
+        assert!(game.validate_move(Position::F6, Position::G8, None).is_ok());
+
+        game.declare_outcome(GameOutcome::Forfeit { winner: PieceColor::White });
+        assert_eq!(game.validate_move(Position::F6, Position::G8, None), Err(IllegalReason::GameOver));
+    }
+}
@@ -1,4 +1,3 @@
-use std::{collections::HashMap, sync::{LazyLock, OnceLock, RwLock}};
 
 use crate::chess::{bitboards::BitBoard, position::*};
 use super::*;
@@ -153,148 +152,24 @@ const KNIGHT_JUMPS: [Offset; 8] = [
 //     0x007fff9fdf7ff813,
 // ];
 
-struct MagicEntry {
-    neg_mask: u64,
-    magic: u64,
-    offset: u32
-}
-
-const EMPTY_MAGIC_ENTRY: MagicEntry = MagicEntry {
-    neg_mask: 0,
-    magic: 0,
-    offset: 0
-};
-
-// const fns can't be parameterized by functions, so we use a macro instead.
-macro_rules! gen_entries {
-    ($relevant_blockers:ident, $raw_magics:expr) => {{
-        let raw_magics = $raw_magics;
-        let mut magics = [EMPTY_MAGIC_ENTRY; Position::NUM];
-        let mut i = 0;
-        while i < raw_magics.len() {
-            let neg_mask = !$relevant_blockers[i];
-            let (magic, offset) = raw_magics[i];
-            magics[i] = MagicEntry { neg_mask, magic, offset };
-            i += 1;
-        }
-        magics
-    }};
-}
-
-// Black magics found by Volker Annuss and Niklas Fiekas
-// http://talkchess.com/forum/viewtopic.php?t=64790
-
-const ORTHOGONAL_MAGICS: &[MagicEntry; Position::NUM] = &gen_entries!(
-    ORTHOGONAL_MASKS,
-    [
-        (0x80280013FF84FFFF, 10890), (0x5FFBFEFDFEF67FFF, 50579), (0xFFEFFAFFEFFDFFFF, 62020),
-        (0x003000900300008A, 67322), (0x0050028010500023, 80251), (0x0020012120A00020, 58503),
-        (0x0030006000C00030, 51175), (0x0058005806B00002, 83130), (0x7FBFF7FBFBEAFFFC, 50430),
-        (0x0000140081050002, 21613), (0x0000180043800048, 72625), (0x7FFFE800021FFFB8, 80755),
-        (0xFFFFCFFE7FCFFFAF, 69753), (0x00001800C0180060, 26973), (0x4F8018005FD00018, 84972),
-        (0x0000180030620018, 31958), (0x00300018010C0003, 69272), (0x0003000C0085FFFF, 48372),
-        (0xFFFDFFF7FBFEFFF7, 65477), (0x7FC1FFDFFC001FFF, 43972), (0xFFFEFFDFFDFFDFFF, 57154),
-        (0x7C108007BEFFF81F, 53521), (0x20408007BFE00810, 30534), (0x0400800558604100, 16548),
-        (0x0040200010080008, 46407), (0x0010020008040004, 11841), (0xFFFDFEFFF7FBFFF7, 21112),
-        (0xFEBF7DFFF8FEFFF9, 44214), (0xC00000FFE001FFE0, 57925), (0x4AF01F00078007C3, 29574),
-        (0xBFFBFAFFFB683F7F, 17309), (0x0807F67FFA102040, 40143), (0x200008E800300030, 64659),
-        (0x0000008780180018, 70469), (0x0000010300180018, 62917), (0x4000008180180018, 60997),
-        (0x008080310005FFFA, 18554), (0x4000188100060006, 14385), (0xFFFFFF7FFFBFBFFF,     0),
-        (0x0000802000200040, 38091), (0x20000202EC002800, 25122), (0xFFFFF9FF7CFFF3FF, 60083),
-        (0x000000404B801800, 72209), (0x2000002FE03FD000, 67875), (0xFFFFFF6FFE7FCFFD, 56290),
-        (0xBFF7EFFFBFC00FFF, 43807), (0x000000100800A804, 73365), (0x6054000A58005805, 76398),
-        (0x0829000101150028, 20024), (0x00000085008A0014,  9513), (0x8000002B00408028, 24324),
-        (0x4000002040790028, 22996), (0x7800002010288028, 23213), (0x0000001800E08018, 56002),
-        (0xA3A80003F3A40048, 22809), (0x2003D80000500028, 44545), (0xFFFFF37EEFEFDFBE, 36072),
-        (0x40000280090013C1,  4750), (0xBF7FFEFFBFFAF71F,  6014), (0xFFFDFFFF777B7D6E, 36054),
-        (0x48300007E8080C02, 78538), (0xAFE0000FFF780402, 28745), (0xEE73FFFBFFBB77FE,  8555),
-        (0x0002000308482882,  1009)
-    ]
-);
-
-const DIAGONAL_MAGICS: &[MagicEntry; Position::NUM] = &gen_entries!(
-    DIAGONAL_MASKS,
-    [
-        (0xA7020080601803D8, 60984), (0x13802040400801F1, 66046), (0x0A0080181001F60C, 32910),
-        (0x1840802004238008, 16369), (0xC03FE00100000000, 42115), (0x24C00BFFFF400000,   835),
-        (0x0808101F40007F04, 18910), (0x100808201EC00080, 25911), (0xFFA2FEFFBFEFB7FF, 63301),
-        (0x083E3EE040080801, 16063), (0xC0800080181001F8, 17481), (0x0440007FE0031000, 59361),
-        (0x2010007FFC000000, 18735), (0x1079FFE000FF8000, 61249), (0x3C0708101F400080, 68938),
-        (0x080614080FA00040, 61791), (0x7FFE7FFF817FCFF9, 21893), (0x7FFEBFFFA01027FD, 62068),
-        (0x53018080C00F4001, 19829), (0x407E0001000FFB8A, 26091), (0x201FE000FFF80010, 15815),
-        (0xFFDFEFFFDE39FFEF, 16419), (0xCC8808000FBF8002, 59777), (0x7FF7FBFFF8203FFF, 16288),
-        (0x8800013E8300C030, 33235), (0x0420009701806018, 15459), (0x7FFEFF7F7F01F7FD, 15863),
-        (0x8700303010C0C006, 75555), (0xC800181810606000, 79445), (0x20002038001C8010, 15917),
-        (0x087FF038000FC001,  8512), (0x00080C0C00083007, 73069), (0x00000080FC82C040, 16078),
-        (0x000000407E416020, 19168), (0x00600203F8008020, 11056), (0xD003FEFE04404080, 62544),
-        (0xA00020C018003088, 80477), (0x7FBFFE700BFFE800, 75049), (0x107FF00FE4000F90, 32947),
-        (0x7F8FFFCFF1D007F8, 59172), (0x0000004100F88080, 55845), (0x00000020807C4040, 61806),
-        (0x00000041018700C0, 73601), (0x0010000080FC4080, 15546), (0x1000003C80180030, 45243),
-        (0xC10000DF80280050, 20333), (0xFFFFFFBFEFF80FDC, 33402), (0x000000101003F812, 25917),
-        (0x0800001F40808200, 32875), (0x084000101F3FD208,  4639), (0x080000000F808081, 17077),
-        (0x0004000008003F80, 62324), (0x08000001001FE040, 18159), (0x72DD000040900A00, 61436),
-        (0xFFFFFEFFBFEFF81D, 57073), (0xCD8000200FEBF209, 61025), (0x100000101EC10082, 81259),
-        (0x7FBAFFFFEFE0C02F, 64083), (0x7F83FFFFFFF07F7F, 56114), (0xFFF1FFFFFFF7FFC1, 57058),
-        (0x0878040000FFE01F, 58912), (0x945E388000801012, 22194), (0x0840800080200FDA, 70880),
-        (0x100000C05F582008, 11140)
-    ]
-);
-
-const ORTHOGONAL_INDEX_BITS: usize = 12;
-
-const DIAGONAL_INDEX_BITS: usize = 9;
-
-const fn get_magic_index(coord_index: usize, blockers: u64, ortho: bool) -> usize {
-    let (magics, index_bits) = if ortho {
-        (ORTHOGONAL_MAGICS, ORTHOGONAL_INDEX_BITS)
-    } else {
-        (DIAGONAL_MAGICS, DIAGONAL_INDEX_BITS)
-    };
-    let magic = &magics[coord_index];
-    let relevant_blockers = blockers | magic.neg_mask;
-    let hash = relevant_blockers.wrapping_mul(magic.magic);
-    magic.offset as usize + (hash >> (Position::NUM - index_bits)) as usize
-}
-
-const fn create_movement_mask(coord_index: usize, ortho: bool) -> u64 {
-    let mut mask = BitBoard::new();
-    let directions = if ortho {
-        Offset::ORTHO_DIRECTIONS
-    } else {
-        Offset::DIAG_DIRECTIONS
-    };
-    let src = Position::from_index(coord_index as u8);
-    let mut i = 0usize;
-    while i < 4 {
-        {
-            let dir = directions[i];
-            let mut dist = 1i8;
-            while dist < 8 {
-                if let Some(dst) = src.checked_offset(dir.with_magnitude(dist)) {
-                    mask.set(dst);
-                    dist += 1;
-                } else {
-                    break;
-                }
-            }
-        }
-        i += 1;
-    }
-    mask.0
-}
+pub mod black_magic;
+pub mod search;
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+mod pext;
 
-const fn create_movement_masks(ortho: bool) -> [u64; 64] {
+const fn create_neg_masks(ortho: bool) -> [u64; 64] {
     let mut masks = [0; 64];
     let mut i = 0usize;
     while i < 64 {
-        masks[i] = create_movement_mask(i, ortho);
+        masks[i] = !black_magic::relevant_blockers(i, ortho);
         i += 1;
     }
     masks
 }
 
-const ORTHOGONAL_MASKS: [u64; 64] = create_movement_masks(true);
-const DIAGONAL_MASKS: [u64; 64] = create_movement_masks(false);
+/// Squares irrelevant to each square's sliding attacks, which the black magics hash as occupied.
+const ORTHOGONAL_NEG_MASKS: [u64; 64] = create_neg_masks(true);
+const DIAGONAL_NEG_MASKS: [u64; 64] = create_neg_masks(false);
 
 const KING_MOVES: [u64; 64] = {
     let mut table = [0u64; 64];
@@ -441,75 +316,24 @@ const ALIGN_MASKS: [[u64; 8]; 64] = {
     table
 };
 
-const fn create_sliding_moves(coord_index: usize, blockers: u64, ortho: bool) -> u64 {
-    let blockers = BitBoard(blockers);
-    let mut bitboard = BitBoard::new();
-    let directions = if ortho {
-        Offset::ORTHO_DIRECTIONS
-    } else {
-        Offset::DIAG_DIRECTIONS
-    };
-    let src = Position::from_index(coord_index as u8);
-    let mut i = 0;
-    while i < 4 {
-        let dir = directions[i];
-        let mut dist = 1;
-        while dist < 8 {
-            if let Some(dst) = src.checked_offset(dir.with_magnitude(dist)) {
-                bitboard.set(dst);
-                if blockers.get(dst) {
-                    break;
-                }
-            } else {
-                break;
-            }
-            dist += 1;
-        }
-        i += 1;
-    }
-    bitboard.0
-
-}
-
-const fn write_attacks(table: &mut [BitBoard], ortho: bool) {
-    let mut coord_index = 0usize;
-    while coord_index < 64 {
-        let mut subset_iter = BitBoard(if ortho {
-            ORTHOGONAL_MASKS[coord_index]
-        } else {
-            DIAGONAL_MASKS[coord_index]
-        }).iter_subsets();
-        while let Some(subset) = subset_iter.const_next() {
-            table[get_magic_index(coord_index, subset.0, ortho)] = BitBoard(create_sliding_moves(coord_index, subset.0, ortho));
-        }
-        coord_index += 1;
-    }
-}
-
-const SLIDING_MOVE_TABLE_SIZE: usize = 87988;
-
-static SLIDING_MOVES: LazyLock<RwLock<HashMap<(Position, BitBoard, bool), BitBoard>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+// Attacks of every square and relevant blocker subset for both kinds of sliders, precomputed by the build script
+include!(concat!(env!("OUT_DIR"), "/sliding_attacks.rs"));
 
-fn get_sliding_attacks_worse(pos: Position, blockers: BitBoard, ortho: bool) -> BitBoard {
-    let lock = SLIDING_MOVES.read().unwrap();
-    let key = (pos, blockers, ortho);
-    if lock.contains_key(&key) {
-        *lock.get(&key).unwrap()
-    } else {
-        let val = BitBoard(create_sliding_moves(pos.into_index() as usize, blockers.0, ortho));
-        drop(lock);
-        SLIDING_MOVES.write().unwrap().insert(key, val);
-        val
-    }
+#[inline]
+fn get_magic_sliding_attacks(pos: Position, blockers: BitBoard, ortho: bool) -> BitBoard {
+    let square = pos.into_index() as usize;
+    let neg_mask = if ortho { ORTHOGONAL_NEG_MASKS[square] } else { DIAGONAL_NEG_MASKS[square] };
+    BitBoard(SLIDING_ATTACKS[black_magic::magic_index(square, neg_mask, blockers.0, ortho)])
 }
 
-static SLIDING_MOVE_TABLE: [OnceLock<BitBoard>; SLIDING_MOVE_TABLE_SIZE] = [const { OnceLock::new() }; SLIDING_MOVE_TABLE_SIZE];
-
+#[inline]
 fn get_sliding_attacks(pos: Position, blockers: BitBoard, ortho: bool) -> BitBoard {
-    let index = get_magic_index(pos.into_index() as usize, blockers.0, ortho);
-    *SLIDING_MOVE_TABLE[index].get_or_init(|| {
-        BitBoard(create_sliding_moves(pos.into_index() as usize, blockers.0, ortho))
-    })
+    #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+    if pext::available() {
+        // SAFETY: BMI2 support was just checked
+        return BitBoard(unsafe { pext::get_sliding_attacks(pos.into_index() as usize, blockers.0, ortho) });
+    }
+    get_magic_sliding_attacks(pos, blockers, ortho)
 }
 
 // fn create_attack_table(coord_index: usize, ortho: bool) {
@@ -529,12 +353,14 @@ fn get_sliding_attacks(pos: Position, blockers: BitBoard, ortho: bool) -> BitBoa
 //     table
 // }
 
+#[inline]
 pub fn get_orthogonal_attacks(pos: Position, blockers: BitBoard) -> BitBoard {
-    get_sliding_attacks_worse(pos, blockers, true)
+    get_sliding_attacks(pos, blockers, true)
 }
 
+#[inline]
 pub fn get_diagonal_attacks(pos: Position, blockers: BitBoard) -> BitBoard {
-    get_sliding_attacks_worse(pos, blockers, false)
+    get_sliding_attacks(pos, blockers, false)
 }
 
 pub fn get_king_moves(pos: Position) -> BitBoard {
@@ -579,4 +405,39 @@ pub fn get_dir_ray_mask(src: Position, dst: Position) -> BitBoard {
 
 pub fn get_align_mask(src: Position, dst: Position) -> BitBoard {
     get_dir_ray_mask(src, dst) | get_dir_ray_mask(dst, src)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod magic_test {
+    use super::*;
+
+    #[cfg(all(feature = "pext", target_arch = "x86_64"))]
+    #[test]
+    fn pext_test() {
+        if !pext::available() {
+            return;
+        }
+        for index in 0..64u8 {
+            for ortho in [true, false] {
+                let mut subsets = BitBoard(black_magic::relevant_blockers(index as usize, ortho)).iter_subsets();
+                while let Some(blockers) = subsets.const_next() {
+                    // SAFETY: BMI2 support was checked above
+                    let pext_attacks = unsafe { pext::get_sliding_attacks(index as usize, blockers.0, ortho) };
+                    assert_eq!(pext_attacks, get_magic_sliding_attacks(Position::from_index(index), blockers, ortho).0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sliding_attacks_test() {
+        for index in 0..64u8 {
+            let pos = Position::from_index(index);
+            for _ in 0..1000 {
+                // Sparse boards like in real games, with the slider's own square possibly occupied too
+                let blockers = rand::random::<u64>() & rand::random::<u64>() & rand::random::<u64>();
+                assert_eq!(get_orthogonal_attacks(pos, BitBoard(blockers)).0, black_magic::sliding_attacks(index as usize, blockers, true));
+                assert_eq!(get_diagonal_attacks(pos, BitBoard(blockers)).0, black_magic::sliding_attacks(index as usize, blockers, false));
+            }
+        }
+    }
+}
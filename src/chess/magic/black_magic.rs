@@ -0,0 +1,73 @@
+//! Black magic bitboards for sliding piece attacks. The build script compiles this file too, to precompute the
+//! attack table, so it can't use anything from the rest of the crate. Squares are indices from 0 (a1) to 63 (h8).
+//!
+//! The magic numbers themselves are in `magics.rs`, which can be regenerated with the `chess-magics` tool.
+
+pub const ORTHOGONAL_INDEX_BITS: u32 = 12;
+
+pub const DIAGONAL_INDEX_BITS: u32 = 9;
+
+include!("magics.rs");
+
+const ORTHOGONAL_DIRECTIONS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+const DIAGONAL_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+/// Squares attacked by a slider on `square` when the squares in `blockers` are occupied.
+pub const fn sliding_attacks(square: usize, blockers: u64, ortho: bool) -> u64 {
+    let directions = if ortho { ORTHOGONAL_DIRECTIONS } else { DIAGONAL_DIRECTIONS };
+    let mut attacks = 0;
+    let mut i = 0;
+    while i < 4 {
+        let (rank_step, file_step) = directions[i];
+        let (mut rank, mut file) = ((square / 8) as i8 + rank_step, (square % 8) as i8 + file_step);
+        while rank >= 0 && rank < 8 && file >= 0 && file < 8 {
+            let bit = 1 << (rank * 8 + file);
+            attacks |= bit;
+            if blockers & bit != 0 {
+                break;
+            }
+            rank += rank_step;
+            file += file_step;
+        }
+        i += 1;
+    }
+    attacks
+}
+
+/// Squares whose occupancy changes the attacks of a slider on `square`: its rays without the edge squares they end
+/// on, since nothing lies behind those.
+pub const fn relevant_blockers(square: usize, ortho: bool) -> u64 {
+    let directions = if ortho { ORTHOGONAL_DIRECTIONS } else { DIAGONAL_DIRECTIONS };
+    let mut mask = 0;
+    let mut i = 0;
+    while i < 4 {
+        let (rank_step, file_step) = directions[i];
+        let (mut rank, mut file) = ((square / 8) as i8 + rank_step, (square % 8) as i8 + file_step);
+        while rank + rank_step >= 0 && rank + rank_step < 8 && file + file_step >= 0 && file + file_step < 8 {
+            mask |= 1 << (rank * 8 + file);
+            rank += rank_step;
+            file += file_step;
+        }
+        i += 1;
+    }
+    mask
+}
+
+/// Index into the attack table of a slider on `square` with the given blockers. Black magics hash the blockers
+/// with all irrelevant squares set, hence the negated mask.
+#[inline]
+pub const fn magic_index(square: usize, neg_mask: u64, blockers: u64, ortho: bool) -> usize {
+    let ((magic, offset), index_bits) = if ortho {
+        (ORTHOGONAL_MAGICS[square], ORTHOGONAL_INDEX_BITS)
+    } else {
+        (DIAGONAL_MAGICS[square], DIAGONAL_INDEX_BITS)
+    };
+    offset as usize + magic_hash(magic, neg_mask, blockers, index_bits)
+}
+
+/// Index of the blockers within a square's section of the table, before its offset is added.
+#[inline]
+pub const fn magic_hash(magic: u64, neg_mask: u64, blockers: u64, index_bits: u32) -> usize {
+    ((blockers | neg_mask).wrapping_mul(magic) >> (64 - index_bits)) as usize
+}
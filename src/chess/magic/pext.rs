@@ -0,0 +1,37 @@
+//! Sliding attacks indexed by gathering the relevant blockers with the BMI2 `pext` instruction. Needs no magic
+//! numbers and packs the table without gaps, but `pext` is only fast on CPUs that implement it in hardware.
+
+use std::arch::x86_64::_pext_u64;
+
+use super::black_magic;
+
+include!(concat!(env!("OUT_DIR"), "/pext_attacks.rs"));
+
+const fn create_masks() -> [u64; 128] {
+    let mut masks = [0; 128];
+    let mut i = 0usize;
+    while i < 64 {
+        masks[i] = black_magic::relevant_blockers(i, true);
+        masks[64 + i] = black_magic::relevant_blockers(i, false);
+        i += 1;
+    }
+    masks
+}
+
+/// Relevant blockers of each square, rooks first, in the order of [`PEXT_OFFSETS`].
+const MASKS: [u64; 128] = create_masks();
+
+/// Whether the CPU supports BMI2.
+#[inline]
+pub fn available() -> bool {
+    cfg!(target_feature = "bmi2") || std::is_x86_feature_detected!("bmi2")
+}
+
+/// # Safety
+/// The CPU has to support BMI2, see [`available`].
+#[inline]
+#[target_feature(enable = "bmi2")]
+pub unsafe fn get_sliding_attacks(square: usize, blockers: u64, ortho: bool) -> u64 {
+    let i = if ortho { square } else { 64 + square };
+    PEXT_ATTACKS[PEXT_OFFSETS[i] as usize + _pext_u64(blockers, MASKS[i]) as usize]
+}
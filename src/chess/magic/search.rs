@@ -0,0 +1,158 @@
+//! Search for black magic numbers, used by the `chess-magics` tool to regenerate `magics.rs`. The index bits stay
+//! fixed at [`black_magic::ORTHOGONAL_INDEX_BITS`] and [`black_magic::DIAGONAL_INDEX_BITS`]; the table shrinks by
+//! preferring magics whose indices lie close together and by letting the squares' sections overlap wherever they
+//! agree.
+
+use std::fmt::Write;
+
+use rand::Rng;
+
+use crate::chess::bitboards::BitBoard;
+use super::black_magic;
+
+/// A magic for one square along with the table entries it hashes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SquareMagic {
+    pub square: usize,
+    pub ortho: bool,
+    pub magic: u64,
+    /// Attacks stored at each used index of the square's section, sorted by index.
+    pub entries: Vec<(usize, u64)>,
+}
+
+impl SquareMagic {
+    /// Hashes every blocker subset of `square` with `magic`. Returns `None` on a destructive collision, where two
+    /// subsets with different attacks land on the same index.
+    pub fn try_new(square: usize, ortho: bool, magic: u64) -> Option<Self> {
+        let index_bits = if ortho { black_magic::ORTHOGONAL_INDEX_BITS } else { black_magic::DIAGONAL_INDEX_BITS };
+        let mask = black_magic::relevant_blockers(square, ortho);
+        let mut section = vec![None; 1 << index_bits];
+        for blockers in BitBoard(mask).iter_subsets() {
+            let index = black_magic::magic_hash(magic, !mask, blockers.0, index_bits);
+            let attacks = black_magic::sliding_attacks(square, blockers.0, ortho);
+            match section[index] {
+                Some(existing) if existing != attacks => return None,
+                _ => section[index] = Some(attacks),
+            }
+        }
+        let entries = section.into_iter().enumerate().filter_map(|(index, attacks)| Some((index, attacks?))).collect();
+        Some(Self { square, ortho, magic, entries })
+    }
+
+    /// Distance from the first to past the last used index, the room the section takes up on its own.
+    pub fn span(&self) -> usize {
+        self.entries.last().unwrap().0 + 1 - self.entries[0].0
+    }
+
+    /// Whether this magic is easier to pack than `other`: it has a smaller span, or as big a span with fewer entries.
+    pub fn is_smaller_than(&self, other: &Self) -> bool {
+        (self.span(), self.entries.len()) < (other.span(), other.entries.len())
+    }
+}
+
+/// Tries random numbers until `tries` of them work as a magic for `square`, and returns the smallest of them and
+/// `best`, if given.
+pub fn find_magic(square: usize, ortho: bool, tries: usize, mut best: Option<SquareMagic>, rng: &mut impl Rng) -> SquareMagic {
+    let mut found = 0;
+    while found < tries {
+        // Classic magics are sparse, but with the irrelevant squares hashed as occupied dense numbers work as well
+        let sparse = rng.random::<u64>() & rng.random::<u64>() & rng.random::<u64>();
+        let magic = if rng.random() { sparse } else { !sparse };
+        let Some(candidate) = SquareMagic::try_new(square, ortho, magic) else {
+            continue;
+        };
+        found += 1;
+        if best.as_ref().is_none_or(|best| candidate.is_smaller_than(best)) {
+            best = Some(candidate);
+        }
+    }
+    best.unwrap()
+}
+
+/// The magics in use with their offsets, rooks first.
+pub fn current_magics() -> Vec<(SquareMagic, u32)> {
+    [(true, black_magic::ORTHOGONAL_MAGICS), (false, black_magic::DIAGONAL_MAGICS)].into_iter().flat_map(|(ortho, table)| {
+        table.into_iter().enumerate().map(move |(square, (magic, offset))| (SquareMagic::try_new(square, ortho, magic).expect("Destructive collision"), offset))
+    }).collect()
+}
+
+/// Places the sections of all `magics` in one table, each at the lowest offset where its entries are free or already
+/// hold the same attacks. Returns the offset of each magic, in the given order, and the size of the table.
+pub fn pack(magics: &[SquareMagic]) -> (Vec<u32>, usize) {
+    let mut order = (0..magics.len()).collect::<Vec<_>>();
+    // Wide sections are the hardest to fit, so they go first
+    order.sort_by_key(|&i| std::cmp::Reverse((magics[i].span(), magics[i].entries.len())));
+
+    let mut table: Vec<Option<u64>> = Vec::new();
+    let mut offsets = vec![0; magics.len()];
+    for i in order {
+        let entries = &magics[i].entries;
+        let fits = |offset: usize| entries.iter().all(|&(index, attacks)| table.get(offset + index).is_none_or(|entry| entry.is_none_or(|existing| existing == attacks)));
+        let offset = (0..).find(|&offset| fits(offset)).unwrap();
+        for &(index, attacks) in entries {
+            if offset + index >= table.len() {
+                table.resize(offset + index + 1, None);
+            }
+            table[offset + index] = Some(attacks);
+        }
+        offsets[i] = offset as u32;
+    }
+    (offsets, table.len())
+}
+
+/// Writes the magics and offsets in the format of `magics.rs`, starting with `header` as comments. Both slices are
+/// indexed by square.
+pub fn to_source(orthogonal: &[(u64, u32)], diagonal: &[(u64, u32)], table_size: usize, header: &str) -> String {
+    let mut source = String::new();
+    for line in header.lines() {
+        writeln!(source, "// {line}").unwrap();
+    }
+    writeln!(source, "// Attack table size: {table_size} entries").unwrap();
+    for (name, kind, magics) in [("ORTHOGONAL_MAGICS", "rooks", orthogonal), ("DIAGONAL_MAGICS", "bishops", diagonal)] {
+        writeln!(source, "\n/// Magic number and table offset of each square for {kind}.").unwrap();
+        writeln!(source, "pub const {name}: [(u64, u32); 64] = [").unwrap();
+        let rows = magics.chunks(3).map(|row| {
+            row.iter().map(|(magic, offset)| format!("(0x{magic:016X}, {offset:>5})")).collect::<Vec<_>>().join(", ")
+        }).collect::<Vec<_>>();
+        writeln!(source, "    {}", rows.join(",\n    ")).unwrap();
+        source.push_str("];\n");
+    }
+    source
+}
+
+#[cfg(test)]
+mod search_test {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn current_magics_test() {
+        // Also checks the shipped magics for destructive collisions
+        let magics = current_magics();
+        assert_eq!(magics.len(), 128);
+        assert!(magics.iter().enumerate().all(|(i, (magic, _))| magic.square == i % 64 && magic.ortho == (i < 64)));
+    }
+
+    #[test]
+    fn find_magic_test() {
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1);
+        let magics = [find_magic(0, false, 2, None, &mut rng), find_magic(27, false, 2, None, &mut rng)];
+        assert!(magics.iter().all(|magic| SquareMagic::try_new(magic.square, magic.ortho, magic.magic).as_ref() == Some(magic)));
+
+        let (offsets, size) = pack(&magics);
+        let mut table = vec![0; size];
+        for (magic, &offset) in magics.iter().zip(&offsets) {
+            for &(index, attacks) in &magic.entries {
+                table[offset as usize + index] = attacks;
+            }
+        }
+        for (magic, &offset) in magics.iter().zip(&offsets) {
+            let mask = black_magic::relevant_blockers(magic.square, false);
+            for blockers in BitBoard(mask).iter_subsets() {
+                let index = offset as usize + black_magic::magic_hash(magic.magic, !mask, blockers.0, black_magic::DIAGONAL_INDEX_BITS);
+                assert_eq!(table[index], black_magic::sliding_attacks(magic.square, blockers.0, false));
+            }
+        }
+    }
+}
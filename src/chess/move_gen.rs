@@ -5,7 +5,239 @@ use crate::chess::position::Offset;
 use super::*;
 use bitboards::*;
 
-pub const MAX_MOVES: usize = 218;
+/// Upper bound on the number of legal moves in a position: 218 in standard chess, plus up to 5 kinds of Crazyhouse
+/// drops onto each of the 62 squares not taken by the kings.
+pub const MAX_MOVES: usize = 218 + 5 * 62;
+
+/// Whether the king of `color` on `pos` would be attacked in Atomic if the board was occupied by `occupied`.
+/// Pieces missing from `occupied` are treated as captured.
+fn atomic_attacked(board: &BoardState, pos: Position, color: PieceColor, occupied: BitBoard) -> bool {
+    let enemy = board.pieces(!color) & occupied;
+    // Capturing a touching king would blow up both kings, so they can't attack each other
+    if !(magic::get_king_moves(pos) & board.pieces_of(!color, PieceKind::King) & enemy).is_empty() {
+        return false;
+    }
+    let ortho_sliders = (board.pieces_of(!color, PieceKind::Rook) | board.pieces_of(!color, PieceKind::Queen)) & enemy;
+    let diag_sliders = (board.pieces_of(!color, PieceKind::Bishop) | board.pieces_of(!color, PieceKind::Queen)) & enemy;
+    !(magic::get_orthogonal_attacks(pos, occupied) & ortho_sliders).is_empty()
+        || !(magic::get_diagonal_attacks(pos, occupied) & diag_sliders).is_empty()
+        || !(magic::get_knight_attacks(pos) & board.pieces_of(!color, PieceKind::Knight) & enemy).is_empty()
+        || !(magic::get_pawn_attacks(pos, color) & board.pieces_of(!color, PieceKind::Pawn) & enemy).is_empty()
+}
+
+/// Whether a pseudo-legal move is legal in Atomic.
+fn atomic_is_legal(board: &BoardState, mov: Move) -> bool {
+    let color = board.side_to_move();
+    let (src, dst) = (mov.src(), mov.dst());
+    let mut occupied = board.all_pieces();
+    let mut king_pos = board.king_pos(color);
+
+    if mov.kind() == MoveKind::Castle {
+        let (king_dst, rook_dst) = mov.castle_destinations();
+        // The king may not pass through attacked squares
+        occupied.clear(src);
+        if rank_span(src, king_dst).iter_positions().any(|pos| atomic_attacked(board, pos, color, occupied)) {
+            return false;
+        }
+        occupied.clear(dst);
+        occupied.set(king_dst);
+        occupied.set(rook_dst);
+        return !atomic_attacked(board, king_dst, color, occupied);
+    }
+
+    occupied.clear(src);
+    let is_capture = mov.kind() == MoveKind::EnPassant || board.pieces(!color).get(dst);
+    if is_capture {
+        // Kings can't capture, as they would explode themselves
+        if mov.piece(board).kind == PieceKind::King {
+            return false;
+        }
+        if mov.kind() == MoveKind::EnPassant {
+            occupied.clear(Position::new(src.rank(), dst.file()));
+        }
+        let mut blast = magic::get_king_moves(dst) & !(board.pieces_of(color, PieceKind::Pawn) | board.pieces_of(!color, PieceKind::Pawn));
+        blast.set(dst);
+        if blast.get(king_pos) {
+            return false;
+        }
+        // Blowing up the enemy king wins, even when it leaves the own king in check
+        if !(blast & board.pieces_of(!color, PieceKind::King)).is_empty() {
+            return true;
+        }
+        occupied &= !blast;
+    } else {
+        occupied.set(dst);
+        if king_pos == src {
+            king_pos = dst;
+        }
+    }
+    !atomic_attacked(board, king_pos, color, occupied)
+}
+
+/// All squares on the rank of `a` from the file of `a` to the file of `b`, inclusive.
+pub(super) fn rank_span(a: Position, b: Position) -> BitBoard {
+    let mut span = BitBoard::EMPTY;
+    for file in a.file().min(b.file())..=a.file().max(b.file()) {
+        span.set(Position::new(a.rank(), file));
+    }
+    span
+}
+
+/// Squares from `a` to `b`, inclusive, which have to be on a common rank, file or diagonal.
+fn line_span(a: Position, b: Position) -> BitBoard {
+    magic::get_dir_ray_mask(a, b) & magic::get_dir_ray_mask(b, a)
+}
+
+/// Squares a piece of `kind` and `color` on `pos` attacks, with the board occupied by `occupied`.
+fn piece_attacks(kind: PieceKind, pos: Position, color: PieceColor, occupied: BitBoard) -> BitBoard {
+    match kind {
+        PieceKind::Pawn => magic::get_pawn_attacks(pos, color),
+        PieceKind::Knight => magic::get_knight_attacks(pos),
+        PieceKind::Bishop => magic::get_diagonal_attacks(pos, occupied),
+        PieceKind::Rook => magic::get_orthogonal_attacks(pos, occupied),
+        PieceKind::Queen => magic::get_orthogonal_attacks(pos, occupied) | magic::get_diagonal_attacks(pos, occupied),
+        PieceKind::King => magic::get_king_moves(pos),
+    }
+}
+
+/// Whether `mov` is one of the moves generated with `captures_only`: captures, except for underpromotions, and
+/// promotions to a queen.
+fn is_capture_stage(board: &BoardState, mov: Move) -> bool {
+    match mov.kind() {
+        MoveKind::EnPassant | MoveKind::Promotion(PromotionKind::Queen) => true,
+        MoveKind::Promotion(_) | MoveKind::Castle | MoveKind::Drop(_) => false,
+        _ => board.pieces(!board.side_to_move()).get(mov.dst()),
+    }
+}
+
+/// Which of the legal moves [`MoveGenerator::generate_stage`] generates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveStage {
+    /// Every legal move.
+    All,
+    /// The moves generated with `captures_only`: captures and promotions to a queen.
+    Captures,
+    /// Every legal move when in check, and none otherwise. Works from the checking pieces alone, so the enemy attack
+    /// map isn't filled in.
+    Evasions,
+    /// Moves left out of [`MoveStage::Captures`] that give check, directly or by uncovering an attack.
+    QuietChecks,
+    /// Moves left out of [`MoveStage::Captures`] that don't give check.
+    QuietNonChecks,
+}
+
+/// What it takes for a move of the side to move to give check, worked out once so that many moves of a position can
+/// be tested cheaply: the squares each kind of piece checks the enemy king from, and the pieces standing between
+/// the king and one of their own sliders, which uncover a check by leaving that line.
+#[derive(Debug, Clone, Copy)]
+pub struct CheckInfo {
+    king_pos: Position,
+    /// Indexed by `kind as usize - 1`.
+    check_squares: [BitBoard; 6],
+    discoverers: BitBoard,
+}
+
+impl CheckInfo {
+    /// `None` if there's no enemy king, or the variant has its own rules for when a king is in check.
+    pub fn new(board: &BoardState) -> Option<Self> {
+        let color = board.side_to_move();
+        if board.variant().has_own_legality() || board.pieces_of(!color, PieceKind::King).is_empty() {
+            return None;
+        }
+        let king_pos = board.king_pos(!color);
+        let occupied = board.all_pieces();
+        let ortho = magic::get_orthogonal_attacks(king_pos, occupied);
+        let diag = magic::get_diagonal_attacks(king_pos, occupied);
+        let check_squares = [
+            magic::get_pawn_attacks(king_pos, !color),
+            magic::get_knight_attacks(king_pos),
+            diag,
+            ortho,
+            ortho | diag,
+            BitBoard::EMPTY,
+        ];
+
+        // A piece of ours uncovers a slider if the king sees it once the piece is gone
+        let ortho_sliders = board.pieces_of(color, PieceKind::Rook) | board.pieces_of(color, PieceKind::Queen);
+        let diag_sliders = board.pieces_of(color, PieceKind::Bishop) | board.pieces_of(color, PieceKind::Queen);
+        let mut discoverers = BitBoard::EMPTY;
+        for blocker in (ortho & board.pieces(color)).iter_positions() {
+            let mut without = occupied;
+            without.clear(blocker);
+            if !(magic::get_orthogonal_attacks(king_pos, without) & !ortho & ortho_sliders).is_empty() {
+                discoverers.set(blocker);
+            }
+        }
+        for blocker in (diag & board.pieces(color)).iter_positions() {
+            let mut without = occupied;
+            without.clear(blocker);
+            if !(magic::get_diagonal_attacks(king_pos, without) & !diag & diag_sliders).is_empty() {
+                discoverers.set(blocker);
+            }
+        }
+
+        Some(Self { king_pos, check_squares, discoverers })
+    }
+
+    /// Squares a piece of `kind` on `src` gives check from, directly or by leaving the line of a slider behind it.
+    /// Doesn't cover promotions, castling and en passant.
+    fn check_targets(&self, kind: PieceKind, src: Position) -> BitBoard {
+        let discovered = if self.discoverers.get(src) { !magic::get_align_mask(src, self.king_pos) } else { BitBoard::EMPTY };
+        self.check_squares[kind as usize - 1] | discovered
+    }
+
+    /// Whether the legal move `mov` puts the enemy king in check.
+    pub fn gives_check(&self, board: &BoardState, mov: Move) -> bool {
+        let (src, dst) = (mov.src(), mov.dst());
+        if let Some(kind) = mov.drop_kind() {
+            return self.check_squares[kind as usize - 1].get(dst);
+        }
+        if matches!(mov.kind(), MoveKind::Castle | MoveKind::EnPassant) {
+            return self.gives_check_after(board, mov);
+        }
+
+        let discovered = self.discoverers.get(src) && !magic::get_align_mask(src, self.king_pos).get(dst);
+        let direct = match mov.promotion_kind() {
+            // The promoted piece may attack through the square the pawn left
+            Some(kind) => {
+                let mut occupied = board.all_pieces();
+                occupied.clear(src);
+                piece_attacks(kind.piece_kind(), dst, board.side_to_move(), occupied).get(self.king_pos)
+            },
+            None => self.check_squares[mov.piece(board).kind as usize - 1].get(dst),
+        };
+        direct || discovered
+    }
+
+    /// Looks for attacks on the king after castling or en passant, which both move two pieces and so don't fit the
+    /// check squares and discoverers.
+    fn gives_check_after(&self, board: &BoardState, mov: Move) -> bool {
+        let color = board.side_to_move();
+        let (src, dst) = (mov.src(), mov.dst());
+        let mut occupied = board.all_pieces();
+        let mut ortho_sliders = board.pieces_of(color, PieceKind::Rook) | board.pieces_of(color, PieceKind::Queen);
+        let diag_sliders = board.pieces_of(color, PieceKind::Bishop) | board.pieces_of(color, PieceKind::Queen);
+        let mut pawn_check = false;
+
+        occupied.clear(src);
+        if mov.kind() == MoveKind::Castle {
+            let (king_dst, rook_dst) = mov.castle_destinations();
+            occupied.clear(dst);
+            occupied.set(king_dst);
+            occupied.set(rook_dst);
+            ortho_sliders.clear(dst);
+            ortho_sliders.set(rook_dst);
+        } else {
+            occupied.clear(Position::new(src.rank(), dst.file()));
+            occupied.set(dst);
+            pawn_check = magic::get_pawn_attacks(dst, color).get(self.king_pos);
+        }
+
+        pawn_check
+            || !(magic::get_orthogonal_attacks(self.king_pos, occupied) & ortho_sliders).is_empty()
+            || !(magic::get_diagonal_attacks(self.king_pos, occupied) & diag_sliders).is_empty()
+    }
+}
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct MoveGenerator {
@@ -32,11 +264,31 @@ impl MoveGenerator {
         self.in_check
     }
 
-    fn color(&self, board: &Board) -> PieceColor {
+    pub fn in_double_check(&self) -> bool {
+        self.in_double_check
+    }
+
+    /// Squares a piece other than the king has to move to in order to resolve the current check, i.e. the checking
+    /// pieces and the squares between them and the king. Every square if not in check.
+    pub fn check_ray_mask(&self) -> BitBoard {
+        self.check_ray_mask
+    }
+
+    /// Rays from the king through each pinned piece up to and including the pinning piece.
+    pub fn pin_rays(&self) -> BitBoard {
+        self.pin_rays
+    }
+
+    /// Squares attacked by the opponent, seen through the king so that it can't step back along a checking ray.
+    pub fn enemy_attack_map(&self) -> BitBoard {
+        self.enemy_attack_map_no_pawns | self.enemy_pawn_attack_map
+    }
+
+    fn color(&self, board: &BoardState) -> PieceColor {
         board.side_to_move()
     }
 
-    fn reset(&mut self, board: &Board) {
+    fn reset(&mut self, board: &BoardState) {
         self.in_check = false;
         self.in_double_check = false;
         self.check_ray_mask = BitBoard::EMPTY;
@@ -47,7 +299,7 @@ impl MoveGenerator {
         self.calculate_attack_data(board);
     }
 
-    fn calculate_attack_data(&mut self, board: &Board) {
+    fn calculate_attack_data(&mut self, board: &BoardState) {
         self.enemy_attack_map_no_pawns = BitBoard::EMPTY;
         // Update sliding piece attack map
         {
@@ -159,188 +411,565 @@ impl MoveGenerator {
         }
     }
 
-    pub fn generate_moves<F: FnMut(Move)>(&mut self, board: &Board, push: &mut F, captures_only: bool) {
+    pub fn generate_moves<F: FnMut(Move)>(&mut self, board: &BoardState, push: &mut F, captures_only: bool) {
         self.generate_quiet_moves = !captures_only;
+
+        // Nothing can be played once a variant rule ended the game
+        if board.variant_winner().is_some() {
+            self.in_check = false;
+            self.in_double_check = false;
+            return;
+        }
+        if board.variant().has_own_legality() {
+            self.generate_variant_moves(board, push);
+            return;
+        }
+
         self.reset(board);
 
         self.generate_king_moves(board, push);
         
         if !self.in_double_check {
-            self.generate_sliding_moves(board, push);
-            self.generate_knight_moves(board, push);
+            self.generate_piece_moves(board, push);
             self.generate_pawn_moves(board, push);
+            if board.variant().has_pockets() && self.generate_quiet_moves {
+                self.generate_drops(board, push);
+            }
         }
     }
 
-    fn generate_king_moves<F: FnMut(Move)>(&self, board: &Board, push: &mut F) {
-        const WHITE_KINGSIDE_MASK: BitBoard = const {
-            let mut mask = BitBoard::new();
-            mask.set(Position::F1);
-            mask.set(Position::G1);
-            mask
-        };
-        const BLACK_KINGSIDE_MASK: BitBoard = const {
-            let mut mask = BitBoard::new();
-            mask.set(Position::F8);
-            mask.set(Position::G8);
-            mask
-        };
-        const WHITE_QUEENSIDE_MASK: BitBoard = const {
-            let mut mask = BitBoard::new();
-            mask.set(Position::D1);
-            mask.set(Position::C1);
-            mask
-        };
-        const BLACK_QUEENSIDE_MASK: BitBoard = const {
-            let mut mask = BitBoard::new();
-            mask.set(Position::D8);
-            mask.set(Position::C8);
-            mask
-        };
-        const WHITE_QUEENSIDE_BLOCK_MASK: BitBoard = const {
-            let mut mask = BitBoard::new();
-            mask.set(Position::D1);
-            mask.set(Position::C1);
-            mask.set(Position::B1);
-            mask
-        };
-        const BLACK_QUEENSIDE_BLOCK_MASK: BitBoard = const {
-            let mut mask = BitBoard::new();
-            mask.set(Position::D8);
-            mask.set(Position::C8);
-            mask.set(Position::B8);
-            mask
-        };
+    /// Generates the legal moves of one [`MoveStage`]. Captures, quiet checks and quiet non-checks together make up
+    /// all legal moves; when in check, [`MoveStage::Evasions`] alone already covers them.
+    pub fn generate_stage<F: FnMut(Move)>(&mut self, board: &BoardState, push: &mut F, stage: MoveStage) {
+        match stage {
+            MoveStage::All => self.generate_moves(board, push, false),
+            MoveStage::Captures => self.generate_moves(board, push, true),
+            MoveStage::Evasions => self.generate_evasions(board, push),
+            MoveStage::QuietChecks | MoveStage::QuietNonChecks => {
+                let checks = stage == MoveStage::QuietChecks;
+                match CheckInfo::new(board) {
+                    Some(check_info) => self.generate_quiet_stage(board, push, &check_info, checks),
+                    // Variants with their own rules have to play each move to find out
+                    None => self.generate_moves(board, &mut |mov| {
+                        if !is_capture_stage(board, mov) && mov.gives_check(board) == checks {
+                            push(mov);
+                        }
+                    }, false),
+                }
+            },
+        }
+    }
 
+    /// Generates the moves out of check from the checking pieces: king steps to squares nothing attacks, and unless
+    /// it's double check, captures of the checker and blocks between it and the king by unpinned pieces.
+    fn generate_evasions<F: FnMut(Move)>(&mut self, board: &BoardState, push: &mut F) {
+        self.generate_quiet_moves = true;
+        if board.variant_winner().is_some() || board.variant().has_own_legality() {
+            let mut moves = Vec::with_capacity(MAX_MOVES);
+            self.generate_moves(board, &mut |mov| moves.push(mov), false);
+            if self.in_check {
+                moves.into_iter().for_each(push);
+            }
+            return;
+        }
 
-        let legal_mask = !(self.enemy_attack_map_no_pawns | self.enemy_pawn_attack_map | board.pieces(self.color(board)));
-        let king_moves = magic::get_king_moves(board.king_pos(self.color(board))) & legal_mask & self.move_type_mask;
-        for dst in king_moves.iter_positions() {
-            push(Move::new(board.king_pos(self.color(board)), dst, moves::MoveKind::Basic));
-        }
-
-        // Castling
-        if !self.in_check && self.generate_quiet_moves {
-            let castle_blockers = self.enemy_attack_map_no_pawns | self.enemy_pawn_attack_map | board.all_pieces();
-            if board.can_kingside_castle(self.color(board)) {
-                let (castle_mask, dst) = match self.color(board) {
-                    PieceColor::White => (WHITE_KINGSIDE_MASK, Position::G1),
-                    PieceColor::Black => (BLACK_KINGSIDE_MASK, Position::G8),
-                };
-                if (castle_mask & castle_blockers).is_empty() {
-                    push(Move::new(board.king_pos(self.color(board)), dst, moves::MoveKind::Castle));
+        let color = self.color(board);
+        let king_pos = board.king_pos(color);
+        let checkers = board.checkers(color);
+        self.in_check = !checkers.is_empty();
+        self.in_double_check = checkers.count() > 1;
+        if !self.in_check {
+            return;
+        }
+
+        // The king doesn't block the attacks on the squares behind it
+        let mut occupied = board.all_pieces();
+        occupied.clear(king_pos);
+        for dst in (magic::get_king_moves(king_pos) & !board.pieces(color)).iter_positions() {
+            if board.attackers_to_occupied(dst, !color, occupied).is_empty() {
+                push(Move::new(king_pos, dst, MoveKind::Basic));
+            }
+        }
+        if self.in_double_check {
+            return;
+        }
+
+        let checker = checkers.iter_positions().next().unwrap();
+        let mut check_ray_mask = checker.bitboard();
+        if matches!(board.get(checker).map(|piece| piece.kind), Some(PieceKind::Bishop | PieceKind::Rook | PieceKind::Queen)) {
+            check_ray_mask |= line_span(king_pos, checker);
+            check_ray_mask.clear(king_pos);
+        }
+        // Only pieces between the king and an enemy slider are pinned, which keeps them from ever resolving the check
+        let mut pin_rays = BitBoard::EMPTY;
+        for pinner in board.xray_attackers_to(king_pos, !color).iter_positions() {
+            let ray = line_span(king_pos, pinner);
+            if !(ray & board.pieces(color)).is_empty() {
+                pin_rays |= ray;
+            }
+        }
+        pin_rays.clear(king_pos);
+
+        self.check_ray_mask = check_ray_mask;
+        self.pin_rays = pin_rays;
+        self.move_type_mask = BitBoard::FULL;
+        self.generate_piece_moves(board, push);
+        self.generate_pawn_moves(board, push);
+        if board.variant().has_pockets() {
+            self.generate_drops(board, push);
+        }
+    }
+
+    /// Generates the moves left out of [`MoveStage::Captures`] that give check, or those that don't. The
+    /// destinations of most moves come straight from the check squares and discovered check candidates in
+    /// `check_info`; only promotions and castling, which move more than one piece's worth, are tested one by one.
+    fn generate_quiet_stage<F: FnMut(Move)>(&mut self, board: &BoardState, push: &mut F, check_info: &CheckInfo, checks: bool) {
+        self.generate_quiet_moves = true;
+        if board.variant_winner().is_some() {
+            self.in_check = false;
+            self.in_double_check = false;
+            return;
+        }
+        self.reset(board);
+        // Captures all belong to the captures stage, apart from underpromotions
+        self.move_type_mask = !board.all_pieces();
+
+        let color = self.color(board);
+        let king_pos = board.king_pos(color);
+        let select = |targets: BitBoard, check_targets: BitBoard| targets & if checks { check_targets } else { !check_targets };
+
+        for dst in select(self.king_targets(board), check_info.check_targets(PieceKind::King, king_pos)).iter_positions() {
+            push(Move::new(king_pos, dst, MoveKind::Basic));
+        }
+        let castling_rooks = self.castling_rooks(board);
+        for kingside in [true, false] {
+            let castle = Move::new(king_pos, board.castling_rook(board::CastleKind::new(color, kingside)), MoveKind::Castle);
+            if castling_rooks.get(castle.dst()) && check_info.gives_check(board, castle) == checks {
+                push(castle);
+            }
+        }
+        if self.in_double_check {
+            return;
+        }
+
+        self.visit_piece_targets(board, &mut |src, targets| {
+            let kind = board.get(src).unwrap().kind;
+            for dst in select(targets, check_info.check_targets(kind, src)).iter_positions() {
+                push(Move::new(src, dst, MoveKind::Basic));
+            }
+        });
+
+        let pawns = board.pieces_of(color, PieceKind::Pawn);
+        let promotion_rank_mask = if color.is_white() { BitBoard::RANK_8 } else { BitBoard::RANK_1 };
+        let [(push_offset, single_push), (double_push_offset, double_push), (capture_w_offset, capture_w), (capture_e_offset, capture_e)] = self.pawn_targets(board, pawns);
+        let along_pin = |src: Position, dst: Position| {
+            !self.pin_rays.get(src) || magic::get_align_mask(src, king_pos) == magic::get_align_mask(dst, king_pos)
+        };
+
+        for (offset, targets, kind) in [(push_offset, single_push & !promotion_rank_mask, MoveKind::Basic), (double_push_offset, double_push, MoveKind::PawnDoublePush)] {
+            for dst in targets.iter_positions() {
+                let src = dst - offset;
+                if along_pin(src, dst) && select(dst.bitboard(), check_info.check_targets(PieceKind::Pawn, src)).get(dst) {
+                    push(Move::new(src, dst, kind));
                 }
             }
-            if board.can_queenside_castle(self.color(board)) {
-                let (castle_mask, castle_block_mask, dst) = match self.color(board) {
-                    PieceColor::White => (WHITE_QUEENSIDE_MASK, WHITE_QUEENSIDE_BLOCK_MASK, Position::C1),
-                    PieceColor::Black => (BLACK_QUEENSIDE_MASK, BLACK_QUEENSIDE_BLOCK_MASK, Position::C8),
-                };
-                if (castle_mask & castle_blockers).is_empty() && (castle_block_mask & board.all_pieces()).is_empty() {
-                    push(Move::new(board.king_pos(self.color(board)), dst, moves::MoveKind::Castle));
+        }
+        // Underpromotions, which may capture as well
+        for (offset, targets) in [(push_offset, single_push), (capture_w_offset, capture_w), (capture_e_offset, capture_e)] {
+            for dst in (targets & promotion_rank_mask).iter_positions() {
+                let src = dst - offset;
+                if !along_pin(src, dst) {
+                    continue;
+                }
+                for kind in [PromotionKind::Rook, PromotionKind::Bishop, PromotionKind::Knight] {
+                    let promotion = Move::new(src, dst, MoveKind::Promotion(kind));
+                    if check_info.gives_check(board, promotion) == checks {
+                        push(promotion);
+                    }
+                }
+            }
+        }
+
+        // Dropped pieces can only check directly
+        if board.variant().has_pockets() {
+            for kind in board.pocket(color).kinds() {
+                for dst in select(self.drop_targets(board, kind), check_info.check_squares[kind as usize - 1]).iter_positions() {
+                    push(Move::new_drop(dst, kind));
                 }
             }
         }
     }
 
-    fn generate_sliding_moves<F: FnMut(Move)>(&self, board: &Board, push: &mut F) {
-        let move_mask = !board.pieces(self.color(board)) & self.check_ray_mask & self.move_type_mask;
+    /// Squares a piece of `kind` can be dropped on.
+    fn drop_targets(&self, board: &BoardState, kind: PieceKind) -> BitBoard {
+        // When in check, a drop has to block it
+        let targets = !board.all_pieces() & self.check_ray_mask;
+        if kind == PieceKind::Pawn { targets & !(BitBoard::RANK_1 | BitBoard::RANK_8) } else { targets }
+    }
+
+    fn generate_drops<F: FnMut(Move)>(&self, board: &BoardState, push: &mut F) {
+        for kind in board.pocket(self.color(board)).kinds() {
+            for dst in self.drop_targets(board, kind).iter_positions() {
+                push(Move::new_drop(dst, kind));
+            }
+        }
+    }
+
+    /// Counts the legal moves from the squares each piece can move to, without building them, which makes it much
+    /// faster than counting what [`Self::generate_moves`] pushes. Every promotion counts once per piece it can
+    /// promote to.
+    pub fn count_moves(&mut self, board: &BoardState) -> usize {
+        self.generate_quiet_moves = true;
+        if board.variant_winner().is_some() || board.variant().has_own_legality() {
+            let mut count = 0;
+            self.generate_moves(board, &mut |_| count += 1, false);
+            return count;
+        }
+        self.reset(board);
+
+        let mut count = self.king_targets(board).count() + self.castling_rooks(board).count();
+        if self.in_double_check {
+            return count;
+        }
+        self.visit_piece_targets(board, &mut |_, targets| count += targets.count());
+
+        let color = self.color(board);
+        let king_pos = board.king_pos(color);
+        let promotion_rank_mask = if color.is_white() { BitBoard::RANK_8 } else { BitBoard::RANK_1 };
+        let count_targets = |targets: BitBoard| (targets & !promotion_rank_mask).count() + 4 * (targets & promotion_rank_mask).count();
+        // Every pawn has at most one move of each kind, so unpinned pawns can be counted all at once
+        let pawns = board.pieces_of(color, PieceKind::Pawn);
+        for (_, targets) in self.pawn_targets(board, pawns & !self.pin_rays) {
+            count += count_targets(targets);
+        }
+        for src in (pawns & self.pin_rays).iter_positions() {
+            for (_, targets) in self.pawn_targets(board, src.bitboard()) {
+                count += count_targets(targets & magic::get_align_mask(src, king_pos));
+            }
+        }
+        count += self.en_passant_sources(board).count();
 
-        let mut ortho_sliders = board.pieces_of(self.color(board), PieceKind::Rook) | board.pieces_of(self.color(board), PieceKind::Queen);
-        let mut diag_sliders = board.pieces_of(self.color(board), PieceKind::Bishop) | board.pieces_of(self.color(board), PieceKind::Queen);
+        if board.variant().has_pockets() {
+            for kind in board.pocket(color).kinds() {
+                count += self.drop_targets(board, kind).count();
+            }
+        }
+        count
+    }
 
-        if self.in_check {
-            ortho_sliders &= !self.pin_rays;
-            diag_sliders &= !self.pin_rays;
+    /// Squares each piece of the side to move can legally move to, indexed by the square it stands on. Castling
+    /// shows up as the king moving onto the rook, as in the castling moves themselves. Drops aren't included.
+    pub fn mobility(&mut self, board: &BoardState) -> [BitBoard; 64] {
+        let mut mobility = [BitBoard::EMPTY; 64];
+        self.generate_quiet_moves = true;
+        if board.variant_winner().is_some() || board.variant().has_own_legality() {
+            self.generate_moves(board, &mut |mov| if !mov.is_drop() {
+                mobility[mov.src().into_index() as usize].set(mov.dst());
+            }, false);
+            return mobility;
         }
+        self.reset(board);
 
-        for src in ortho_sliders.iter_positions() {
-            let mut move_positions = magic::get_orthogonal_attacks(src, board.all_pieces()) & move_mask;
+        let color = self.color(board);
+        let king_pos = board.king_pos(color);
+        mobility[king_pos.into_index() as usize] = self.king_targets(board) | self.castling_rooks(board);
+        if self.in_double_check {
+            return mobility;
+        }
+        self.visit_piece_targets(board, &mut |src, targets| mobility[src.into_index() as usize] = targets);
 
+        for src in board.pieces_of(color, PieceKind::Pawn).iter_positions() {
+            let mut targets = self.pawn_targets(board, src.bitboard()).into_iter().fold(BitBoard::EMPTY, |acc, (_, targets)| acc | targets);
             if self.pin_rays.get(src) {
-                move_positions &= magic::get_dir_ray_mask(src, board.king_pos(self.color(board)))
+                targets &= magic::get_align_mask(src, king_pos);
             }
+            mobility[src.into_index() as usize] = targets;
+        }
+        if let Some(en_passant_file) = board.en_passant_file() {
+            let dst = Position::new(if color.is_white() { 5 } else { 2 }, en_passant_file);
+            for src in self.en_passant_sources(board).iter_positions() {
+                mobility[src.into_index() as usize].set(dst);
+            }
+        }
+        mobility
+    }
 
-            for dst in move_positions.iter_positions() {
-                push(Move::new(src, dst, moves::MoveKind::Basic));
+    /// Generates the moves of variants whose legality isn't covered by check and pin detection, by filtering
+    /// pseudo-legal moves.
+    fn generate_variant_moves<F: FnMut(Move)>(&mut self, board: &BoardState, push: &mut F) {
+        let color = self.color(board);
+        let mut moves = Vec::with_capacity(MAX_MOVES);
+        self.generate_pseudo_legal_moves(board, &mut |mov| moves.push(mov));
+
+        match board.variant() {
+            Variant::Atomic => {
+                let king_pos = board.king_pos(color);
+                self.in_check = atomic_attacked(board, king_pos, color, board.all_pieces());
+                for mov in moves {
+                    if atomic_is_legal(board, mov) {
+                        push(mov);
+                    }
+                }
+            },
+            Variant::Antichess => {
+                // Captures are compulsory
+                self.in_check = false;
+                let is_capture = |mov: &Move| mov.kind() == MoveKind::EnPassant || board.pieces(!color).get(mov.dst());
+                let any_capture = moves.iter().any(is_capture);
+                for mov in moves {
+                    if !any_capture || is_capture(&mov) {
+                        push(mov);
+                    }
+                }
+            },
+            _ => unreachable!("{} uses the standard legality", board.variant()),
+        }
+    }
+
+    /// Generates every move that follows the movement rules of the pieces, without regard for the king's safety.
+    fn generate_pseudo_legal_moves<F: FnMut(Move)>(&self, board: &BoardState, push: &mut F) {
+        let color = self.color(board);
+        let own = board.pieces(color);
+        let enemy = board.pieces(!color);
+        // Captures only still include the promotions to a queen, as in the legal generator
+        let targets = if self.generate_quiet_moves { !own } else { enemy };
+
+        let mut push_from = |src: Position, attacks: BitBoard| {
+            for dst in (attacks & targets).iter_positions() {
+                push(Move::new(src, dst, MoveKind::Basic));
             }
+        };
+
+        for src in (board.pieces_of(color, PieceKind::Rook) | board.pieces_of(color, PieceKind::Queen)).iter_positions() {
+            push_from(src, magic::get_orthogonal_attacks(src, board.all_pieces()));
+        }
+        for src in (board.pieces_of(color, PieceKind::Bishop) | board.pieces_of(color, PieceKind::Queen)).iter_positions() {
+            push_from(src, magic::get_diagonal_attacks(src, board.all_pieces()));
+        }
+        for src in board.pieces_of(color, PieceKind::Knight).iter_positions() {
+            push_from(src, magic::get_knight_attacks(src));
+        }
+        for src in board.pieces_of(color, PieceKind::King).iter_positions() {
+            push_from(src, magic::get_king_moves(src));
         }
 
-        for src in diag_sliders.iter_positions() {
-            let mut legal_moves = magic::get_diagonal_attacks(src, board.all_pieces()) & move_mask;
+        let (push_offset, start_rank, promotion_rank) = match color {
+            PieceColor::White => (Offset::N, 1, 7),
+            PieceColor::Black => (Offset::S, 6, 0),
+        };
+        let ep_target = board.en_passant_file().map(|file| Position::new(if color.is_white() { 5 } else { 2 }, file));
+        for src in board.pieces_of(color, PieceKind::Pawn).iter_positions() {
+            let mut push_pawn_move = |dst: Position, kind: MoveKind| {
+                if dst.rank() == promotion_rank {
+                    push(Move::new(src, dst, MoveKind::Promotion(PromotionKind::Queen)));
+                    if self.generate_quiet_moves {
+                        push(Move::new(src, dst, MoveKind::Promotion(PromotionKind::Rook)));
+                        push(Move::new(src, dst, MoveKind::Promotion(PromotionKind::Bishop)));
+                        push(Move::new(src, dst, MoveKind::Promotion(PromotionKind::Knight)));
+                        if !board.variant().has_royal_king() {
+                            push(Move::new(src, dst, MoveKind::Promotion(PromotionKind::King)));
+                        }
+                    }
+                } else if self.generate_quiet_moves || kind == MoveKind::EnPassant || enemy.get(dst) {
+                    push(Move::new(src, dst, kind));
+                }
+            };
 
-            if self.pin_rays.get(src) {
-                legal_moves &= magic::get_dir_ray_mask(src, board.king_pos(self.color(board)))
+            let single_push = src + push_offset;
+            if board.get(single_push).is_none() {
+                push_pawn_move(single_push, MoveKind::Basic);
+                let double_push = single_push + push_offset;
+                if src.rank() == start_rank && board.get(double_push).is_none() {
+                    push_pawn_move(double_push, MoveKind::PawnDoublePush);
+                }
+            }
+            for dst in magic::get_pawn_attacks(src, color).iter_positions() {
+                if enemy.get(dst) {
+                    push_pawn_move(dst, MoveKind::Basic);
+                } else if Some(dst) == ep_target {
+                    push_pawn_move(dst, MoveKind::EnPassant);
+                }
             }
+        }
 
-            for dst in legal_moves.iter_positions() {
-                push(Move::new(src, dst, moves::MoveKind::Basic));
+        // Castling, checked for attacks by the variant's own legality test
+        if board.variant().has_castling() && self.generate_quiet_moves {
+            let king_src = board.king_pos(color);
+            for kingside in [true, false] {
+                let kind = board::CastleKind::new(color, kingside);
+                let rook_src = board.castling_rook(kind);
+                if !board.can_castle(kind) || rook_src.rank() != king_src.rank() || board.get(rook_src) != Some(Piece::new(PieceKind::Rook, color)) {
+                    continue;
+                }
+                let castle = Move::new(king_src, rook_src, MoveKind::Castle);
+                let (king_dst, rook_dst) = castle.castle_destinations();
+                let mut blockers = board.all_pieces();
+                blockers.clear(king_src);
+                blockers.clear(rook_src);
+                if ((rank_span(king_src, king_dst) | rank_span(rook_src, rook_dst)) & blockers).is_empty() {
+                    push(castle);
+                }
             }
         }
     }
 
-    fn generate_knight_moves<F: FnMut(Move)>(&self, board: &Board, push: &mut F) {
-        let move_mask = !board.pieces(self.color(board)) & self.check_ray_mask & self.move_type_mask;
-        let knights = board.pieces_of(self.color(board), PieceKind::Knight) & !self.pin_rays;
+    /// Squares the king can step to, not counting castling.
+    fn king_targets(&self, board: &BoardState) -> BitBoard {
+        let legal_mask = !(self.enemy_attack_map_no_pawns | self.enemy_pawn_attack_map | board.pieces(self.color(board)));
+        magic::get_king_moves(board.king_pos(self.color(board))) & legal_mask & self.move_type_mask
+    }
 
-        for src in knights.iter_positions() {
-            let move_positions = magic::get_knight_attacks(src) & move_mask;
+    /// Rooks the king can castle with, following the Chess960 rules which include standard castling.
+    fn castling_rooks(&self, board: &BoardState) -> BitBoard {
+        let mut rooks = BitBoard::EMPTY;
+        if self.in_check || !self.generate_quiet_moves {
+            return rooks;
+        }
+        let king_src = board.king_pos(self.color(board));
+        let attacked = self.enemy_attack_map_no_pawns | self.enemy_pawn_attack_map;
+        let enemy_ortho_sliders = board.pieces_of(!self.color(board), PieceKind::Rook) | board.pieces_of(!self.color(board), PieceKind::Queen);
+
+        for kingside in [true, false] {
+            let kind = board::CastleKind::new(self.color(board), kingside);
+            let rook_src = board.castling_rook(kind);
+            if !board.can_castle(kind) || rook_src.rank() != king_src.rank() || board.get(rook_src) != Some(Piece::new(PieceKind::Rook, self.color(board))) {
+                continue;
+            }
 
-            for dst in move_positions.iter_positions() {
-                push(Move::new(src, dst, moves::MoveKind::Basic));
+            let castle = Move::new(king_src, rook_src, moves::MoveKind::Castle);
+            let (king_dst, rook_dst) = castle.castle_destinations();
+
+            // Apart from the king and rook themselves, nothing may stand on the squares either of them crosses
+            let mut blockers = board.all_pieces();
+            blockers.clear(king_src);
+            blockers.clear(rook_src);
+            let king_path = rank_span(king_src, king_dst);
+            if !((king_path | rank_span(rook_src, rook_dst)) & blockers).is_empty() || !(king_path & attacked).is_empty() {
+                continue;
+            }
+
+            // The rook may have been shielding the king's destination from a slider further along the rank
+            blockers.set(rook_dst);
+            if !(magic::get_orthogonal_attacks(king_dst, blockers) & enemy_ortho_sliders).is_empty() {
+                continue;
             }
+
+            rooks.set(rook_src);
         }
+        rooks
     }
 
-    fn generate_pawn_moves<F: FnMut(Move)>(&self, board: &Board, push: &mut F) {
+    fn generate_king_moves<F: FnMut(Move)>(&self, board: &BoardState, push: &mut F) {
         let king_pos = board.king_pos(self.color(board));
+        for dst in self.king_targets(board).iter_positions() {
+            push(Move::new(king_pos, dst, moves::MoveKind::Basic));
+        }
+        let castling_rooks = self.castling_rooks(board);
+        if !castling_rooks.is_empty() {
+            // Kingside first, so that the order doesn't depend on where the rooks stand
+            for kingside in [true, false] {
+                let rook_src = board.castling_rook(board::CastleKind::new(self.color(board), kingside));
+                if castling_rooks.get(rook_src) {
+                    push(Move::new(king_pos, rook_src, moves::MoveKind::Castle));
+                }
+            }
+        }
+    }
 
-        let pawns = board.pieces_of(self.color(board), PieceKind::Pawn);
-
-        let push_offset = match self.color(board) {
-            PieceColor::White => Offset::N,
-            PieceColor::Black => Offset::S,
+    /// Calls `visit` with every knight, bishop, rook and queen of the side to move that can move, along with the
+    /// squares it can legally move to.
+    fn visit_piece_targets<F: FnMut(Position, BitBoard)>(&self, board: &BoardState, visit: &mut F) {
+        let color = self.color(board);
+        let king_pos = board.king_pos(color);
+        let move_mask = !board.pieces(color) & self.check_ray_mask & self.move_type_mask;
+        // A pinned piece can only move along its pin, which never resolves a check
+        let unpinned = if self.in_check { !self.pin_rays } else { BitBoard::FULL };
+        let along_pin = |src: Position, targets: BitBoard| {
+            if self.pin_rays.get(src) { targets & magic::get_align_mask(src, king_pos) } else { targets }
         };
 
-        let double_push_offset = push_offset * 2;
+        for src in (board.pieces_of(color, PieceKind::Knight) & !self.pin_rays).iter_positions() {
+            visit(src, magic::get_knight_attacks(src) & move_mask);
+        }
+        for src in (board.pieces_of(color, PieceKind::Bishop) & unpinned).iter_positions() {
+            visit(src, along_pin(src, magic::get_diagonal_attacks(src, board.all_pieces()) & move_mask));
+        }
+        for src in (board.pieces_of(color, PieceKind::Rook) & unpinned).iter_positions() {
+            visit(src, along_pin(src, magic::get_orthogonal_attacks(src, board.all_pieces()) & move_mask));
+        }
+        for src in (board.pieces_of(color, PieceKind::Queen) & unpinned).iter_positions() {
+            let attacks = magic::get_orthogonal_attacks(src, board.all_pieces()) | magic::get_diagonal_attacks(src, board.all_pieces());
+            visit(src, along_pin(src, attacks & move_mask));
+        }
+    }
 
-        let capture_w_offset = match self.color(board) {
-            PieceColor::White => Offset::NW,
-            PieceColor::Black => Offset::SW,
-        };
+    fn generate_piece_moves<F: FnMut(Move)>(&self, board: &BoardState, push: &mut F) {
+        self.visit_piece_targets(board, &mut |src, targets| {
+            for dst in targets.iter_positions() {
+                push(Move::new(src, dst, moves::MoveKind::Basic));
+            }
+        });
+    }
 
-        let capture_e_offset = match self.color(board) {
-            PieceColor::White => Offset::NE,
-            PieceColor::Black => Offset::SE,
+    /// Destinations of the single pushes, double pushes and captures towards either side of `pawns` that stay out
+    /// of check, each along with the offset from the source square. Pins and en passant aren't accounted for.
+    fn pawn_targets(&self, board: &BoardState, pawns: BitBoard) -> [(Offset, BitBoard); 4] {
+        let (push_offset, capture_w_offset, capture_e_offset, double_push_rank_mask) = match self.color(board) {
+            PieceColor::White => (Offset::N, Offset::NW, Offset::NE, BitBoard::RANK_4),
+            PieceColor::Black => (Offset::S, Offset::SW, Offset::SE, BitBoard::RANK_5),
         };
+        let empty = !board.all_pieces();
+        let enemy = board.pieces(!self.color(board));
+
+        let single_push = pawns.shift(push_offset) & empty;
+        let double_push = single_push.shift(push_offset) & empty & double_push_rank_mask;
+        let capture_w = (pawns & !BitBoard::FILE_A).shift(capture_w_offset) & enemy;
+        let capture_e = (pawns & !BitBoard::FILE_H).shift(capture_e_offset) & enemy;
+
+        [
+            (push_offset, single_push & self.check_ray_mask),
+            (push_offset * 2, double_push & self.check_ray_mask),
+            (capture_w_offset, capture_w & self.check_ray_mask),
+            (capture_e_offset, capture_e & self.check_ray_mask),
+        ]
+    }
 
-        let promotion_rank_mask = match self.color(board) {
-            PieceColor::White => BitBoard::RANK_8,
-            PieceColor::Black => BitBoard::RANK_1,
+    /// Pawns that can legally capture en passant.
+    fn en_passant_sources(&self, board: &BoardState) -> BitBoard {
+        let mut sources = BitBoard::EMPTY;
+        let Some(en_passant_file) = board.en_passant_file() else {
+            return sources;
         };
-
-        let double_push_rank_mask = match self.color(board) {
-            PieceColor::White => BitBoard::RANK_4,
-            PieceColor::Black => BitBoard::RANK_5,
+        let king_pos = board.king_pos(self.color(board));
+        let (dst, en_passant_capture) = match self.color(board) {
+            PieceColor::White => (Position::new(5, en_passant_file), Position::new(4, en_passant_file)),
+            PieceColor::Black => (Position::new(2, en_passant_file), Position::new(3, en_passant_file)),
         };
 
-        let single_push = pawns.shift(push_offset) & !board.all_pieces();
-        let double_push = single_push.shift(push_offset) & !board.all_pieces() & double_push_rank_mask & self.check_ray_mask;
-
-        let single_push_promotions = single_push & promotion_rank_mask & self.check_ray_mask;
-        let single_push_no_promotions = single_push & !promotion_rank_mask & self.check_ray_mask;
+        if self.check_ray_mask.get(en_passant_capture) || self.check_ray_mask.get(dst) {
+            let pawns = board.pieces_of(self.color(board), PieceKind::Pawn) & magic::get_pawn_attacks(dst, !self.color(board));
 
-        let capture_w = (pawns & !BitBoard::FILE_A).shift(capture_w_offset) & board.pieces(!self.color(board));
-        let capture_e = (pawns & !BitBoard::FILE_H).shift(capture_e_offset) & board.pieces(!self.color(board));
-
-        let capture_w_promotions = capture_w & promotion_rank_mask & self.check_ray_mask;
-        let capture_e_promotions = capture_e & promotion_rank_mask & self.check_ray_mask;
+            for src in pawns.iter_positions() {
+                let along_pin = !self.pin_rays.get(src) || magic::get_align_mask(src, king_pos) == magic::get_align_mask(dst, king_pos);
+                if along_pin && !self.in_check_after_en_passant(src, dst, en_passant_capture, board) {
+                    sources.set(src);
+                }
+            }
+        }
+        sources
+    }
 
-        let capture_w_no_promotions = capture_w & !promotion_rank_mask & self.check_ray_mask;
-        let capture_e_no_promotions = capture_e & !promotion_rank_mask & self.check_ray_mask;
+    fn generate_pawn_moves<F: FnMut(Move)>(&self, board: &BoardState, push: &mut F) {
+        let king_pos = board.king_pos(self.color(board));
+        let pawns = board.pieces_of(self.color(board), PieceKind::Pawn);
+        let promotion_rank_mask = match self.color(board) {
+            PieceColor::White => BitBoard::RANK_8,
+            PieceColor::Black => BitBoard::RANK_1,
+        };
+        let [(push_offset, single_push), (double_push_offset, double_push), (capture_w_offset, capture_w), (capture_e_offset, capture_e)] = self.pawn_targets(board, pawns);
 
         if self.generate_quiet_moves {
-            for dst in single_push_no_promotions.iter_positions() {
+            for dst in (single_push & !promotion_rank_mask).iter_positions() {
                 let src = dst - push_offset;
                 if !self.pin_rays.get(src) || magic::get_align_mask(src, king_pos) == magic::get_align_mask(dst, king_pos) {
                     push(Move::new(src, dst, moves::MoveKind::Basic));
@@ -355,60 +984,28 @@ impl MoveGenerator {
             }
         }
 
-        for dst in capture_w_no_promotions.iter_positions() {
-            let src = dst - capture_w_offset;
-            if !self.pin_rays.get(src) || magic::get_align_mask(src, king_pos) == magic::get_align_mask(dst, king_pos) {
-                push(Move::new(src, dst, moves::MoveKind::Basic));
-            }
-        }
-
-        for dst in capture_e_no_promotions.iter_positions() {
-            let src = dst - capture_e_offset;
-            if !self.pin_rays.get(src) || magic::get_align_mask(src, king_pos) == magic::get_align_mask(dst, king_pos) {
-                push(Move::new(src, dst, moves::MoveKind::Basic));
-            }
-        }
-
-        for dst in single_push_promotions.iter_positions() {
-            let src = dst - push_offset;
-            if !self.pin_rays.get(src) || magic::get_align_mask(src, king_pos) == magic::get_align_mask(dst, king_pos) {
-                self.generate_promotions(src, dst, push);
-            }
-        }
-
-        for dst in capture_w_promotions.iter_positions() {
-            let src = dst - capture_w_offset;
-            if !self.pin_rays.get(src) || magic::get_align_mask(src, king_pos) == magic::get_align_mask(dst, king_pos) {
-                self.generate_promotions(src, dst, push);
+        for (offset, captures) in [(capture_w_offset, capture_w), (capture_e_offset, capture_e)] {
+            for dst in (captures & !promotion_rank_mask).iter_positions() {
+                let src = dst - offset;
+                if !self.pin_rays.get(src) || magic::get_align_mask(src, king_pos) == magic::get_align_mask(dst, king_pos) {
+                    push(Move::new(src, dst, moves::MoveKind::Basic));
+                }
             }
         }
 
-        for dst in capture_e_promotions.iter_positions() {
-            let src = dst - capture_e_offset;
-            if !self.pin_rays.get(src) || magic::get_align_mask(src, king_pos) == magic::get_align_mask(dst, king_pos) {
-                self.generate_promotions(src, dst, push);
+        for (offset, promotions) in [(push_offset, single_push), (capture_w_offset, capture_w), (capture_e_offset, capture_e)] {
+            for dst in (promotions & promotion_rank_mask).iter_positions() {
+                let src = dst - offset;
+                if !self.pin_rays.get(src) || magic::get_align_mask(src, king_pos) == magic::get_align_mask(dst, king_pos) {
+                    self.generate_promotions(src, dst, push);
+                }
             }
         }
 
         if let Some(en_passant_file) = board.en_passant_file() {
-            let en_passant_rank = match self.color(board) {
-                PieceColor::White => 5,
-                PieceColor::Black => 2,
-            };
-
-            let dst = Position::new(en_passant_rank, en_passant_file);
-            let en_passant_capture = dst - push_offset;
-
-            if self.check_ray_mask.get(en_passant_capture) {
-                let pawns = pawns & magic::get_pawn_attacks(dst, !self.color(board));
-
-                for src in pawns.iter_positions() {
-                    if !self.pin_rays.get(src) || magic::get_align_mask(src, king_pos) == magic::get_align_mask(dst, king_pos) {
-                        if !self.in_check_after_en_passant(src, dst, en_passant_capture, board) {
-                            push(Move::new(src, dst, moves::MoveKind::EnPassant));
-                        }
-                    }
-                }
+            let dst = Position::new(if self.color(board).is_white() { 5 } else { 2 }, en_passant_file);
+            for src in self.en_passant_sources(board).iter_positions() {
+                push(Move::new(src, dst, moves::MoveKind::EnPassant));
             }
         }
     }
@@ -423,25 +1020,27 @@ impl MoveGenerator {
         }
     }
 
-    fn in_check_after_en_passant(&self, src: Position, dst: Position, en_passant_capture: Position, board: &Board) -> bool {
+    fn in_check_after_en_passant(&self, src: Position, dst: Position, en_passant_capture: Position, board: &BoardState) -> bool {
         let ortho_attackers = board.pieces_of(!self.color(board), PieceKind::Rook) | board.pieces_of(!self.color(board), PieceKind::Queen);
 
         if !ortho_attackers.is_empty() {
             let masked_blockers = {
                 let mut mask = board.all_pieces();
                 mask.clear(src);
-                mask.clear(dst);
+                mask.set(dst);
                 mask.clear(en_passant_capture);
                 mask
             };
             let ortho_attacks = magic::get_orthogonal_attacks(board.king_pos(self.color(board)), masked_blockers);
-            !(ortho_attackers & ortho_attackers).is_empty()
+            !(ortho_attacks & ortho_attackers).is_empty()
         } else { false }
     }
 }
 
+#[cfg(test)]
 mod movegen_test {
-    use crate::chess::{Board, Move, MoveGenerator, MoveKind, PromotionKind};
+    use crate::chess::{bitboards::BitBoard, Board, Game, GameOutcome, Move, MoveGenerator, Variant};
+    use super::MoveStage;
 
     #[test]
     fn move_count_test() {
@@ -451,6 +1050,220 @@ mod movegen_test {
         println!("Move count: {}", count_moves(&mut board, &mut move_gen, &mut moves, 2, false, true));
     }
 
+    fn perft(board: &mut Board, move_gen: &mut MoveGenerator, depth: usize) -> usize {
+        if depth == 1 {
+            return move_gen.count_moves(board);
+        }
+        let mut moves = Vec::new();
+        move_gen.generate_moves(board, &mut |mov| moves.push(mov), false);
+        let mut count = 0;
+        for mov in moves {
+            board.make_move(mov, true);
+            count += perft(board, move_gen, depth - 1);
+            board.unmake_move(mov, true);
+        }
+        count
+    }
+
+    #[test]
+    fn perft_test() {
+        // Reference values from https://www.chessprogramming.org/Perft_Results
+        const POSITIONS: [(&str, &[usize]); 6] = [
+            ("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197281]),
+            ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &[48, 2039, 97862]),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &[14, 191, 2812, 43238]),
+            ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", &[6, 264, 9467]),
+            ("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8", &[44, 1486, 62379]),
+            ("r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10", &[46, 2079, 89890]),
+        ];
+
+        let mut move_gen = MoveGenerator::new();
+        for (fen, counts) in POSITIONS {
+            let mut board = Board::from_fen(fen).unwrap();
+            for (depth, count) in counts.iter().enumerate() {
+                assert_eq!(perft(&mut board, &mut move_gen, depth + 1), *count, "{fen} at depth {}", depth + 1);
+            }
+        }
+    }
+
+    #[test]
+    fn chess960_perft_test() {
+        // Reference values from https://www.chessprogramming.org/Chess960_Perft_Results
+        const POSITIONS: [(&str, &[usize]); 5] = [
+            ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &[21, 528, 12189, 326672]),
+            ("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9", &[21, 807, 18002]),
+            ("b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9", &[20, 479, 10471]),
+            ("qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9", &[22, 593, 13440]),
+            ("1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9", &[28, 1120, 31058]),
+        ];
+
+        let mut move_gen = MoveGenerator::new();
+        for (fen, counts) in POSITIONS {
+            let mut board = Board::from_fen(fen).unwrap();
+            assert!(board.is_chess960());
+            for (depth, count) in counts.iter().enumerate() {
+                assert_eq!(perft(&mut board, &mut move_gen, depth + 1), *count, "{fen} at depth {}", depth + 1);
+            }
+            assert_eq!(board.to_shredder_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn variant_perft_test() {
+        const POSITIONS: [(Variant, &str, &[usize]); 12] = [
+            (Variant::KingOfTheHill, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197281]),
+            // A king one step from the centre wins there, so the tree is smaller than in standard chess, where
+            // these count 8, 40, 320, 2131 and 8, 47, 339, 2277
+            (Variant::KingOfTheHill, "4k3/8/8/8/8/3K4/8/8 w - - 0 1", &[8, 30, 240, 1458]),
+            (Variant::KingOfTheHill, "8/8/8/2k5/8/8/3P4/3K4 b - - 0 1", &[8, 36, 267, 1616]),
+            (Variant::ThreeCheck, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1", &[20, 400, 8902, 197281]),
+            // The side on its third check wins by checking, standard counts are 14, 63, 1149, 6786 and 21, 73, 1851, 8927
+            (Variant::ThreeCheck, "4k3/8/8/8/8/8/8/4K2R w - - 1+3 0 1", &[14, 60, 1092, 5965]),
+            (Variant::ThreeCheck, "3qk3/8/8/8/8/8/8/4K3 b - - 3+1 0 1", &[21, 57, 1475, 5765]),
+            (Variant::Atomic, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902, 197326]),
+            (Variant::Antichess, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1", &[20, 400, 8067, 153299]),
+            (Variant::Antichess, "8/1p6/8/8/8/8/P7/8 w - - 0 1", &[2, 4, 4, 3, 1, 0]),
+            (Variant::Crazyhouse, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1", &[20, 400, 8902, 197281]),
+            (Variant::Crazyhouse, "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1", &[301, 75353]),
+            (Variant::Standard, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &[20, 400, 8902]),
+        ];
+
+        let mut move_gen = MoveGenerator::new();
+        for (variant, fen, counts) in POSITIONS {
+            let mut board = Board::from_variant_fen(variant, fen).unwrap();
+            for (depth, count) in counts.iter().enumerate() {
+                assert_eq!(perft(&mut board, &mut move_gen, depth + 1), *count, "{variant} {fen} at depth {}", depth + 1);
+            }
+            assert_eq!(board.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn variant_win_test() {
+        // Positions one move from a variant win, with the number of legal moves and the moves that win
+        const POSITIONS: [(Variant, &str, usize, &[&str]); 4] = [
+            (Variant::KingOfTheHill, "4k3/8/8/8/8/3K4/8/8 w - - 0 1", 8, &["d3d4", "d3e4"]),
+            // The rook guards both hill squares in reach
+            (Variant::KingOfTheHill, "4k3/8/8/8/2r5/3K4/8/8 w - - 0 1", 4, &[]),
+            (Variant::ThreeCheck, "4k3/8/8/8/8/8/8/4K2R w - - 1+3 0 1", 14, &["h1h8"]),
+            (Variant::ThreeCheck, "3qk3/8/8/8/8/8/8/4K3 b - - 3+1 0 1", 21, &["d8d1", "d8d2", "d8a5", "d8h4", "d8e7"]),
+        ];
+
+        for (variant, fen, count, wins) in POSITIONS {
+            let board = Board::from_variant_fen(variant, fen).unwrap();
+            assert_eq!(board.variant_winner(), None, "{fen}");
+            let moves = board.legal_moves();
+            assert_eq!(moves.len(), count, "{fen}");
+
+            let mut winning = Vec::new();
+            for mov in moves {
+                let mut state = *board.state();
+                state.make_move(mov);
+                if let Some(winner) = state.variant_winner() {
+                    assert_eq!(winner, board.side_to_move(), "{fen} after {mov}");
+                    winning.push(mov.to_string());
+                }
+            }
+            winning.sort();
+            let mut expected = wins.iter().map(|mov| mov.to_string()).collect::<Vec<_>>();
+            expected.sort();
+            assert_eq!(winning, expected, "{fen}");
+
+            // The win ends the game
+            if let Some(mov) = wins.first() {
+                let mut game = Game::from_board(board.clone());
+                game.make_move(Move::parse(mov, &board).unwrap(), None);
+                assert_eq!(game.outcome(), Some(GameOutcome::VariantWin { winner: board.side_to_move(), variant }), "{fen}");
+            }
+        }
+    }
+
+    #[test]
+    fn bulk_count_test() {
+        const POSITIONS: [(Variant, &str); 5] = [
+            (Variant::Standard, "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"),
+            (Variant::Standard, "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"),
+            (Variant::Standard, "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9"),
+            (Variant::Crazyhouse, "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1"),
+            (Variant::Atomic, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        ];
+
+        let mut move_gen = MoveGenerator::new();
+        for (variant, fen) in POSITIONS {
+            let mut board = Board::from_variant_fen(variant, fen).unwrap();
+            let mut moves = Vec::new();
+            move_gen.generate_moves(&board, &mut |mov| moves.push(mov), false);
+            // Check the counts in the positions one move further as well, which include checks and pins
+            for mov in moves {
+                board.make_move(mov, true);
+                let mut replies = Vec::new();
+                move_gen.generate_moves(&board, &mut |mov| replies.push(mov), false);
+                assert_eq!(move_gen.count_moves(&board), replies.len(), "{} after {mov}", board.to_fen());
+
+                let mut mobility = [BitBoard::EMPTY; 64];
+                for reply in replies.iter().filter(|reply| !reply.is_drop()) {
+                    mobility[reply.src().into_index() as usize].set(reply.dst());
+                }
+                assert_eq!(move_gen.mobility(&board), mobility, "{} after {mov}", board.to_fen());
+                board.unmake_move(mov, true);
+            }
+        }
+    }
+
+    #[test]
+    fn stage_test() {
+        const POSITIONS: [(Variant, &str); 11] = [
+            (Variant::Standard, "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"),
+            (Variant::Standard, "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1"),
+            (Variant::Standard, "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"),
+            (Variant::Standard, "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8"),
+            // In check from a pawn that can be taken en passant, from two pieces, and with a pinned piece that could
+            // otherwise block
+            (Variant::Standard, "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1"),
+            (Variant::Standard, "4k3/8/8/8/8/5n2/8/4K2r w - - 0 1"),
+            (Variant::Standard, "4k3/4r3/8/8/1b6/8/3N4/4K3 w - - 0 1"),
+            (Variant::Crazyhouse, "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1"),
+            (Variant::Crazyhouse, "4k3/8/8/8/8/8/8/4R1K1[Nn] b - - 0 1"),
+            (Variant::ThreeCheck, "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 3+3 0 1"),
+            (Variant::Atomic, "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        ];
+
+        let mut move_gen = MoveGenerator::new();
+        let mut stage = |board: &Board, stage: MoveStage| {
+            let mut moves = Vec::new();
+            move_gen.generate_stage(board, &mut |mov| moves.push(mov), stage);
+            moves.sort_unstable();
+            moves
+        };
+        for (variant, fen) in POSITIONS {
+            let mut board = Board::from_variant_fen(variant, fen).unwrap();
+            let mut positions = vec![board.clone()];
+            for mov in stage(&board, MoveStage::All) {
+                board.make_move(mov, true);
+                positions.push(board.clone());
+                board.unmake_move(mov, true);
+            }
+
+            for board in positions {
+                let all = stage(&board, MoveStage::All);
+                let evasions = stage(&board, MoveStage::Evasions);
+                assert_eq!(evasions, if board.in_check(board.side_to_move()) { all.clone() } else { Vec::new() }, "{}", board.to_fen());
+
+                let checks = stage(&board, MoveStage::QuietChecks);
+                let mut partition = [stage(&board, MoveStage::Captures), checks.clone(), stage(&board, MoveStage::QuietNonChecks)].concat();
+                partition.sort_unstable();
+                assert_eq!(partition, all, "{}", board.to_fen());
+
+                for mov in all {
+                    let mut after = board.clone();
+                    after.make_move(mov, true);
+                    assert_eq!(mov.gives_check(&board), after.in_check(after.side_to_move()), "{} {mov}", board.to_fen());
+                }
+                assert!(checks.iter().all(|mov| mov.gives_check(&board)));
+            }
+        }
+    }
+
     fn count_moves(board: &mut Board, move_gen: &mut MoveGenerator, moves: &mut Vec<Move>, depth: usize, is_promotion: bool, is_outermost: bool) -> usize {
         if depth == 0 {
             if is_promotion {
@@ -509,9 +1322,25 @@ impl MoveTree {
 
     pub fn push(&mut self, value: Move) {
         self.moves.push(value);
-        self.src.set(value.src());
-        self.dst[value.src().into_index() as usize].get_or_insert(BitBoard::EMPTY).set(value.dst());
-        self.kind[value.src().into_index() as usize][value.dst().into_index() as usize].replace(value.kind());
+        // Drops have no source square to select them by on the board
+        if value.is_drop() {
+            return;
+        }
+        self.insert(value.src(), value.dst(), value.kind());
+
+        // Castling can also be entered by moving the king to its destination, unless that is ambiguous
+        if value.kind() == MoveKind::Castle {
+            let (king_dst, _) = value.castle_destinations();
+            if king_dst != value.src() && self.kind[value.src().into_index() as usize][king_dst.into_index() as usize].is_none() {
+                self.insert(value.src(), king_dst, MoveKind::Castle);
+            }
+        }
+    }
+
+    fn insert(&mut self, src: Position, dst: Position, kind: MoveKind) {
+        self.src.set(src);
+        self.dst[src.into_index() as usize].get_or_insert(BitBoard::EMPTY).set(dst);
+        self.kind[src.into_index() as usize][dst.into_index() as usize].replace(kind);
     }
 
     pub fn clear(&mut self) {
@@ -536,6 +1365,10 @@ impl MoveTree {
     pub fn get(&self, src: Position, dst: Position, promotion: Option<PromotionKind>) -> Option<Move> {
         if self.has_dst(src, dst) {
             let kind = self.kind[src.into_index() as usize][dst.into_index() as usize].unwrap();
+            if kind == MoveKind::Castle {
+                let mut castles = self.moves.iter().copied().filter(|mov| mov.kind() == MoveKind::Castle && mov.src() == src);
+                return castles.clone().find(|mov| mov.dst() == dst).or_else(|| castles.find(|mov| mov.castle_destinations().0 == dst));
+            }
             Some(Move::new(src, dst, if kind.is_promotion() {
                 MoveKind::Promotion(promotion.expect("Promotion must be provided when move is a promotion."))
             } else { kind }))
@@ -14,6 +14,47 @@ pub enum IllegalReason {
     NoMove,
     /// An empty slot tried to move.
     NoPiece,
+    /// The piece belongs to the player not in turn.
+    NotYourTurn,
+    /// The piece is pinned to its king and can't leave the line of the pin.
+    Pinned,
+    /// The move would leave the own king in check.
+    IntoCheck,
+    /// The king tried to castle through or into an attacked square.
+    CastlingThroughCheck,
+    /// The king or the rook moved before, so castling on that side isn't allowed anymore.
+    NoCastlingRights,
+    /// Another piece is in the way.
+    Blocked,
+    /// The promotion piece is missing, or given for a move that isn't a promotion.
+    InvalidPromotion,
+    /// A capture is available and has to be played (Antichess).
+    CaptureRequired,
+    /// The game ended without a checkmate, e.g. by stalemate, a variant rule or the fifty-move rule, which is
+    /// applied without waiting for a claim.
+    GameOver,
+}
+
+impl std::fmt::Display for IllegalReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            IllegalReason::InvalidMove => "The piece can't move like that",
+            IllegalReason::SameColor => "You can't capture your own piece",
+            IllegalReason::InCheck => "Your king is in check",
+            IllegalReason::InCheckmate => "You are checkmated",
+            IllegalReason::NoMove => "The piece has to move",
+            IllegalReason::NoPiece => "There is no piece there",
+            IllegalReason::NotYourTurn => "It's not your turn",
+            IllegalReason::Pinned => "The piece is pinned to your king",
+            IllegalReason::IntoCheck => "Your king would be in check",
+            IllegalReason::CastlingThroughCheck => "You can't castle through check",
+            IllegalReason::NoCastlingRights => "You can't castle anymore on that side",
+            IllegalReason::Blocked => "Another piece is in the way",
+            IllegalReason::InvalidPromotion => "Invalid promotion",
+            IllegalReason::CaptureRequired => "You have to capture",
+            IllegalReason::GameOver => "The game is over",
+        })
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -23,6 +64,8 @@ pub enum MoveKind {
     Castle,
     PawnDoublePush,
     Promotion(PromotionKind),
+    /// A piece from the mover's pocket is put on the board (Crazyhouse).
+    Drop(PieceKind),
 }
 
 impl MoveKind {
@@ -39,12 +82,19 @@ impl MoveKind {
             _ => None
         }
     }
+
+    pub fn drop_kind(&self) -> Option<PieceKind> {
+        match self {
+            Self::Drop(kind) => Some(*kind),
+            _ => None
+        }
+    }
 }
 
 impl super::IntoPacked for MoveKind {
     type Packed = u8;
 
-    const MASK: Self::Packed = 0b111;
+    const MASK: Self::Packed = 0b1111;
 
     fn into_packed(self) -> Self::Packed {
         match self {
@@ -58,6 +108,15 @@ impl super::IntoPacked for MoveKind {
                 PromotionKind::Knight => 0b101,
                 PromotionKind::Rook => 0b110,
                 PromotionKind::Bishop => 0b111,
+                PromotionKind::King => 0b1000,
+            },
+            MoveKind::Drop(piece_kind) => match piece_kind {
+                PieceKind::Pawn => 0b1001,
+                PieceKind::Knight => 0b1010,
+                PieceKind::Bishop => 0b1011,
+                PieceKind::Rook => 0b1100,
+                PieceKind::Queen => 0b1101,
+                PieceKind::King => unreachable!("Kings can't be dropped"),
             },
         }
     }
@@ -75,6 +134,12 @@ impl super::FromPacked for MoveKind {
             0b101 => MoveKind::Promotion(PromotionKind::Knight),
             0b110 => MoveKind::Promotion(PromotionKind::Rook),
             0b111 => MoveKind::Promotion(PromotionKind::Bishop),
+            0b1000 => MoveKind::Promotion(PromotionKind::King),
+            0b1001 => MoveKind::Drop(PieceKind::Pawn),
+            0b1010 => MoveKind::Drop(PieceKind::Knight),
+            0b1011 => MoveKind::Drop(PieceKind::Bishop),
+            0b1100 => MoveKind::Drop(PieceKind::Rook),
+            0b1101 => MoveKind::Drop(PieceKind::Queen),
             _ => unreachable!()
         }
     }
@@ -86,9 +151,13 @@ pub enum PromotionKind {
     Rook,
     Bishop,
     Knight,
+    /// Only possible in Antichess, where the king is an ordinary piece.
+    King,
 }
 
 
+/// A move packed into 16 bits: 6 bits each for the source and destination square, then 4 bits of [`MoveKind`].
+/// Drops use the destination as their source.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Move(u16);
 
@@ -97,6 +166,10 @@ impl Move {
         Self((src.into_packed() as u16) | ((dst.into_packed() as u16) << 6) | ((kind.into_packed() as u16) << 12))
     }
 
+    pub(super) fn new_drop(dst: position::Position, kind: PieceKind) -> Self {
+        Self::new(dst, dst, MoveKind::Drop(kind))
+    }
+
     #[inline]
     pub fn src(&self) -> position::Position {
         position::Position::from_packed((self.0 & 0x3F) as u8)
@@ -109,14 +182,20 @@ impl Move {
 
     #[inline]
     pub fn kind(&self) -> MoveKind {
-        MoveKind::from_packed(((self.0 >> 12) & 0x7) as u8)
+        MoveKind::from_packed(((self.0 >> 12) & 0xF) as u8)
     }
 
-    pub fn piece(&self, board: &Board) -> Piece {
-        board.get(self.src()).unwrap()
+    pub fn piece(&self, board: &BoardState) -> Piece {
+        match self.drop_kind() {
+            Some(kind) => Piece::new(kind, board.side_to_move()),
+            None => board.get(self.src()).unwrap(),
+        }
     }
 
-    pub fn capture(&self, board: &Board) -> Option<Piece> {
+    pub fn capture(&self, board: &BoardState) -> Option<Piece> {
+        if self.kind() == MoveKind::Castle || self.is_drop() {
+            return None;
+        }
         board.get(if self.kind() == MoveKind::EnPassant {
             let dst = self.dst();
             Position::new(match dst.rank() {
@@ -133,20 +212,60 @@ impl Move {
         self.kind().is_promotion()
     }
 
+    /// Squares the king and the rook end up on after this castling move. Castling moves are stored as the king
+    /// capturing its own rook, so that they stay unambiguous in Chess960.
+    pub fn castle_destinations(&self) -> (position::Position, position::Position) {
+        let (src, dst) = (self.src(), self.dst());
+        if dst.file() > src.file() {
+            (position::Position::new(src.rank(), 6), position::Position::new(src.rank(), 5))
+        } else {
+            (position::Position::new(src.rank(), 2), position::Position::new(src.rank(), 3))
+        }
+    }
+
     pub fn promotion_kind(&self) -> Option<PromotionKind> {
         self.kind().promotion_kind()
     }
+
+    pub fn is_drop(&self) -> bool {
+        self.drop_kind().is_some()
+    }
+
+    pub fn drop_kind(&self) -> Option<PieceKind> {
+        self.kind().drop_kind()
+    }
+
+    /// Whether this legal move puts the opponent in check, directly or by uncovering an attack of another piece. To
+    /// test many moves of the same position, build a [`move_gen::CheckInfo`] once instead.
+    pub fn gives_check(&self, board: &BoardState) -> bool {
+        match move_gen::CheckInfo::new(board) {
+            Some(check_info) => check_info.gives_check(board, *self),
+            // Variants with their own rules have to play the move to find out
+            None => {
+                let mut after = *board;
+                after.make_move(*self);
+                after.in_check(after.side_to_move())
+            },
+        }
+    }
 }
 
 impl std::fmt::Display for Move {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}{}", self.src(), self.dst())?;
+        if let Some(kind) = self.drop_kind() {
+            return write!(f, "{}@{}", Piece::new(kind, PieceColor::White), self.dst());
+        }
+
+        // Castling is shown with the king's own destination, as in standard UCI notation
+        let dst = if self.kind() == MoveKind::Castle { self.castle_destinations().0 } else { self.dst() };
+        write!(f, "{}{}", self.src(), dst)?;
         if let Some(kind) = self.promotion_kind() {
             write!(f, "{}", match kind {
                 PromotionKind::Queen => 'q',
                 PromotionKind::Knight => 'n',
                 PromotionKind::Rook => 'r',
                 PromotionKind::Bishop => 'b',
+                PromotionKind::King => 'k',
             })?;
         }
         Ok(())
@@ -161,7 +280,7 @@ impl PartialOrd for Move {
 
 impl Ord for Move {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.src().cmp(&other.src()).then(self.dst().cmp(&other.dst())).then(self.promotion_kind().cmp(&other.promotion_kind()))
+        self.src().cmp(&other.src()).then(self.dst().cmp(&other.dst())).then(self.promotion_kind().cmp(&other.promotion_kind())).then(self.drop_kind().cmp(&other.drop_kind()))
     }
 }
 
@@ -195,6 +314,8 @@ impl MoveKey {
             PromotionKind::Knight => 0x2000,
             PromotionKind::Rook => 0x4000,
             PromotionKind::Bishop => 0x8000,
+            // There is no room left for Antichess king promotions
+            PromotionKind::King => 0,
         }
     }
 
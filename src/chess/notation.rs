@@ -0,0 +1,179 @@
+use super::*;
+
+impl PieceKind {
+    pub const fn san_char(&self) -> Option<char> {
+        match self {
+            PieceKind::Pawn => None,
+            PieceKind::Knight => Some('N'),
+            PieceKind::Bishop => Some('B'),
+            PieceKind::Rook => Some('R'),
+            PieceKind::Queen => Some('Q'),
+            PieceKind::King => Some('K'),
+        }
+    }
+}
+
+impl PromotionKind {
+    pub const fn piece_kind(&self) -> PieceKind {
+        match self {
+            PromotionKind::Queen => PieceKind::Queen,
+            PromotionKind::Rook => PieceKind::Rook,
+            PromotionKind::Bishop => PieceKind::Bishop,
+            PromotionKind::Knight => PieceKind::Knight,
+            PromotionKind::King => PieceKind::King,
+        }
+    }
+}
+
+impl Move {
+    /// Formats this move for the UCI protocol. Chess960 castling moves are written as the king capturing its own
+    /// rook, which is what engines expect with `UCI_Chess960` enabled.
+    pub fn to_uci(self, chess960: bool) -> String {
+        if chess960 && self.kind() == MoveKind::Castle {
+            format!("{}{}", self.src(), self.dst())
+        } else {
+            self.to_string()
+        }
+    }
+
+    /// Formats this move in Standard Algebraic Notation. The move must be legal on `board`.
+    pub fn to_san(self, board: &BoardState) -> String {
+        let mut move_generator = MoveGenerator::new();
+        let mut legal_moves = Vec::new();
+        move_generator.generate_moves(board, &mut |mov| legal_moves.push(mov), false);
+
+        let src = self.src();
+        let dst = self.dst();
+        let piece = self.piece(board);
+
+        let mut san = String::new();
+
+        if self.kind() == MoveKind::Castle {
+            san.push_str(if dst.file() > src.file() { "O-O" } else { "O-O-O" });
+        } else if self.is_drop() {
+            san.push_str(&self.to_string());
+        } else {
+            let is_capture = self.kind() == MoveKind::EnPassant || self.capture(board).is_some();
+
+            if let Some(c) = piece.kind.san_char() {
+                san.push(c);
+
+                // Disambiguate between pieces of the same kind that can reach the same square
+                let mut ambiguous = false;
+                let mut same_file = false;
+                let mut same_rank = false;
+                for other in legal_moves.iter() {
+                    if other.dst() == dst && other.src() != src && !other.is_drop() && other.piece(board).kind == piece.kind {
+                        ambiguous = true;
+                        same_file |= other.src().file() == src.file();
+                        same_rank |= other.src().rank() == src.rank();
+                    }
+                }
+                if ambiguous {
+                    let (fchar, rchar) = src.into_chars();
+                    if !same_file {
+                        san.push(fchar);
+                    } else if !same_rank {
+                        san.push(rchar);
+                    } else {
+                        san.push(fchar);
+                        san.push(rchar);
+                    }
+                }
+            } else if is_capture {
+                san.push(src.into_chars().0);
+            }
+
+            if is_capture {
+                san.push('x');
+            }
+
+            san.push_str(&dst.to_string());
+
+            if let Some(promotion_kind) = self.promotion_kind() {
+                san.push('=');
+                san.push(promotion_kind.piece_kind().san_char().unwrap());
+            }
+        }
+
+        let mut next_board = *board;
+        next_board.make_move(self);
+        let mut has_reply = false;
+        move_generator.generate_moves(&next_board, &mut |_| has_reply = true, false);
+        if move_generator.in_check() {
+            san.push(if has_reply { '+' } else { '#' });
+        }
+
+        san
+    }
+
+    /// Finds the legal move on `board` written as `text`, either in Standard Algebraic Notation or in coordinates as
+    /// in UCI. Check marks, annotations and the `=` of promotions may be left out, and castling may use zeros.
+    pub fn parse(text: &str, board: &BoardState) -> Option<Self> {
+        let normalize = |text: &str| text.trim().trim_end_matches(['+', '#', '!', '?']).replace('=', "").replace('0', "O");
+        let san = normalize(text);
+        let coordinates = text.trim().to_ascii_lowercase();
+        board.legal_moves().into_iter().find(|mov| {
+            mov.to_string() == coordinates || mov.to_uci(true) == coordinates || normalize(&mov.to_san(board)) == san
+        })
+    }
+}
+
+#[cfg(test)]
+mod notation_test {
+    use crate::chess::*;
+
+    fn find_move(board: &Board, text: &str) -> Move {
+        let mut found = None;
+        MoveGenerator::new().generate_moves(board, &mut |mov| if mov.to_string() == text { found = Some(mov) }, false);
+        found.unwrap()
+    }
+
+    #[test]
+    fn san_test() {
+        let board = Board::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(find_move(&board, "e1g1").to_san(&board), "O-O");
+        assert_eq!(find_move(&board, "e1c1").to_san(&board), "O-O-O");
+        assert_eq!(find_move(&board, "e5f7").to_san(&board), "Nxf7");
+        assert_eq!(find_move(&board, "c3b1").to_san(&board), "Nb1");
+        assert_eq!(find_move(&board, "d5e6").to_san(&board), "dxe6");
+        assert_eq!(find_move(&board, "a1b1").to_san(&board), "Rb1");
+
+        let board = Board::from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").unwrap();
+        assert_eq!(find_move(&board, "d7c8q").to_san(&board), "dxc8=Q");
+        assert_eq!(find_move(&board, "c4f7").to_san(&board), "Bxf7");
+
+        let board = Board::from_fen("6k1/5ppp/8/R7/8/7K/8/R6R w - - 0 1").unwrap();
+        assert_eq!(find_move(&board, "a1d1").to_san(&board), "Rad1");
+        assert_eq!(find_move(&board, "h1d1").to_san(&board), "Rhd1");
+        assert_eq!(find_move(&board, "a1a3").to_san(&board), "R1a3");
+        assert_eq!(find_move(&board, "a1b1").to_san(&board), "Rab1");
+        assert_eq!(find_move(&board, "a5a8").to_san(&board), "Ra8#");
+
+        // A piece in the pocket that could be dropped on the square doesn't make a move ambiguous
+        let board = Board::from_variant_fen(Variant::Crazyhouse, "4k3/8/8/8/8/8/8/4K1N1[N] w - - 0 1").unwrap();
+        assert_eq!(find_move(&board, "g1f3").to_san(&board), "Nf3");
+        assert_eq!(find_move(&board, "N@f3").to_san(&board), "N@f3");
+    }
+
+    #[test]
+    fn parse_test() {
+        let board = Board::from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").unwrap();
+        for text in ["dxc8=Q", "dxc8Q", "d7c8q", "D7C8Q"] {
+            assert_eq!(Move::parse(text, &board), Some(find_move(&board, "d7c8q")), "{text}");
+        }
+        for text in ["O-O", "0-0", "e1g1", "e1h1"] {
+            assert_eq!(Move::parse(text, &board), Some(find_move(&board, "e1g1")), "{text}");
+        }
+        assert_eq!(Move::parse("Bxf7+", &board), Some(find_move(&board, "c4f7")));
+        assert_eq!(Move::parse("  Nbc3 ", &board), Some(find_move(&board, "b1c3")));
+        assert_eq!(Move::parse("Nc3", &board), None, "Ambiguous");
+        assert_eq!(Move::parse("e4e5", &board), None);
+
+        let board = Board::from_variant_fen(Variant::Crazyhouse, "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1").unwrap();
+        assert_eq!(Move::parse("Q@c7", &board).map(|mov| mov.to_string()), Some("Q@c7".to_string()));
+
+        let board = Board::from_variant_fen(Variant::Crazyhouse, "4k3/8/8/8/8/8/8/4K1N1[N] w - - 0 1").unwrap();
+        assert_eq!(Move::parse("Nf3", &board), Some(find_move(&board, "g1f3")));
+    }
+}
@@ -0,0 +1,161 @@
+use super::*;
+use bitboards::BitBoard;
+
+/// The rules a game is played by. The board, the move generator and the game outcome consult it wherever a variant
+/// differs from standard chess. Chess960 castling is independent of this and tracked by the board itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    /// Bringing the king to one of the four center squares wins.
+    KingOfTheHill,
+    /// Giving check for the third time wins.
+    ThreeCheck,
+    /// Captures explode, removing the capturing piece and every piece but pawns next to the captured one.
+    /// Exploding the enemy king wins.
+    Atomic,
+    /// Captures are compulsory and the king is an ordinary piece. Losing all pieces or being stalemated wins.
+    Antichess,
+    /// Captured pieces go to the capturer's pocket and can be dropped back onto the board as a move.
+    Crazyhouse,
+}
+
+impl Variant {
+    pub const ALL: [Self; 6] = [Self::Standard, Self::KingOfTheHill, Self::ThreeCheck, Self::Atomic, Self::Antichess, Self::Crazyhouse];
+
+    /// Checks that win a game of Three-check.
+    pub const CHECKS_TO_WIN: u8 = 3;
+
+    /// The hill of King of the Hill: d4, e4, d5 and e5.
+    pub const HILL: BitBoard = const {
+        let mut hill = BitBoard::new();
+        hill.set(Position::D4);
+        hill.set(Position::E4);
+        hill.set(Position::D5);
+        hill.set(Position::E5);
+        hill
+    };
+
+    /// Name as used in the PGN `Variant` tag.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::ThreeCheck => "Three-check",
+            Variant::Atomic => "Atomic",
+            Variant::Antichess => "Antichess",
+            Variant::Crazyhouse => "Crazyhouse",
+        }
+    }
+
+    /// Parses a variant name as written by [`Variant::name`] or common alternatives like `3check` or `koth`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_ascii_lowercase();
+        Some(match name.as_str() {
+            "standard" | "chess" | "normal" => Variant::Standard,
+            "kingofthehill" | "koth" => Variant::KingOfTheHill,
+            "threecheck" | "3check" => Variant::ThreeCheck,
+            "atomic" => Variant::Atomic,
+            "antichess" | "giveaway" | "suicide" => Variant::Antichess,
+            "crazyhouse" | "zh" => Variant::Crazyhouse,
+            _ => return None,
+        })
+    }
+
+    /// Whether kings have to be kept out of check. In Antichess the king can simply be captured.
+    pub const fn has_royal_king(&self) -> bool {
+        !matches!(self, Variant::Antichess)
+    }
+
+    pub const fn has_castling(&self) -> bool {
+        !matches!(self, Variant::Antichess)
+    }
+
+    /// Whether captured pieces can be dropped back onto the board.
+    pub const fn has_pockets(&self) -> bool {
+        matches!(self, Variant::Crazyhouse)
+    }
+
+    /// Whether legal moves can't be found with the standard check and pin detection, and are instead filtered from
+    /// all pseudo-legal moves.
+    pub(super) const fn has_own_legality(&self) -> bool {
+        matches!(self, Variant::Atomic | Variant::Antichess)
+    }
+
+    /// The winner, if a rule of this variant ended the game in the position on `board`.
+    pub fn winner(&self, board: &BoardState) -> Option<PieceColor> {
+        [PieceColor::White, PieceColor::Black].into_iter().find(|&color| match self {
+            Variant::KingOfTheHill => !(board.pieces_of(color, PieceKind::King) & Self::HILL).is_empty(),
+            Variant::ThreeCheck => board.checks_given(color) >= Self::CHECKS_TO_WIN,
+            Variant::Atomic => board.pieces_of(!color, PieceKind::King).is_empty(),
+            _ => false,
+        })
+    }
+
+    /// The winner when the side to move has no legal moves left.
+    pub fn winner_without_moves(&self, side_to_move: PieceColor, in_check: bool) -> Option<PieceColor> {
+        match self {
+            Variant::Antichess => Some(side_to_move),
+            _ if in_check => Some(!side_to_move),
+            _ => None,
+        }
+    }
+
+    /// How a game of this variant is won other than by checkmate, used to describe game outcomes.
+    pub const fn win_description(&self) -> &'static str {
+        match self {
+            Variant::KingOfTheHill => "reaching the hill",
+            Variant::ThreeCheck => "giving three checks",
+            Variant::Atomic => "exploding the king",
+            Variant::Antichess => "running out of moves",
+            Variant::Standard | Variant::Crazyhouse => "checkmate",
+        }
+    }
+}
+
+impl std::fmt::Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Pieces a Crazyhouse player captured and can drop onto the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Pocket([u8; 5]);
+
+impl Pocket {
+    /// Kinds of pieces that can be in a pocket, in the order they are written in FEN.
+    pub const KINDS: [PieceKind; 5] = [PieceKind::Queen, PieceKind::Rook, PieceKind::Bishop, PieceKind::Knight, PieceKind::Pawn];
+
+    fn index(kind: PieceKind) -> usize {
+        match kind {
+            PieceKind::Pawn => 0,
+            PieceKind::Knight => 1,
+            PieceKind::Bishop => 2,
+            PieceKind::Rook => 3,
+            PieceKind::Queen => 4,
+            PieceKind::King => unreachable!("Kings can't be pocketed"),
+        }
+    }
+
+    pub fn count(&self, kind: PieceKind) -> u8 {
+        self.0[Self::index(kind)]
+    }
+
+    pub fn add(&mut self, kind: PieceKind) {
+        self.0[Self::index(kind)] += 1;
+    }
+
+    pub fn remove(&mut self, kind: PieceKind) {
+        self.0[Self::index(kind)] -= 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&count| count == 0)
+    }
+
+    /// Kinds of pieces with at least one in the pocket.
+    pub fn kinds(&self) -> impl Iterator<Item = PieceKind> + '_ {
+        Self::KINDS.into_iter().filter(|&kind| self.count(kind) > 0)
+    }
+}
@@ -1,44 +1,104 @@
 use super::*;
 
+/// Seed of the keys. Changing it changes every hash, so hashes saved with the old keys no longer match.
+pub const SEED: u64 = 0x9E3779B97F4A7C15;
+
 struct ZobristData {
     pub pieces: [[u64; 16]; 64],
     pub castling_rights: [u64; 16],
-    pub en_passant_file: [u64; 9],
+    pub en_passant_file: [u64; 8],
     pub side_to_move: u64,
+    pub pockets: [[[u64; 17]; 5]; 2],
+    pub checks_given: [[u64; 4]; 2],
+}
+
+/// Steps the SplitMix64 generator and returns its next output.
+const fn split_mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+const fn fill<const N: usize>(state: &mut u64) -> [u64; N] {
+    let mut keys = [0; N];
+    let mut i = 0;
+    while i < N {
+        keys[i] = split_mix(state);
+        i += 1;
+    }
+    keys
 }
 
-fn get_zobrist_data() -> &'static ZobristData {
-    static ZOBRIST_DATA: std::sync::OnceLock<ZobristData> = std::sync::OnceLock::new();
-    ZOBRIST_DATA.get_or_init(|| {
-        use rand::*;
-        let mut rng = rand::rng();
-
-        ZobristData { 
-            pieces: rng.random(), 
-            castling_rights: rng.random(), 
-            en_passant_file: rng.random(), 
-            side_to_move: rng.random()
+impl ZobristData {
+    /// Keys drawn from a SplitMix64 generator started at `seed`.
+    const fn generate(seed: u64) -> Self {
+        let mut state = seed;
+        let mut pieces = [[0; 16]; 64];
+        let mut i = 0;
+        while i < 64 {
+            pieces[i] = fill(&mut state);
+            i += 1;
         }
-    })
+        let castling_rights = fill(&mut state);
+        let en_passant_file = fill(&mut state);
+        let side_to_move = split_mix(&mut state);
+        let mut pockets = [[[0; 17]; 5]; 2];
+        let mut checks_given = [[0; 4]; 2];
+        let mut color = 0;
+        while color < 2 {
+            let mut kind = 0;
+            while kind < 5 {
+                pockets[color][kind] = fill(&mut state);
+                kind += 1;
+            }
+            checks_given[color] = fill(&mut state);
+            color += 1;
+        }
+        Self { pieces, castling_rights, en_passant_file, side_to_move, pockets, checks_given }
+    }
 }
 
+// The keys are the same in every run, so hashes can be saved and bugs involving them reproduced
+static ZOBRIST_DATA: ZobristData = ZobristData::generate(SEED);
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct ZobristHash(u64);
 
 impl ZobristHash {
+    /// The hash as a number, which stays the same across runs and builds.
+    pub const fn value(&self) -> u64 {
+        self.0
+    }
+
     pub fn update_with_piece_pos(&mut self, piece: Option<pieces::Piece>, pos: position::Position) {
-        self.0 ^= get_zobrist_data().pieces[pos.into_packed() as usize][piece.into_packed() as usize];
+        self.0 ^= ZOBRIST_DATA.pieces[pos.into_packed() as usize][piece.into_packed() as usize];
     }
 
     pub fn update_with_castle_state(&mut self, castling_rights: board::CastleState) {
-        self.0 ^= get_zobrist_data().castling_rights[castling_rights.into_packed() as usize];
+        self.0 ^= ZOBRIST_DATA.castling_rights[castling_rights.into_packed() as usize];
     }
 
+    /// Toggles the key for en passant being possible on `file`. No en passant square has no key.
     pub fn update_with_en_passant_file(&mut self, file: Option<u8>) {
-        self.0 ^= get_zobrist_data().en_passant_file[if let Some(rank) = file { rank as usize } else { 8 }];
+        if let Some(file) = file {
+            self.0 ^= ZOBRIST_DATA.en_passant_file[file as usize];
+        }
     }
 
     pub fn update_with_side_to_move(&mut self) {
-        self.0 ^= get_zobrist_data().side_to_move;
+        self.0 ^= ZOBRIST_DATA.side_to_move;
+    }
+
+    /// Toggles the key for `color` having `count` pieces of `kind` in its Crazyhouse pocket.
+    pub fn update_with_pocket_count(&mut self, color: pieces::PieceColor, kind: pieces::PieceKind, count: u8) {
+        let kind_index = variant::Pocket::KINDS.iter().position(|&pocket_kind| pocket_kind == kind).unwrap();
+        self.0 ^= ZOBRIST_DATA.pockets[color.is_black() as usize][kind_index][count.min(16) as usize];
+    }
+
+    /// Toggles the key for `color` having given `count` checks in Three-check.
+    pub fn update_with_checks_given(&mut self, color: pieces::PieceColor, count: u8) {
+        self.0 ^= ZOBRIST_DATA.checks_given[color.is_black() as usize][count.min(3) as usize];
     }
 }
\ No newline at end of file
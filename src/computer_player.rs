@@ -1,28 +1,84 @@
 use std::i32;
+use std::time::{Duration, Instant};
 
 use rand::seq::{IndexedRandom, SliceRandom};
 
 use crate::chess::*;
+use crate::player::Player;
 
+/// How much effort the engine may spend on a single move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// Maximum search depth in plies.
+    pub depth: usize,
+    /// Time after which the search is stopped, keeping the result of the last completed depth.
+    pub move_time: Option<Duration>,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        Self {
+            depth: 5,
+            move_time: None,
+        }
+    }
+}
 
+/// Time to spend on a move with `remaining` time left, from `moves_to_go` moves until the next time control
+/// (or an estimate for sudden death) and the time gained back per move.
+pub fn time_budget(remaining: Duration, moves_to_go: Option<u32>, increment: Duration) -> Duration {
+    const SAFETY_MARGIN: Duration = Duration::from_millis(50);
+
+    let moves_to_go = moves_to_go.unwrap_or(30).max(1);
+    let budget = (remaining / moves_to_go) + (increment * 3 / 4);
+    budget.min(remaining / 2).saturating_sub(SAFETY_MARGIN).max(Duration::from_millis(1))
+}
 
 pub struct ComputerPlayer {
     color: PieceColor,
+    limits: SearchLimits,
     move_generator: MoveGenerator,
     moves: Vec<Move>,
     current_move: Option<Move>,
+    deadline: Option<Instant>,
+    aborted: bool,
+    verbose: bool,
+    nodes: usize,
 }
 
 impl ComputerPlayer {
     pub fn new(color: PieceColor) -> Self {
+        Self::with_limits(color, SearchLimits::default())
+    }
+
+    pub fn with_limits(color: PieceColor, limits: SearchLimits) -> Self {
         Self {
             color,
+            limits,
             move_generator: MoveGenerator::new(),
             moves: Vec::new(),
-            current_move: None
+            current_move: None,
+            deadline: None,
+            aborted: false,
+            verbose: true,
+            nodes: 0,
         }
     }
 
+    pub fn limits(&self) -> SearchLimits {
+        self.limits
+    }
+
+    /// Whether search progress is printed to stdout.
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+
+    /// Moves searched during the last turn, over all iterations of the deepening.
+    pub fn nodes(&self) -> usize {
+        self.nodes
+    }
+
     pub fn move_count(&self) -> usize {
         self.moves.len()
     }
@@ -48,16 +104,30 @@ impl ComputerPlayer {
         });
     }
     
-    fn evaluate(board: &Board) -> i32 {
+    /// Static evaluation of `board` in centipawns, from the point of view of the side to move.
+    pub fn evaluate(board: &Board) -> i32 {
         let our_value = board.total_value(board.side_to_move());
         let their_value = board.total_value(!board.side_to_move());
-        our_value - their_value
+        // Losing material is the goal in Antichess
+        if board.variant() == Variant::Antichess {
+            their_value - our_value
+        } else {
+            our_value - their_value
+        }
     }
     
     fn search(&mut self, depth: usize, alpha: i32, beta: i32, board: &mut Board, best_move: &mut Option<Move>, iterations: &mut usize) -> i32 {
         if depth == 0 {
             return Self::evaluate(board);
         }
+
+        if self.aborted || self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            self.aborted = true;
+            return 0;
+        }
+
+        // At the root, the best move of the previous iteration is searched first
+        let hint = best_move.take();
     
         let moves_start = self.moves.len();
     
@@ -66,20 +136,24 @@ impl ComputerPlayer {
         let moves_end = self.moves.len();
     
         Self::order_moves(&mut self.moves[moves_start..moves_end], &board);
+        if let Some(i) = hint.and_then(|hint| self.moves[moves_start..moves_end].iter().position(|&mov| mov == hint)) {
+            self.moves[moves_start..=moves_start + i].rotate_right(1);
+        }
     
         let ret = if moves_end == moves_start {
-            // No available moves
-            if board.in_check(board.side_to_move()) {
-                -i32::MAX// So that when it's negated it won't overflow 
-            } else {
-                0
+            // No available moves, either because the game was won by a variant rule or the side to move is stuck
+            let winner = board.variant_winner().or_else(|| board.variant().winner_without_moves(board.side_to_move(), board.in_check(board.side_to_move())));
+            match winner {
+                Some(winner) if winner == board.side_to_move() => i32::MAX,
+                Some(_) => -i32::MAX,// So that when it's negated it won't overflow 
+                None => 0,
             }
         } else {
             let mut best_evaluation = alpha;
             
             for i in moves_start..moves_end {
                 let current_move = self.moves[i];
-                let evaluation = if current_move.capture(board).is_some_and(|piece| piece.kind == PieceKind::King) {
+                let evaluation = if board.variant().has_royal_king() && current_move.capture(board).is_some_and(|piece| piece.kind == PieceKind::King) {
                     i32::MAX // It's a check!
                 } else {
                     board.make_move(current_move, true);
@@ -90,6 +164,8 @@ impl ComputerPlayer {
                 *iterations += 1;
                 if evaluation >= beta  {
                     best_evaluation = beta;
+                    // A cutoff at the root is a win, e.g. mate in one, so the move must still be kept
+                    best_move.replace(current_move);
                     break;
                 } else if evaluation >= best_evaluation {
                     best_evaluation = evaluation;
@@ -105,32 +181,87 @@ impl ComputerPlayer {
     
         ret
     }
+}
+
+impl Player for ComputerPlayer {
+    fn color(&self) -> PieceColor {
+        self.color
+    }
 
-    pub fn begin_turn(&mut self, board: &mut Board) -> Option<Move> {
-        
+    fn in_turn(&self) -> bool {
+        self.current_move.is_some()
+    }
+
+    fn begin_turn(&mut self, game: &Game) {
+        let mut board = game.board().clone();
+        let start = Instant::now();
         let mut best_move = None;
         let mut iterations = 0;
-        let best_eval = self.search(5, -i32::MAX, i32::MAX, board, &mut best_move, &mut iterations);
-        println!("Iterations searched: {}", iterations);
-        if let Some(best_move) = best_move {
-            println!("Evaluation: {}", best_eval);
-            self.current_move.replace(best_move);
-            Some(best_move)
-        } else {
-            None
+
+        // On a clock, the time manager's budget overrides a longer fixed move time
+        let color = game.side_to_move();
+        let budget = Clock::for_game(game).map(|clock| {
+            let increment = match clock.increment(color) {
+                Increment::None => Duration::ZERO,
+                Increment::Fischer(increment) | Increment::Bronstein(increment) | Increment::Delay(increment) => increment,
+            };
+            time_budget(game.clock(color).unwrap_or_default(), clock.moves_to_go(color), increment)
+        });
+        let move_time = match (self.limits.move_time, budget) {
+            (Some(move_time), Some(budget)) => Some(move_time.min(budget)),
+            (move_time, budget) => move_time.or(budget),
+        };
+
+        // Iterative deepening: only results of fully searched depths are kept
+        self.aborted = false;
+        self.deadline = None;
+        for depth in 1..=self.limits.depth.max(1) {
+            let mut depth_best_move = best_move;
+            let eval = self.search(depth, -i32::MAX, i32::MAX, &mut board, &mut depth_best_move, &mut iterations);
+            if self.aborted {
+                self.moves.clear();
+                break;
+            }
+            best_move = depth_best_move.or(best_move);
+            if self.verbose {
+                println!("Depth {}: evaluation {}, {} iterations", depth, eval, iterations);
+            }
+
+            // The first depth always completes so that there is a move to play
+            self.deadline = move_time.map(|move_time| start + move_time);
+
+            // A deeper search is unlikely to finish in the time that is left
+            if budget.is_some_and(|budget| start.elapsed() > budget / 2) {
+                break;
+            }
         }
 
-        // self.move_generator.generate_moves(board, &mut |mov| self.moves.push(mov), false);
-        // let chosen_move = self.moves.choose(&mut rand::rng()).copied();
-        // if let Some(chosen_move) = chosen_move {
-        //     board.make_move(chosen_move, Some(PromotionKind::Queen), false);
-        // }
-        // chosen_move
+        self.current_move = best_move;
+        self.nodes = iterations;
+    }
+
+    fn poll_move(&mut self) -> Option<Move> {
+        self.current_move.take()
     }
 
-    pub fn finish_turn(&mut self, board: &mut Board) {
-        if let Some(current_move) = self.current_move.take() {
-            board.make_move(current_move, false);
+    fn cancel_turn(&mut self) {
+        self.current_move.take();
+    }
+}
+
+#[cfg(test)]
+mod computer_player_test {
+    use super::*;
+
+    #[test]
+    fn mate_in_one_test() {
+        let game = Game::from_fen("3r3k/6pp/8/8/8/8/6PP/3R2K1 w - - 0 1").unwrap();
+        let mate = Move::parse("Rxd8#", game.board()).unwrap();
+        for depth in 1..=4 {
+            let mut player = ComputerPlayer::with_limits(PieceColor::White, SearchLimits { depth, move_time: None });
+            player.set_verbose(false);
+            player.begin_turn(&game);
+            assert_eq!(player.poll_move(), Some(mate), "depth {depth}");
         }
     }
-}
\ No newline at end of file
+}
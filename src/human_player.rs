@@ -1,6 +1,7 @@
 use std::collections::{HashMap, HashSet};
 
 use crate::chess::*;
+use crate::player::{BoardInput, Player};
 
 pub struct HumanPlayer {
     color: PieceColor,
@@ -25,19 +26,6 @@ impl HumanPlayer {
         }
     }
 
-    pub fn begin_turn(&mut self, board: &Board) {
-        self.start_pos.take();
-        self.target_pos.take();
-        self.promotion.take();
-        self.move_tree.clear();
-        self.move_generator.generate_moves(board, &mut |mov| self.move_tree.push(mov), false);
-        self.in_turn = true;
-    }
-
-    pub fn in_turn(&self) -> bool {
-        self.in_turn
-    }
-
     pub fn start_position(&self) -> Option<Position> {
         self.start_pos
     }
@@ -74,15 +62,6 @@ impl HumanPlayer {
         false
     }
 
-    pub fn can_move_to(&self, target: Position) -> bool {
-        if let Some(start) = self.start_pos {
-            if self.move_tree.has_dst(start, target) {
-                return true;
-            }
-        }
-        false
-    }
-
     pub fn needs_promotion_choice(&self) -> bool {
         if let (Some(start_pos), Some(target_pos)) = (self.start_pos, self.target_pos) {
             if self.move_tree.has_promotion(start_pos, target_pos) {
@@ -101,17 +80,87 @@ impl HumanPlayer {
         }
         false
     }
+}
+
+impl Player for HumanPlayer {
+    fn color(&self) -> PieceColor {
+        self.color
+    }
+
+    fn in_turn(&self) -> bool {
+        self.in_turn
+    }
+
+    fn begin_turn(&mut self, game: &Game) {
+        self.cancel_move();
+        self.move_tree.clear();
+        self.move_generator.generate_moves(game.board(), &mut |mov| self.move_tree.push(mov), false);
+        self.in_turn = true;
+    }
 
-    pub fn finish_turn(&mut self, board: &mut Board) -> Option<Move> {
+    fn poll_move(&mut self) -> Option<Move> {
+        if self.pending_promotion().is_some() {
+            return None;
+        }
         if let (Some(start_pos), Some(target_pos), promotion) = (self.start_pos, self.target_pos, self.promotion) {
             if let Some(_move) = self.move_tree.get(start_pos, target_pos, promotion) {
-                board.make_move(_move, false);
+                self.cancel_move();
                 self.in_turn = false;
                 return Some(_move);
             }
-        } 
+        }
         None
     }
 
+    fn cancel_turn(&mut self) {
+        self.cancel_move();
+        self.in_turn = false;
+    }
 
-}
\ No newline at end of file
+    fn is_interactive(&self) -> bool {
+        true
+    }
+
+    fn handle_input(&mut self, input: BoardInput) -> bool {
+        match input {
+            BoardInput::Select(start) => {
+                self.cancel_move();
+                self.set_start_position(start)
+            },
+            BoardInput::Drop(target) => {
+                // A piece dropped on a square it can't reach goes back
+                let accepted = self.set_target_position(target);
+                if !accepted {
+                    self.cancel_move();
+                }
+                accepted
+            },
+            BoardInput::Promote(promotion) => self.promote(promotion),
+            BoardInput::Cancel => {
+                self.cancel_move();
+                true
+            },
+        }
+    }
+
+    fn selected_square(&self) -> Option<Position> {
+        self.start_pos
+    }
+
+    fn can_move_to(&self, target: Position) -> bool {
+        if let Some(start) = self.start_pos {
+            if self.move_tree.has_dst(start, target) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn pending_promotion(&self) -> Option<Position> {
+        if self.needs_promotion_choice() && self.promotion.is_none() {
+            self.target_pos
+        } else {
+            None
+        }
+    }
+}
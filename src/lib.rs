@@ -0,0 +1,6 @@
+pub mod chess;
+pub mod player;
+pub mod human_player;
+pub mod computer_player;
+pub mod uci_player;
+pub mod match_runner;
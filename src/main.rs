@@ -1,23 +1,106 @@
-mod chess;
-mod computer_player;
-mod human_player;
+mod setup_screen;
+mod theme;
 
-use chess::*;
+use chess::chess::*;
+use chess::player::{BoardInput, Player};
+use chess::{computer_player, human_player, uci_player};
 
-use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::pixels::Color;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::rect::Rect;
 use sdl2::*;
 use sdl2::gfx::primitives::DrawRenderer;
-use std::collections::HashMap;
 use std::time::Duration;
 
+/// A computer move sliding into place. It is played once the animation ends, with the time its player had left.
+#[derive(Clone, Copy)]
+struct MoveAnimation {
+    mov: Move,
+    remaining: Option<Duration>,
+    start: std::time::Instant,
+    end: std::time::Instant,
+}
+
+/// Screen area of the square at `pos`. A flipped board is drawn from black's side.
+fn square_rect(pos: Position, flipped: bool) -> Rect {
+    let (row, column) = if flipped {
+        (pos.rank() as i32, 7 - pos.file() as i32)
+    } else {
+        (7 - pos.rank() as i32, pos.file() as i32)
+    };
+    Rect::new(column * 80, row * 80, 80, 80)
+}
+
+/// The square under the screen point `(x, y)`, if any.
+fn square_at(x: i32, y: i32, flipped: bool) -> Option<Position> {
+    let (row, column) = (y.div_euclid(80), x.div_euclid(80));
+    if !(0..8).contains(&row) || !(0..8).contains(&column) {
+        return None;
+    }
+    Some(if flipped {
+        Position::new(row as u8, (7 - column) as u8)
+    } else {
+        Position::new((7 - row) as u8, column as u8)
+    })
+}
+
+/// Squares of the promotion picker, stacked from the promotion square towards the middle of the board.
+fn promotion_choice_rects(target: Position, flipped: bool) -> [(PromotionKind, Rect); 4] {
+    let target_rect = square_rect(target, flipped);
+    let step = if target_rect.y == 0 { 80 } else { -80 };
+    [PromotionKind::Queen, PromotionKind::Rook, PromotionKind::Bishop, PromotionKind::Knight]
+        .into_iter()
+        .enumerate()
+        .map(|(i, kind)| (kind, Rect::new(target_rect.x, target_rect.y + (i as i32 * step), 80, 80)))
+        .collect::<Vec<_>>()
+        .try_into()
+        .unwrap()
+}
+
+fn create_player(color: PieceColor, setup: &setup_screen::PlayerSetup) -> anyhow::Result<Box<dyn Player>> {
+    Ok(match setup.kind {
+        setup_screen::PlayerKind::Human => Box::new(human_player::HumanPlayer::new(color)),
+        setup_screen::PlayerKind::Computer => Box::new(computer_player::ComputerPlayer::with_limits(color, setup.limits)),
+        setup_screen::PlayerKind::UciEngine => Box::new(uci_player::UciEnginePlayer::from_command_line(color, &setup.engine_command, setup.limits)?),
+    })
+}
+
+fn cancel_turns(players: &mut [Box<dyn Player>; 2]) {
+    for player in players.iter_mut() {
+        player.cancel_turn();
+    }
+}
+
+/// Plays the move of the side to move and lets the other player know about it. `clock` is the mover's time left.
+fn play_move(game: &mut Game, players: &mut [Box<dyn Player>; 2], mov: Move, clock: Option<Duration>) {
+    game.make_move(mov, clock);
+    players[game.side_to_move().is_black() as usize].opponent_moved(game, mov);
+}
+
+/// A clock showing the times recorded in `game`, running for the side to move.
+fn resume_clock(game: &Game) -> Option<Clock> {
+    let mut clock = Clock::for_game(game)?;
+    clock.start(game.side_to_move(), std::time::Instant::now());
+    Some(clock)
+}
+
+fn format_clock(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+    } else if secs >= 10 {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    } else {
+        // Show tenths when time is short
+        format!("0:{:02}.{}", secs, time.subsec_millis() / 100)
+    }
+}
+
 pub fn main() {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let font_context = ttf::init().unwrap();
-    let font = font_context.load_font("LiberationMono-Regular.ttf", 18).unwrap();
 
     let window = video_subsystem.window("Chess", 960, 640)
         .position_centered()
@@ -27,82 +110,45 @@ pub fn main() {
     let mut canvas = window.into_canvas().build().unwrap();
     let texture_creator = canvas.texture_creator();
 
-    let textures = [
-        (Piece::WHITE_PAWN, "white_pawn.png"),
-        (Piece::BLACK_PAWN, "black_pawn.png"),
-        (Piece::WHITE_KNIGHT, "white_knight.png"),
-        (Piece::BLACK_KNIGHT, "black_knight.png"),
-        (Piece::WHITE_BISHOP, "white_bishop.png"),
-        (Piece::BLACK_BISHOP, "black_bishop.png"),
-        (Piece::WHITE_ROOK, "white_rook.png"),
-        (Piece::BLACK_ROOK, "black_rook.png"),
-        (Piece::WHITE_QUEEN, "white_queen.png"),
-        (Piece::BLACK_QUEEN, "black_queen.png"),
-        (Piece::WHITE_KING, "white_king.png"),
-        (Piece::BLACK_KING, "black_king.png"),
-    ].into_iter().map(|(piece, filename)| {
-        let image = image::open(filename).unwrap();
-        let image = image.into_rgba8();
-        let mut texture = texture_creator.create_texture_static(
-            PixelFormatEnum::ARGB8888, 
-            image.width(), 
-            image.height()
-        ).unwrap();
-        texture.update(None, &image, (image.width()*4) as usize).unwrap();
-        texture.set_blend_mode(render::BlendMode::Blend);
-        (piece, texture)
-    }).collect::<HashMap<_, _>>(); 
-
-    // let mut board = Board::from_fen("rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8").unwrap();
-    let mut board = Board::new();
-
-    let mut mouse_click: Option<(bool, i32, i32)> = None;
+    let theme = theme::Theme::load_default().unwrap_or_else(|error| {
+        eprintln!("{error:#}, using the default theme");
+        theme::Theme::default()
+    });
+    let textures = theme.pieces.load_textures(&texture_creator, &font_context).unwrap_or_else(|error| {
+        eprintln!("{error:#}, using the classic pieces");
+        theme::PieceSet::Classic.load_textures(&texture_creator, &font_context).unwrap()
+    });
+
+    let mut game = Game::new();
+
+    let mut mouse_click: Option<(bool, i32, i32)>;
     let mut mouse_pos: (i32, i32) = (0, 0);
 
-    let mut checkmate: Option<PieceColor> = None;
+    let mut outcome: Option<GameOutcome> = None;
 
-    let mut moving_piece: Option<(Piece, Position, Option<(Position, std::time::Instant, std::time::Instant)>)> = None;
+    let mut moving_piece: Option<(Piece, Position, Option<MoveAnimation>)> = None;
 
-    let mut log = Vec::new();
+    let mut clock: Option<Clock> = None;
 
-    let player_color_ = if let Ok(button) = sdl2::messagebox::show_message_box(
-        messagebox::MessageBoxFlag::INFORMATION, 
-        &[
-            messagebox::ButtonData {
-                flags: messagebox::MessageBoxButtonFlag::NOTHING,
-                button_id: 0,
-                text: "White",
-            },
-            messagebox::ButtonData {
-                flags: messagebox::MessageBoxButtonFlag::NOTHING,
-                button_id: 1,
-                text: "Black",
-            }
-        ], 
-        "Choose a color", 
-        "Choose the color you want to play as.", 
-        None, 
-        None
-    ) {
-        match button {
-            messagebox::ClickedButton::CloseButton => std::process::exit(0),
-            messagebox::ClickedButton::CustomButton(button_data) => match button_data.button_id {
-                0 => PieceColor::White,
-                1 => PieceColor::Black,
-                _ => unreachable!()
-            },
-        }
-    } else { std::process::exit(0) }; 
+    // Why the last move the user tried was rejected
+    let mut status: Option<String> = None;
 
-    let play_white = false;
-    let play_black = false;
+    // A promotion piece chosen with the keyboard this frame
+    let mut promotion_key: Option<PromotionKind>;
 
-    let mut players: [Box<dyn std::any::Any>; 2] = [
-        if play_white { Box::new(human_player::HumanPlayer::new(PieceColor::White)) } else { Box::new(computer_player::ComputerPlayer::new(PieceColor::White)) },
-        if play_black { Box::new(human_player::HumanPlayer::new(PieceColor::Black)) } else { Box::new(computer_player::ComputerPlayer::new(PieceColor::Black)) },
+    // The setup screen is shown first and can be reopened with F2
+    let mut setup_screen = setup_screen::SetupScreen::new();
+    let mut show_setup = true;
+    let mut game_started = false;
+    let clipboard = video_subsystem.clipboard();
+
+    let mut players: [Box<dyn Player>; 2] = [
+        Box::new(human_player::HumanPlayer::new(PieceColor::White)),
+        Box::new(computer_player::ComputerPlayer::new(PieceColor::Black)),
     ];
 
-    let mut current_player: &mut dyn std::any::Any = players[board.side_to_move().is_black() as usize].as_mut();
+    let mut flipped = false;
+
     
     canvas.set_blend_mode(sdl2::render::BlendMode::Blend);
     canvas.set_draw_color(Color::RGB(127, 127, 127));
@@ -113,25 +159,99 @@ pub fn main() {
     'running: loop {
         start = std::time::Instant::now();
         mouse_click = None;
+        promotion_key = None;
         canvas.set_draw_color(Color::RGB(127, 127, 127));
         canvas.clear();
         for event in event_pump.poll_iter() {
+            if show_setup {
+                match event {
+                    Event::Quit {..} => break 'running,
+                    Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                        if !game_started {
+                            break 'running
+                        }
+                        show_setup = false;
+                        if let (Some(clock), None) = (clock.as_mut(), outcome) {
+                            clock.start(game.side_to_move(), std::time::Instant::now());
+                        }
+                    },
+                    Event::MouseMotion { x, y, .. } => mouse_pos = (x, y),
+                    _ => if let Some((new_game, setup)) = setup_screen.handle_event(&event, &clipboard) {
+                        let new_players = create_player(PieceColor::White, &setup.players[0])
+                            .and_then(|white| Ok([white, create_player(PieceColor::Black, &setup.players[1])?]));
+                        match new_players {
+                            Ok(new_players) => players = new_players,
+                            Err(err) => {
+                                setup_screen.set_error(format!("{err:#}"));
+                                continue;
+                            },
+                        }
+                        game = new_game;
+
+                        // Draw the board from the human player's side
+                        flipped = setup.players[1].kind == setup_screen::PlayerKind::Human && setup.players[0].kind != setup_screen::PlayerKind::Human;
+
+                        moving_piece.take();
+                        outcome.take();
+                        clock = resume_clock(&game);
+                        show_setup = false;
+                        game_started = true;
+                    },
+                }
+                continue;
+            }
             match event {
                 Event::Quit {..} |
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
-                Event::MouseButtonDown { timestamp, window_id, which, mouse_btn, clicks, x, y } => {
+                Event::KeyDown { keycode: Some(Keycode::F2), .. } => {
+                    cancel_turns(&mut players);
+                    moving_piece.take();
+                    if let Some(clock) = clock.as_mut() {
+                        clock.stop(std::time::Instant::now());
+                    }
+                    show_setup = true;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F), .. } => {
+                    flipped = !flipped;
+                },
+                Event::KeyDown { keycode: Some(Keycode::P), .. } => {
+                    println!("{}", game.to_pgn());
+                },
+                Event::KeyDown { keycode: Some(keycode @ (Keycode::Q | Keycode::R | Keycode::B | Keycode::N)), .. } => {
+                    promotion_key = Some(match keycode {
+                        Keycode::Q => PromotionKind::Queen,
+                        Keycode::R => PromotionKind::Rook,
+                        Keycode::B => PromotionKind::Bishop,
+                        _ => PromotionKind::Knight,
+                    });
+                },
+                Event::KeyDown { keycode: Some(keycode @ (Keycode::Left | Keycode::Right | Keycode::Home | Keycode::End)), .. } => {
+                    match keycode {
+                        Keycode::Left => { game.undo(); },
+                        Keycode::Right => { game.redo(); },
+                        Keycode::Home => game.jump_to(0),
+                        _ => game.jump_to(game.len()),
+                    }
+
+                    // Whoever was thinking has to start over in the new position
+                    cancel_turns(&mut players);
+                    moving_piece.take();
+                    outcome.take();
+                    clock = resume_clock(&game);
+                },
+                Event::MouseButtonDown { mouse_btn, x, y, .. } => {
                     if mouse_btn == mouse::MouseButton::Left {
                         mouse_click = Some((true, x, y));
                     }
                 },
-                Event::MouseButtonUp { timestamp, window_id, which, mouse_btn, clicks, x, y } => {
+                Event::MouseButtonUp { mouse_btn, x, y, .. } => {
                     if mouse_btn == mouse::MouseButton::Left {
                         mouse_click = Some((false, x, y));
                     }
                 }
-                Event::MouseMotion { timestamp, window_id, which, mousestate, x, y, xrel, yrel } => {
+                Event::MouseMotion { x, y, .. } => {
                     mouse_pos = (x, y);
                 }
                 _ => {}
@@ -139,110 +259,135 @@ pub fn main() {
         }
         // The rest of the game loop goes here...
 
-        {
-            if let Some(human_player) = current_player.downcast_mut::<human_player::HumanPlayer>() {
-                if !human_player.in_turn() {
-                    human_player.begin_turn(&board);
-                    if human_player.move_count() == 0 && human_player.in_check() {
-                        checkmate.replace(board.side_to_move());
+        if show_setup {
+            setup_screen.draw(&mut canvas, mouse_pos);
+            canvas.present();
+            std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60).saturating_sub(std::time::Instant::now().saturating_duration_since(start)));
+            continue;
+        }
+
+        if outcome.is_none() {
+            if let Some(color) = clock.as_ref().and_then(|clock| clock.flagged(std::time::Instant::now())) {
+                game.flag_fall(color);
+            }
+            outcome = game.outcome();
+            if let Some(game_outcome) = outcome {
+                if let Some(clock) = clock.as_mut() {
+                    clock.stop(std::time::Instant::now());
+                }
+                for player in players.iter_mut() {
+                    player.game_over(&game, game_outcome);
+                }
+            }
+        }
+
+        if outcome.is_none() {
+            let player = players[game.side_to_move().is_black() as usize].as_mut();
+
+            if let Some((_, _, Some(animation))) = moving_piece {
+                // Wait for the animation to finish before playing the move
+                if std::time::Instant::now() > animation.end {
+                    moving_piece.take();
+                    play_move(&mut game, &mut players, animation.mov, animation.remaining);
+                }
+            } else {
+                if !player.in_turn() {
+                    let start = std::time::Instant::now();
+                    player.begin_turn(&game);
+                    if !player.is_interactive() {
+                        println!("Computer move took {} ms", start.elapsed().as_millis());
                     }
                 }
-                if let Some((down, x, y)) = mouse_click {
-                    let (rank, file) = (y / 80, x / 80);
-                    if (0..8).contains(&rank) && (0..8).contains(&file) {
-                        let piece_pos = if board.side_to_move().is_white() || true {
-                            Position::new((7-rank) as u8, file as u8)
-                        } else {
-                            Position::new(rank as u8, file as u8)
-                        };
+
+                if let Some(target) = player.pending_promotion() {
+                    let mut choice = promotion_key;
+                    if let Some((true, x, y)) = mouse_click {
+                        choice = promotion_choice_rects(target, flipped).into_iter()
+                            .find(|(_, rect)| rect.contains_point((x, y)))
+                            .map(|(kind, _)| kind);
+                        if choice.is_none() {
+                            // Clicking outside the picker takes the move back
+                            player.handle_input(BoardInput::Cancel);
+                        }
+                    }
+                    if let Some(kind) = choice {
+                        player.handle_input(BoardInput::Promote(kind));
+                    }
+                } else if let Some((down, x, y)) = mouse_click.filter(|_| player.is_interactive()) {
+                    if let Some(piece_pos) = square_at(x, y, flipped) {
                         if down {
-                            if let Some(piece) = board.get(piece_pos) {
-                                
-                                if human_player.set_start_position(piece_pos) {
+                            if let Some(piece) = game.board().get(piece_pos) {
+                                if player.handle_input(BoardInput::Select(piece_pos)) {
                                     moving_piece.replace((piece, piece_pos, None));
+                                    status.take();
+                                } else if piece.color != game.side_to_move() {
+                                    status = Some(IllegalReason::NotYourTurn.to_string());
                                 }
                             }
-                        } else if !down {
-                            if human_player.set_target_position(piece_pos) {
-                                if human_player.needs_promotion_choice() {
-                                    unimplemented!();
+                        } else {
+                            if !player.handle_input(BoardInput::Drop(piece_pos)) {
+                                if let Some((_, src, None)) = moving_piece {
+                                    // Dropping the piece back where it was isn't worth a message
+                                    status = game.validate_move(src, piece_pos, None).err()
+                                        .filter(|reason| *reason != IllegalReason::NoMove)
+                                        .map(|reason| reason.to_string());
                                 }
-                                human_player.finish_turn(&mut board);
-
-                                current_player = players[board.side_to_move().is_black() as usize].as_mut();
-                            } else {
-                                human_player.cancel_move();
                             }
                             moving_piece.take();
                         }
                     } else if !down {
-                        human_player.cancel_move();
+                        player.handle_input(BoardInput::Cancel);
                         moving_piece.take();
                     }
                 }
-            } else if let Some(computer_player) = current_player.downcast_mut::<computer_player::ComputerPlayer>() {
-                
-                if let Some((_, _, Some((_, _, anim_end)))) = moving_piece {
+
+                if let Some(mov) = player.poll_move() {
+                    status.take();
                     let now = std::time::Instant::now();
-                    if now > anim_end {
-                        moving_piece.take();
-                        computer_player.finish_turn(&mut board);
-                        current_player = players[board.side_to_move().is_black() as usize].as_mut();
-                    }
-                } else {
-                    let start = std::time::Instant::now();
-                    if let Some(mov) = computer_player.begin_turn(&mut board) {
-                        println!("Computer move took {} ms", start.elapsed().as_millis());
-                        let now = std::time::Instant::now();
-                        moving_piece.replace((mov.piece(&board), mov.src(), Some((mov.dst(), now, now + std::time::Duration::from_secs_f32(0.25)))));
-                        log.push(mov);
+                    let remaining = clock.as_mut().and_then(|clock| clock.press(now));
+                    if player.is_interactive() {
+                        // The piece was already dragged into place
+                        play_move(&mut game, &mut players, mov, remaining);
                     } else {
-                        if computer_player.move_count() == 0 && computer_player.in_check() {
-                            checkmate.replace(board.side_to_move());
-                        }
+                        moving_piece.replace((mov.piece(game.board()), mov.src(), Some(MoveAnimation { mov, remaining, start: now, end: now + std::time::Duration::from_secs_f32(0.25) })));
                     }
                 }
-                
             }
         }
 
-        for rank in (0u8..8) {
-            for file in (0u8..8) {
-                let square_rect = Rect::new((file as i32) * 80, (rank as i32) * 80, 80, 80);
-                let piece_rect = Rect::new(((file as i32) * 80) + 10, ((rank as i32) * 80) + 10, 60, 60);
-                let piece_pos: Position = if board.side_to_move().is_white() || true {
-                    (7-rank, file).into()
-                } else {
-                    (rank, file).into()
-                };
+        let current_player = players[game.side_to_move().is_black() as usize].as_ref();
+        let pending_promotion = if outcome.is_none() { current_player.pending_promotion() } else { None };
+
+        for rank in 0u8..8 {
+            for file in 0u8..8 {
+                let piece_pos = Position::new(rank, file);
+                let square_rect = square_rect(piece_pos, flipped);
+                let piece_rect = Rect::new(square_rect.x + 10, square_rect.y + 10, 60, 60);
                 
+                // a1 is a dark square
                 if (piece_pos.rank() + piece_pos.file()) % 2 == 0 {
-                    canvas.set_draw_color(Color::RGB(255, 213, 128));
+                    canvas.set_draw_color(theme.dark_square);
                 } else {
-                    canvas.set_draw_color(Color::RGB(179, 119, 0));
+                    canvas.set_draw_color(theme.light_square);
                 }
                 canvas.fill_rect(square_rect).unwrap();
-                let _ = canvas.string(piece_rect.x as i16, piece_rect.y as i16, &format!("{}", piece_pos), Color::RGBA(191, 191, 191, 127));
+                let _ = canvas.string(piece_rect.x as i16, piece_rect.y as i16, &format!("{}", piece_pos), theme.coordinates);
                 
                 // Draw green circle if player can move there
-                if let Some(human_player) = current_player.downcast_ref::<human_player::HumanPlayer>() {
-                    if let Some(old_piece_pos) = human_player.start_position() {
-                        if human_player.can_move_to(piece_pos) {
-                            let center = square_rect.center();
-                            
-                            canvas.filled_circle(center.x as i16, center.y as i16, 30, Color::RGBA(0, 255, 0, 127)).unwrap();
-                        }
-                    }
+                if current_player.selected_square().is_some() && current_player.can_move_to(piece_pos) {
+                    let center = square_rect.center();
+                    
+                    canvas.filled_circle(center.x as i16, center.y as i16, 30, theme.move_hint).unwrap();
                 }
 
                 // Draw highlight if mouse is hovering
                 if square_rect.contains_point(mouse_pos) {
-                    canvas.set_draw_color(Color::RGBA(255, 255, 255, 63));
+                    canvas.set_draw_color(theme.hover);
                     canvas.fill_rect(square_rect).unwrap();
                 }
 
                 // Draw piece if present and not being moved
-                if let Some(piece) = board.get(piece_pos) {
+                if let Some(piece) = game.board().get(piece_pos) {
                     if moving_piece.is_none_or(|(_, src, _)| src != piece_pos) {
                         let texture = textures.get(&piece).unwrap();
                         canvas.copy(texture, None, piece_rect).unwrap();
@@ -253,15 +398,17 @@ pub fn main() {
 
         // Draw grabbed piece
         if let Some((piece, src, dst)) = moving_piece {
-            if let Some((dst, anim_start, anim_end)) = dst {
+            if let Some(MoveAnimation { mov, start: anim_start, end: anim_end, .. }) = dst {
+                let dst = if mov.kind() == MoveKind::Castle { mov.castle_destinations().0 } else { mov.dst() };
                 // Computer (animated) move
                 let now = std::time::Instant::now();
                 let blend = ((now - anim_start).as_secs_f32() / (anim_end - anim_start).as_secs_f32()).clamp(0.0, 1.0);
 
-                let src_x = ((src.file() as i32 * 80) + 10) as f32;
-                let src_y = (((7-src.rank()) as i32 * 80) + 10) as f32;
-                let dst_x = ((dst.file() as i32 * 80) + 10) as f32;
-                let dst_y = (((7-dst.rank()) as i32 * 80) + 10) as f32;
+                let (src_rect, dst_rect) = (square_rect(src, flipped), square_rect(dst, flipped));
+                let src_x = (src_rect.x + 10) as f32;
+                let src_y = (src_rect.y + 10) as f32;
+                let dst_x = (dst_rect.x + 10) as f32;
+                let dst_y = (dst_rect.y + 10) as f32;
 
                 let anim_x = ((blend * dst_x) + ((1.0 - blend) * src_x)) as i32;
                 let anim_y = ((blend * dst_y) + ((1.0 - blend) * src_y)) as i32;
@@ -277,23 +424,80 @@ pub fn main() {
             }
         }
 
+        // Draw promotion picker
+        if let Some(target) = pending_promotion {
+            canvas.set_draw_color(Color::RGBA(0, 0, 0, 127));
+            canvas.fill_rect(Rect::new(0, 0, 640, 640)).unwrap();
+
+            for (kind, square_rect) in promotion_choice_rects(target, flipped) {
+                canvas.set_draw_color(if square_rect.contains_point(mouse_pos) {
+                    Color::RGB(255, 255, 255)
+                } else {
+                    Color::RGB(191, 191, 191)
+                });
+                canvas.fill_rect(square_rect).unwrap();
+
+                let piece_rect = Rect::new(square_rect.x + 10, square_rect.y + 10, 60, 60);
+                let texture = textures.get(&Piece::new(kind.piece_kind(), game.side_to_move())).unwrap();
+                canvas.copy(texture, None, piece_rect).unwrap();
+            }
+        }
+
         {
             let (max_width, max_height) = (300, 200);
             canvas.set_draw_color(Color::BLACK);
             canvas.fill_rect(Rect::new(640, 0, max_width+20, max_height+20)).unwrap();
             
-            let mut line = 0i16;
-            for move_result in log.iter().rev().take(20).rev() {
-                let string = format!("{move_result}");
+            // One line per full move, e.g. "12. Nf3 e5"
+            let start_ply = game.start_board().ply_count();
+            let mut lines = Vec::new();
+            for (i, record) in game.records().iter().enumerate() {
+                let ply = start_ply + i;
+                let color = if i < game.ply() { Color::WHITE } else { Color::GRAY };
+                if ply.is_multiple_of(2) || i == 0 {
+                    let number = format!("{}.{}", (ply / 2) + 1, if ply.is_multiple_of(2) { "" } else { ".." });
+                    lines.push(vec![(number, Color::WHITE)]);
+                }
+                lines.last_mut().unwrap().push((record.san.clone(), color));
+            }
+
+            for (line, tokens) in lines.iter().skip(lines.len().saturating_sub(20)).enumerate() {
+                let mut column = 0i16;
+                for (token, color) in tokens {
+                    canvas.string(645 + (column*8), 5 + (line as i16 * 10), token, *color).unwrap();
+                    column += token.len() as i16 + 1;
+                }
+            }
+
+            // Clocks, with the top one belonging to the player at the top of the board
+            if let Some(clock) = clock.as_ref() {
+                let now = std::time::Instant::now();
+                let colors = if flipped { [PieceColor::White, PieceColor::Black] } else { [PieceColor::Black, PieceColor::White] };
+                for (i, color) in colors.into_iter().enumerate() {
+                    let rect = Rect::new(640, 240 + (i as i32 * 40), max_width + 20, 30);
+                    let running = clock.running() == Some(color);
+                    canvas.set_draw_color(if running { Color::RGB(255, 213, 128) } else { Color::BLACK });
+                    canvas.fill_rect(rect).unwrap();
 
-                canvas.string(645, 5 + (line*10), &string, Color::WHITE).unwrap();
-                line += 1;
+                    let name = if color.is_white() { "White" } else { "Black" };
+                    let time = clock.remaining(color, now);
+                    let text_color = if time.is_zero() { Color::RED } else if running { Color::BLACK } else { Color::WHITE };
+                    canvas.string((rect.x + 5) as i16, (rect.y + 11) as i16, &format!("{name:<8}{}", format_clock(time)), text_color).unwrap();
+                }
+            }
+
+            // Status bar
+            let rect = Rect::new(640, 610, max_width + 20, 30);
+            canvas.set_draw_color(Color::BLACK);
+            canvas.fill_rect(rect).unwrap();
+            if let Some(status) = status.as_ref() {
+                canvas.string((rect.x + 5) as i16, (rect.y + 11) as i16, status, Color::RGB(255, 128, 128)).unwrap();
             }
         }
 
         canvas.present();
 
-        if let Some(color) = checkmate {
+        if let Some(game_outcome) = outcome {
             if let Ok(button) = sdl2::messagebox::show_message_box(
                 messagebox::MessageBoxFlag::INFORMATION, 
                 &[
@@ -303,12 +507,8 @@ pub fn main() {
                         text: "OK",
                     }
                 ], 
-                "Checkmate", 
-                if color.is_white() {
-                    "White wins!"
-                } else {
-                    "Black wins!"
-                }, 
+                "Game over", 
+                &game_outcome.to_string(), 
                 None, 
                 None
             ) {
@@ -319,8 +519,11 @@ pub fn main() {
                         button_id: 0,
                         text: _,
                     }) => {
-                        board.reset();
-                        checkmate.take();
+                        game.reset();
+                        cancel_turns(&mut players);
+                        moving_piece.take();
+                        outcome.take();
+                        clock = resume_clock(&game);
                     },
                     _ => unreachable!()
                 }
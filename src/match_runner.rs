@@ -0,0 +1,441 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::chess::*;
+use crate::computer_player::{ComputerPlayer, SearchLimits};
+use crate::player::Player;
+use crate::uci_player::UciEnginePlayer;
+
+/// One side of a match: the built-in engine or an external UCI engine, with its search limits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineConfig {
+    pub name: String,
+    /// Command line of a UCI engine, or `None` for [`ComputerPlayer`].
+    pub command: Option<String>,
+    pub limits: SearchLimits,
+    /// UCI options sent with `setoption` after the handshake.
+    pub options: Vec<(String, String)>,
+}
+
+impl EngineConfig {
+    /// Parses a list of `key=value` settings: `name`, `cmd`, `depth`, `movetime` (in milliseconds) and
+    /// `option.<name>` for UCI options. Without `cmd` the built-in engine is used.
+    pub fn parse<'a>(settings: impl IntoIterator<Item = &'a str>) -> anyhow::Result<Self> {
+        let mut config = Self {
+            name: String::new(),
+            command: None,
+            limits: SearchLimits::default(),
+            options: Vec::new(),
+        };
+        for setting in settings {
+            let (key, value) = setting.split_once('=').ok_or_else(|| anyhow!("Expected key=value, got `{setting}`"))?;
+            match key {
+                "name" => config.name = value.to_string(),
+                "cmd" => config.command = Some(value.to_string()),
+                "depth" => config.limits.depth = value.parse().with_context(|| format!("Invalid depth `{value}`"))?,
+                "movetime" => config.limits.move_time = Some(Duration::from_millis(value.parse().with_context(|| format!("Invalid move time `{value}`"))?)),
+                _ => if let Some(option) = key.strip_prefix("option.") {
+                    config.options.push((option.to_string(), value.to_string()));
+                } else {
+                    bail!("Unknown engine setting `{key}`");
+                },
+            }
+        }
+        if config.name.is_empty() {
+            config.name = config.command.clone().unwrap_or_else(|| format!("Internal depth {}", config.limits.depth));
+        }
+        Ok(config)
+    }
+
+    pub fn create_player(&self, color: PieceColor) -> anyhow::Result<Box<dyn Player>> {
+        if let Some(command) = &self.command {
+            let mut player = UciEnginePlayer::from_command_line(color, command, self.limits)?;
+            for (name, value) in self.options.iter() {
+                player.set_option(name, value)?;
+            }
+            Ok(Box::new(player))
+        } else {
+            let mut player = ComputerPlayer::with_limits(color, self.limits);
+            player.set_verbose(false);
+            Ok(Box::new(player))
+        }
+    }
+}
+
+/// Reads starting positions from a file with one FEN per line. Blank lines and lines starting with `#` are skipped.
+pub fn load_openings(path: &str) -> anyhow::Result<Vec<String>> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("Failed to read openings from `{path}`"))?;
+    let mut openings = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        Board::from_fen(line).with_context(|| format!("Invalid FEN on line {} of `{path}`", i + 1))?;
+        openings.push(line.to_string());
+    }
+    if openings.is_empty() {
+        bail!("No openings in `{path}`");
+    }
+    Ok(openings)
+}
+
+/// Wins, losses and draws from the point of view of the first engine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MatchScore {
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtVerdict {
+    /// The first engine is stronger by at least `elo1`.
+    AcceptH1,
+    /// The first engine is not stronger by more than `elo0`.
+    AcceptH0,
+    Continue,
+}
+
+/// A sequential probability ratio test of `elo0` against `elo1`, with false positive rate `alpha` and false
+/// negative rate `beta`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        Self {
+            elo0: 0.0,
+            elo1: 10.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn score_to_elo(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+impl Sprt {
+    /// Lower and upper log-likelihood ratio bounds at which H0 and H1 are accepted.
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    /// Log-likelihood ratio of the score, using the normal approximation of the trinomial model.
+    pub fn llr(&self, score: &MatchScore) -> f64 {
+        let Some((mean, variance)) = score.mean_and_variance() else { return 0.0 };
+        if variance == 0.0 {
+            return 0.0;
+        }
+        let (s0, s1) = (elo_to_score(self.elo0), elo_to_score(self.elo1));
+        score.games() as f64 * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn verdict(&self, score: &MatchScore) -> SprtVerdict {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            SprtVerdict::AcceptH1
+        } else if llr <= lower {
+            SprtVerdict::AcceptH0
+        } else {
+            SprtVerdict::Continue
+        }
+    }
+}
+
+impl MatchScore {
+    pub fn games(&self) -> usize {
+        self.wins + self.losses + self.draws
+    }
+
+    /// Mean and variance of the per-game score.
+    fn mean_and_variance(&self) -> Option<(f64, f64)> {
+        let n = self.games() as f64;
+        if n == 0.0 {
+            return None;
+        }
+        let (w, l, d) = (self.wins as f64 / n, self.losses as f64 / n, self.draws as f64 / n);
+        let mean = w + (d / 2.0);
+        let variance = (w * (1.0 - mean).powi(2)) + (d * (0.5 - mean).powi(2)) + (l * mean.powi(2));
+        Some((mean, variance))
+    }
+
+    /// Elo difference of the first engine and the half-width of its 95% confidence interval.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let (mean, variance) = self.mean_and_variance()?;
+        let margin = 1.959964 * (variance / self.games() as f64).sqrt();
+        let clamp = |score: f64| score.clamp(1e-6, 1.0 - 1e-6);
+        // Adding zero turns -0.0 into 0.0 for display
+        let elo = score_to_elo(clamp(mean)) + 0.0;
+        let error = (score_to_elo(clamp(mean + margin)) - score_to_elo(clamp(mean - margin))) / 2.0;
+        Some((elo, error))
+    }
+
+    fn add(&mut self, outcome: GameOutcome, first_engine_color: PieceColor) {
+        match outcome.winner() {
+            Some(winner) if winner == first_engine_color => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None => self.draws += 1,
+        }
+    }
+}
+
+impl std::fmt::Display for MatchScore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "+{} -{} ={}", self.wins, self.losses, self.draws)?;
+        if let Some((elo, error)) = self.elo() {
+            write!(f, ", Elo {elo:.1} +/- {error:.1}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchSettings {
+    pub games: usize,
+    /// Games still running after this many plies are adjudicated as a draw.
+    pub max_plies: usize,
+    /// A player that takes longer than this for a move forfeits the game.
+    pub move_timeout: Duration,
+    /// Clock both engines play on. Without one, engines use their own search limits.
+    pub time_control: Option<TimeControl>,
+    pub sprt: Option<Sprt>,
+    pub event: String,
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        Self {
+            games: 100,
+            max_plies: 400,
+            move_timeout: Duration::from_secs(60),
+            time_control: None,
+            sprt: None,
+            event: "Engine match".to_string(),
+        }
+    }
+}
+
+/// Plays one game to the end and returns it. The outcome is always set.
+pub fn play_game(players: &mut [Box<dyn Player>; 2], mut game: Game, settings: &MatchSettings) -> Game {
+    let mut clock = Clock::for_game(&game);
+    if let Some(clock) = clock.as_mut() {
+        clock.start(game.side_to_move(), Instant::now());
+    }
+
+    loop {
+        if let Some(outcome) = game.outcome() {
+            for player in players.iter_mut() {
+                player.game_over(&game, outcome);
+            }
+            return game;
+        }
+
+        let side = game.side_to_move();
+        if game.ply() >= settings.max_plies {
+            game.declare_outcome(GameOutcome::Adjudication { winner: None });
+            game.set_tag("Termination", "adjudication");
+            continue;
+        }
+
+        let player = players[side.is_black() as usize].as_mut();
+        // The built-in engine searches inside `begin_turn`, so the time limit starts before it. The clock has been
+        // running since the opponent's move.
+        let deadline = Instant::now() + settings.move_timeout;
+        player.begin_turn(&game);
+        let mov = loop {
+            if let Some(mov) = player.poll_move() {
+                break (Instant::now() < deadline).then_some(mov);
+            }
+            let flagged = clock.as_ref().is_some_and(|clock| clock.flagged(Instant::now()).is_some());
+            if player.error().is_some() || flagged || Instant::now() >= deadline {
+                player.cancel_turn();
+                break None;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        };
+
+        // A move that arrives after the flag fell doesn't count
+        let now = Instant::now();
+        if clock.as_ref().is_some_and(|clock| clock.flagged(now).is_some()) {
+            game.set_tag("Termination", "time forfeit");
+            game.flag_fall(side);
+            continue;
+        }
+
+        match mov {
+            Some(mov) => {
+                let remaining = clock.as_mut().and_then(|clock| clock.press(now));
+                game.make_move(mov, remaining);
+                players[game.side_to_move().is_black() as usize].opponent_moved(&game, mov);
+            },
+            None => {
+                game.set_tag("Termination", if player.error().is_some() { "rules infraction" } else { "time forfeit" });
+                game.declare_outcome(GameOutcome::Forfeit { winner: !side });
+            },
+        }
+    }
+}
+
+/// Plays a match between `engines[0]` and `engines[1]`. Each opening is played twice with colors swapped.
+///
+/// `on_game` is called after every game with the game, the round number and the running score. The match stops
+/// early when the SPRT reaches a verdict.
+pub fn run_match(
+    engines: &[EngineConfig; 2],
+    openings: &[String],
+    settings: &MatchSettings,
+    mut on_game: impl FnMut(&Game, usize, &MatchScore),
+) -> anyhow::Result<MatchScore> {
+    let mut players = [engines[0].create_player(PieceColor::White)?, engines[1].create_player(PieceColor::Black)?];
+    let mut reversed_players = [engines[1].create_player(PieceColor::White)?, engines[0].create_player(PieceColor::Black)?];
+
+    let mut score = MatchScore::default();
+    for round in 0..settings.games {
+        let opening = &openings[(round / 2) % openings.len()];
+        let first_engine_color = if round % 2 == 0 { PieceColor::White } else { PieceColor::Black };
+        let (players, white, black) = if first_engine_color.is_white() {
+            (&mut players, &engines[0], &engines[1])
+        } else {
+            (&mut reversed_players, &engines[1], &engines[0])
+        };
+
+        let mut game = Game::from_fen(opening)?;
+        game.set_tag("Event", &settings.event);
+        game.set_tag("Round", &(round + 1).to_string());
+        game.set_tag("White", &white.name);
+        game.set_tag("Black", &black.name);
+        game.set_time_control(settings.time_control.clone());
+
+        let game = play_game(players, game, settings);
+        score.add(game.outcome().unwrap(), first_engine_color);
+        on_game(&game, round + 1, &score);
+
+        if settings.sprt.is_some_and(|sprt| sprt.verdict(&score) != SprtVerdict::Continue) {
+            break;
+        }
+    }
+    Ok(score)
+}
+
+#[cfg(test)]
+mod match_runner_test {
+    use super::*;
+
+    #[test]
+    fn elo_test() {
+        let score = MatchScore { wins: 60, losses: 40, draws: 0 };
+        let (elo, error) = score.elo().unwrap();
+        assert!((elo - 70.4).abs() < 0.1);
+        assert!((error - 70.5).abs() < 1.0);
+
+        let score = MatchScore { wins: 30, losses: 30, draws: 40 };
+        assert_eq!(score.elo().unwrap().0, 0.0);
+    }
+
+    #[test]
+    fn sprt_test() {
+        let sprt = Sprt::default();
+        let (lower, upper) = sprt.bounds();
+        assert!((lower + 2.944).abs() < 0.001);
+        assert!((upper - 2.944).abs() < 0.001);
+
+        assert_eq!(sprt.verdict(&MatchScore { wins: 10, losses: 10, draws: 10 }), SprtVerdict::Continue);
+        assert_eq!(sprt.verdict(&MatchScore { wins: 600, losses: 400, draws: 1000 }), SprtVerdict::AcceptH1);
+        assert_eq!(sprt.verdict(&MatchScore { wins: 400, losses: 600, draws: 1000 }), SprtVerdict::AcceptH0);
+    }
+
+    #[test]
+    fn match_test() {
+        let engines = [
+            EngineConfig::parse(["name=Shallow", "depth=1"]).unwrap(),
+            EngineConfig::parse(["name=Deep", "depth=2"]).unwrap(),
+        ];
+        let openings = ["rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1".to_string()];
+        let settings = MatchSettings { games: 2, max_plies: 10, ..Default::default() };
+
+        let mut games = Vec::new();
+        let score = run_match(&engines, &openings, &settings, |game, _, _| games.push(game.clone())).unwrap();
+        assert_eq!(score.games(), 2);
+        assert_eq!(games[0].tag("White"), Some("Shallow"));
+        assert_eq!(games[1].tag("White"), Some("Deep"));
+        for game in games {
+            assert!(game.outcome().is_some());
+            assert!(game.played_records().iter().all(|record| record.clock.is_none()));
+            assert!(game.to_pgn().contains("[FEN \"rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1\"]"));
+        }
+    }
+
+    /// Plays its first legal move, but only after taking `delay` in `begin_turn`.
+    struct SlowPlayer {
+        color: PieceColor,
+        delay: Duration,
+        mov: Option<Move>,
+    }
+
+    impl Player for SlowPlayer {
+        fn color(&self) -> PieceColor {
+            self.color
+        }
+
+        fn in_turn(&self) -> bool {
+            self.mov.is_some()
+        }
+
+        fn begin_turn(&mut self, game: &Game) {
+            std::thread::sleep(self.delay);
+            self.mov = game.board().legal_moves().first().copied();
+        }
+
+        fn poll_move(&mut self) -> Option<Move> {
+            self.mov.take()
+        }
+
+        fn cancel_turn(&mut self) {
+            self.mov = None;
+        }
+    }
+
+    #[test]
+    fn slow_begin_turn_test() {
+        let slow = |color| Box::new(SlowPlayer { color, delay: Duration::from_millis(50), mov: None }) as Box<dyn Player>;
+
+        // Time spent in `begin_turn` counts against the move timeout...
+        let settings = MatchSettings { move_timeout: Duration::from_millis(20), ..Default::default() };
+        let game = play_game(&mut [slow(PieceColor::White), slow(PieceColor::Black)], Game::new(), &settings);
+        assert_eq!(game.outcome(), Some(GameOutcome::Forfeit { winner: PieceColor::Black }));
+        assert_eq!(game.tag("Termination"), Some("time forfeit"));
+
+        // ...and against the clock
+        let mut game = Game::new();
+        game.set_time_control(Some(TimeControl::fischer(Duration::from_millis(20), Duration::ZERO)));
+        let game = play_game(&mut [slow(PieceColor::White), slow(PieceColor::Black)], game, &MatchSettings::default());
+        assert_eq!(game.outcome(), Some(GameOutcome::Timeout { winner: Some(PieceColor::Black) }));
+    }
+
+    #[test]
+    fn timed_match_test() {
+        let engines = [EngineConfig::parse(["depth=3"]).unwrap(), EngineConfig::parse(["depth=3"]).unwrap()];
+        let openings = [Board::new().to_fen()];
+        let settings = MatchSettings { games: 1, max_plies: 6, time_control: Some(TimeControl::fischer(Duration::from_secs(10), Duration::from_secs(1))), ..Default::default() };
+
+        let mut games = Vec::new();
+        run_match(&engines, &openings, &settings, |game, _, _| games.push(game.clone())).unwrap();
+        assert_eq!(games[0].tag("TimeControl"), Some("10+1"));
+        assert!(games[0].to_pgn().contains("[%clk 0:00:1"));
+        assert!(games[0].clock(PieceColor::White).is_some_and(|clock| clock > Duration::from_secs(9)));
+    }
+}
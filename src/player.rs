@@ -0,0 +1,70 @@
+use crate::chess::*;
+
+/// A board interaction forwarded from the user interface to an interactive player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoardInput {
+    /// The piece on this square was picked up.
+    Select(Position),
+    /// The picked up piece was dropped on this square.
+    Drop(Position),
+    /// The piece to promote to was chosen.
+    Promote(PromotionKind),
+    /// The move being entered was abandoned.
+    Cancel,
+}
+
+/// One side of a game. The game loop only talks to players through this trait.
+///
+/// A turn starts with [`Player::begin_turn`], after which [`Player::poll_move`] is called every frame until it
+/// returns the chosen move. The caller plays the move on the game and tells the other player about it.
+pub trait Player {
+    fn color(&self) -> PieceColor;
+
+    /// Whether a turn was begun and its move has not been returned yet.
+    fn in_turn(&self) -> bool;
+
+    /// Starts thinking about a move in the current position of `game`.
+    fn begin_turn(&mut self, game: &Game);
+
+    /// Returns the chosen move once it is ready, ending the turn.
+    fn poll_move(&mut self) -> Option<Move>;
+
+    /// Abandons the current turn, e.g. after the game was rewound.
+    fn cancel_turn(&mut self);
+
+    /// Why the player can't continue the game, e.g. because an engine crashed. A player in error loses the game.
+    fn error(&self) -> Option<&str> {
+        None
+    }
+
+    /// Called after the opponent's move `mov` was played on `game`.
+    fn opponent_moved(&mut self, _game: &Game, _mov: Move) {}
+
+    /// Called once when the game ends.
+    fn game_over(&mut self, _game: &Game, _outcome: GameOutcome) {}
+
+    /// Whether moves are entered by the user through [`Player::handle_input`].
+    fn is_interactive(&self) -> bool {
+        false
+    }
+
+    /// Forwards a board interaction. Returns whether the input was accepted.
+    fn handle_input(&mut self, _input: BoardInput) -> bool {
+        false
+    }
+
+    /// The square of the piece picked up by an interactive player.
+    fn selected_square(&self) -> Option<Position> {
+        None
+    }
+
+    /// Whether the selected piece can move to `target`.
+    fn can_move_to(&self, _target: Position) -> bool {
+        false
+    }
+
+    /// The target square of a pawn move waiting for the promotion piece to be chosen.
+    fn pending_promotion(&self) -> Option<Position> {
+        None
+    }
+}
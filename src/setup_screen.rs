@@ -0,0 +1,392 @@
+use std::time::Duration;
+
+use anyhow::Context;
+
+use sdl2::clipboard::ClipboardUtil;
+use sdl2::event::Event;
+use sdl2::gfx::primitives::DrawRenderer;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use chess::chess::*;
+use chess::computer_player::SearchLimits;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerKind {
+    Human,
+    Computer,
+    UciEngine,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerSetup {
+    pub kind: PlayerKind,
+    /// Search limits used when the side is played by an engine.
+    pub limits: SearchLimits,
+    /// Command line that starts the external engine.
+    pub engine_command: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartPosition {
+    Standard,
+    Fen(String),
+    /// Chess960 starting position by Scharnagl index.
+    Chess960(u16),
+}
+
+impl StartPosition {
+    /// Sets up the game, or explains what's wrong with the chosen position, e.g. a mistyped FEN.
+    pub fn create_game(&self) -> anyhow::Result<Game> {
+        Ok(match self {
+            StartPosition::Standard => Game::new(),
+            StartPosition::Fen(fen) => Game::from_fen(fen.trim()).context("Invalid FEN")?,
+            StartPosition::Chess960(index) => Game::from_board(Board::chess960(*index)),
+        })
+    }
+}
+
+/// Everything chosen on the setup screen, indexed by color like the players in `main`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameSetup {
+    pub players: [PlayerSetup; 2],
+    pub start_position: StartPosition,
+    pub time_control: Option<TimeControl>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StartKind {
+    Standard,
+    Fen,
+    Chess960,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextField {
+    Fen,
+    EngineCommand(PieceColor),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    SetKind(PieceColor, PlayerKind),
+    ChangeDepth(PieceColor, isize),
+    ChangeMoveTime(PieceColor, isize),
+    SetStartKind(StartKind),
+    ChangeChess960Index(i32),
+    RandomChess960Index,
+    ChangeTimeControl(isize),
+    Start,
+}
+
+struct Button {
+    rect: Rect,
+    label: String,
+    action: Action,
+    selected: bool,
+}
+
+const MAX_DEPTH: usize = 10;
+/// Clock presets as PGN `TimeControl` strings, see [`TimeControl::parse`].
+const TIME_CONTROLS: [(&str, Option<&str>); 9] = [
+    ("No clock", None),
+    ("1 min", Some("60")),
+    ("3 min + 2 s", Some("180+2")),
+    ("5 min", Some("300")),
+    ("10 min + 5 s", Some("600+5")),
+    ("15 min + 10 s", Some("900+10")),
+    ("5 min, 3 s delay", Some("300d3")),
+    ("5 min, 3 s Bronstein delay", Some("300b3")),
+    ("40 moves in 90 min, then 30 min, + 30 s", Some("40/5400+30:1800+30")),
+];
+const MOVE_TIMES: [Option<Duration>; 8] = [
+    None,
+    Some(Duration::from_millis(100)),
+    Some(Duration::from_millis(500)),
+    Some(Duration::from_secs(1)),
+    Some(Duration::from_secs(2)),
+    Some(Duration::from_secs(5)),
+    Some(Duration::from_secs(10)),
+    Some(Duration::from_secs(30)),
+];
+
+/// In-window screen for setting up a new game.
+pub struct SetupScreen {
+    players: [PlayerSetup; 2],
+    start_kind: StartKind,
+    fen: String,
+    chess960_index: u16,
+    /// Index into [`TIME_CONTROLS`].
+    time_control: usize,
+    focus: Option<TextField>,
+    error: Option<String>,
+}
+
+impl Default for SetupScreen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SetupScreen {
+    pub fn new() -> Self {
+        Self {
+            players: [
+                PlayerSetup { kind: PlayerKind::Human, limits: SearchLimits::default(), engine_command: "stockfish".to_string() },
+                PlayerSetup { kind: PlayerKind::Computer, limits: SearchLimits::default(), engine_command: "stockfish".to_string() },
+            ],
+            start_kind: StartKind::Standard,
+            fen: String::new(),
+            chess960_index: Board::STANDARD_CHESS960_INDEX,
+            time_control: 0,
+            focus: None,
+            error: None,
+        }
+    }
+
+    pub fn start_position(&self) -> StartPosition {
+        match self.start_kind {
+            StartKind::Standard => StartPosition::Standard,
+            StartKind::Fen => StartPosition::Fen(self.fen.clone()),
+            StartKind::Chess960 => StartPosition::Chess960(self.chess960_index),
+        }
+    }
+
+    /// Shows an error, e.g. when a player could not be created from the chosen setup.
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    fn text_field_rect(field: TextField) -> Rect {
+        match field {
+            TextField::Fen => Rect::new(40, 350, 880, 30),
+            TextField::EngineCommand(color) => Rect::new(if color.is_white() { 40 } else { 500 }, 240, 420, 30),
+        }
+    }
+
+    fn text_field_mut(&mut self, field: TextField) -> &mut String {
+        match field {
+            TextField::Fen => &mut self.fen,
+            TextField::EngineCommand(color) => &mut self.players[color.is_black() as usize].engine_command,
+        }
+    }
+
+    fn text_fields(&self) -> Vec<TextField> {
+        let mut fields = Vec::new();
+        for color in [PieceColor::White, PieceColor::Black] {
+            if self.players[color.is_black() as usize].kind == PlayerKind::UciEngine {
+                fields.push(TextField::EngineCommand(color));
+            }
+        }
+        if self.start_kind == StartKind::Fen {
+            fields.push(TextField::Fen);
+        }
+        fields
+    }
+
+    fn move_time_label(move_time: Option<Duration>) -> String {
+        match move_time {
+            None => "no limit".to_string(),
+            Some(move_time) => format!("{} s", move_time.as_secs_f32()),
+        }
+    }
+
+    fn buttons(&self) -> Vec<Button> {
+        let mut buttons = Vec::new();
+
+        for color in [PieceColor::White, PieceColor::Black] {
+            let x = if color.is_white() { 40 } else { 500 };
+            let setup = &self.players[color.is_black() as usize];
+            for (i, (kind, label)) in [(PlayerKind::Human, "Human"), (PlayerKind::Computer, "Engine"), (PlayerKind::UciEngine, "UCI")].into_iter().enumerate() {
+                buttons.push(Button {
+                    rect: Rect::new(x + (i as i32 * 110), 100, 100, 30),
+                    label: label.to_string(),
+                    action: Action::SetKind(color, kind),
+                    selected: setup.kind == kind,
+                });
+            }
+            if setup.kind != PlayerKind::Human {
+                for (y, action) in [(150, Action::ChangeDepth as fn(PieceColor, isize) -> Action), (200, Action::ChangeMoveTime)] {
+                    buttons.push(Button { rect: Rect::new(x + 260, y, 30, 30), label: "-".to_string(), action: action(color, -1), selected: false });
+                    buttons.push(Button { rect: Rect::new(x + 300, y, 30, 30), label: "+".to_string(), action: action(color, 1), selected: false });
+                }
+            }
+        }
+
+        for (i, (kind, label)) in [(StartKind::Standard, "Standard"), (StartKind::Fen, "FEN"), (StartKind::Chess960, "Chess960")].into_iter().enumerate() {
+            buttons.push(Button {
+                rect: Rect::new(40 + (i as i32 * 110), 300, 100, 30),
+                label: label.to_string(),
+                action: Action::SetStartKind(kind),
+                selected: self.start_kind == kind,
+            });
+        }
+        if self.start_kind == StartKind::Chess960 {
+            buttons.push(Button { rect: Rect::new(200, 350, 30, 30), label: "-".to_string(), action: Action::ChangeChess960Index(-1), selected: false });
+            buttons.push(Button { rect: Rect::new(240, 350, 30, 30), label: "+".to_string(), action: Action::ChangeChess960Index(1), selected: false });
+            buttons.push(Button { rect: Rect::new(280, 350, 100, 30), label: "Random".to_string(), action: Action::RandomChess960Index, selected: false });
+        }
+
+        buttons.push(Button { rect: Rect::new(40, 480, 30, 30), label: "-".to_string(), action: Action::ChangeTimeControl(-1), selected: false });
+        buttons.push(Button { rect: Rect::new(80, 480, 30, 30), label: "+".to_string(), action: Action::ChangeTimeControl(1), selected: false });
+
+        buttons.push(Button { rect: Rect::new(400, 560, 160, 40), label: "Start game".to_string(), action: Action::Start, selected: false });
+
+        buttons
+    }
+
+    fn perform(&mut self, action: Action) -> Option<(Game, GameSetup)> {
+        match action {
+            Action::SetKind(color, kind) => {
+                self.players[color.is_black() as usize].kind = kind;
+                if kind == PlayerKind::UciEngine {
+                    self.focus = Some(TextField::EngineCommand(color));
+                }
+            },
+            Action::ChangeDepth(color, delta) => {
+                let limits = &mut self.players[color.is_black() as usize].limits;
+                limits.depth = limits.depth.saturating_add_signed(delta).clamp(1, MAX_DEPTH);
+            },
+            Action::ChangeMoveTime(color, delta) => {
+                let limits = &mut self.players[color.is_black() as usize].limits;
+                let i = MOVE_TIMES.iter().position(|&move_time| move_time == limits.move_time).unwrap_or(0);
+                limits.move_time = MOVE_TIMES[i.saturating_add_signed(delta).min(MOVE_TIMES.len() - 1)];
+            },
+            Action::SetStartKind(kind) => {
+                self.start_kind = kind;
+                if kind == StartKind::Fen {
+                    self.focus = Some(TextField::Fen);
+                }
+            },
+            Action::ChangeChess960Index(delta) => self.chess960_index = (self.chess960_index as i32 + delta).rem_euclid(960) as u16,
+            Action::RandomChess960Index => self.chess960_index = rand::random_range(0..960),
+            Action::ChangeTimeControl(delta) => self.time_control = self.time_control.saturating_add_signed(delta).min(TIME_CONTROLS.len() - 1),
+            Action::Start => {
+                let start_position = self.start_position();
+                match start_position.create_game() {
+                    Ok(mut game) => {
+                        self.error.take();
+                        let time_control = TIME_CONTROLS[self.time_control].1.map(|spec| TimeControl::parse(spec).unwrap());
+                        game.set_time_control(time_control.clone());
+                        return Some((game, GameSetup { players: self.players.clone(), start_position, time_control }));
+                    },
+                    Err(err) => self.error = Some(format!("{err:#}")),
+                }
+            },
+        }
+        None
+    }
+
+    /// The focused text field, if it is currently shown.
+    fn focused_field(&self) -> Option<TextField> {
+        self.focus.filter(|field| self.text_fields().contains(field))
+    }
+
+    /// Handles an input event, returning the new game once the player presses "Start game".
+    pub fn handle_event(&mut self, event: &Event, clipboard: &ClipboardUtil) -> Option<(Game, GameSetup)> {
+        match event {
+            Event::MouseButtonDown { mouse_btn: sdl2::mouse::MouseButton::Left, x, y, .. } => {
+                if let Some(field) = self.text_fields().into_iter().find(|&field| Self::text_field_rect(field).contains_point((*x, *y))) {
+                    self.focus = Some(field);
+                }
+                let action = self.buttons().into_iter().find(|button| button.rect.contains_point((*x, *y))).map(|button| button.action);
+                if let Some(action) = action {
+                    return self.perform(action);
+                }
+            },
+            Event::KeyDown { keycode: Some(Keycode::Return), .. } => return self.perform(Action::Start),
+            Event::KeyDown { keycode: Some(Keycode::Backspace), .. } => {
+                if let Some(field) = self.focused_field() {
+                    self.text_field_mut(field).pop();
+                }
+            },
+            Event::KeyDown { keycode: Some(Keycode::V), keymod, .. } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                if let (Some(field), Ok(text)) = (self.focused_field(), clipboard.clipboard_text()) {
+                    self.text_field_mut(field).push_str(text.trim());
+                }
+            },
+            Event::TextInput { text, .. } => {
+                if let Some(field) = self.focused_field() {
+                    self.text_field_mut(field).push_str(text);
+                }
+            },
+            _ => {}
+        }
+        None
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, mouse_pos: (i32, i32)) {
+        canvas.set_draw_color(Color::RGB(47, 47, 47));
+        canvas.clear();
+
+        let text = |canvas: &mut Canvas<Window>, x: i32, y: i32, s: &str, color: Color| {
+            canvas.string(x as i16, y as i16, s, color).unwrap();
+        };
+
+        text(canvas, 40, 30, "New game", Color::WHITE);
+
+        for color in [PieceColor::White, PieceColor::Black] {
+            let x = if color.is_white() { 40 } else { 500 };
+            let setup = &self.players[color.is_black() as usize];
+            text(canvas, x, 80, if color.is_white() { "White" } else { "Black" }, Color::WHITE);
+            if setup.kind != PlayerKind::Human {
+                text(canvas, x, 161, &format!("Depth: {}", setup.limits.depth), Color::WHITE);
+                text(canvas, x, 211, &format!("Time per move: {}", Self::move_time_label(setup.limits.move_time)), Color::WHITE);
+            }
+        }
+
+        text(canvas, 40, 280, "Start position", Color::WHITE);
+        text(canvas, 40, 460, "Clock", Color::WHITE);
+        text(canvas, 130, 491, TIME_CONTROLS[self.time_control].0, Color::WHITE);
+        match self.start_kind {
+            StartKind::Standard => {},
+            StartKind::Fen => {
+                text(canvas, 40, 390, "Type or paste (Ctrl+V) a FEN string", Color::GRAY);
+            },
+            StartKind::Chess960 => {
+                text(canvas, 40, 361, &format!("Position: {}", self.chess960_index), Color::WHITE);
+            },
+        }
+
+        let focus = self.focused_field();
+        for field in self.text_fields() {
+            let rect = Self::text_field_rect(field);
+            let value = match field {
+                TextField::Fen => &self.fen,
+                TextField::EngineCommand(color) => &self.players[color.is_black() as usize].engine_command,
+            };
+            canvas.set_draw_color(Color::BLACK);
+            canvas.fill_rect(rect).unwrap();
+            canvas.set_draw_color(if focus == Some(field) { Color::WHITE } else { Color::GRAY });
+            canvas.draw_rect(rect).unwrap();
+
+            // Show the end of the text if it doesn't fit, followed by a cursor
+            let max_len = (rect.width() as usize / 8) - 2;
+            let skip = value.chars().count().saturating_sub(max_len);
+            let visible = value.chars().skip(skip).collect::<String>();
+            let cursor = if focus == Some(field) { "_" } else { "" };
+            text(canvas, rect.x + 5, rect.y + 11, &format!("{visible}{cursor}"), Color::WHITE);
+        }
+
+        if let Some(error) = &self.error {
+            text(canvas, 40, 420, error, Color::RGB(255, 95, 95));
+        }
+
+        for button in self.buttons() {
+            canvas.set_draw_color(if button.selected {
+                Color::RGB(179, 119, 0)
+            } else if button.rect.contains_point(mouse_pos) {
+                Color::RGB(127, 127, 127)
+            } else {
+                Color::RGB(95, 95, 95)
+            });
+            canvas.fill_rect(button.rect).unwrap();
+            let label_x = button.rect.x + ((button.rect.width() as i32 - (button.label.len() as i32 * 8)) / 2);
+            let label_y = button.rect.y + ((button.rect.height() as i32 - 8) / 2);
+            text(canvas, label_x, label_y, &button.label, Color::WHITE);
+        }
+    }
+}
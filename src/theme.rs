@@ -0,0 +1,233 @@
+//! Board colors and piece images.
+//!
+//! A theme file holds one `key = value` setting per line; lines starting with `;` or `#` followed by a space are
+//! comments. Colors are written as `#rrggbb` or `#rrggbbaa`. For example:
+//!
+//! ```text
+//! # Green board with the letter pieces
+//! base = green
+//! hover = #ffffff3f
+//! pieces = letters
+//! ```
+//!
+//! `base` picks the built-in colors the other settings start from (`brown`, `green` or `blue`). `pieces` is either
+//! the name of a built-in set (`classic` or `letters`) or a directory, relative to the theme file, holding one image
+//! per piece named like `white_knight.png`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use sdl2::gfx::primitives::DrawRenderer;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, TextureCreator};
+use sdl2::rwops::RWops;
+use sdl2::surface::Surface;
+use sdl2::ttf::Sdl2TtfContext;
+use sdl2::video::WindowContext;
+
+use chess::chess::*;
+
+/// Theme file looked for in the working directory when `CHESS_THEME` isn't set.
+pub const THEME_FILE: &str = "theme.cfg";
+
+/// The monospace font used for all text.
+pub const FONT: &[u8] = include_bytes!("../assets/fonts/LiberationMono-Regular.ttf");
+
+/// File name of the image of `piece` in a piece set directory, e.g. `white_knight.png`.
+pub fn piece_file_name(piece: Piece) -> String {
+    let kind = match piece.kind {
+        PieceKind::Pawn => "pawn",
+        PieceKind::Knight => "knight",
+        PieceKind::Bishop => "bishop",
+        PieceKind::Rook => "rook",
+        PieceKind::Queen => "queen",
+        PieceKind::King => "king",
+    };
+    format!("{}_{kind}.png", if piece.color.is_white() { "white" } else { "black" })
+}
+
+const PIECES: [Piece; 12] = [
+    Piece::WHITE_PAWN, Piece::WHITE_KNIGHT, Piece::WHITE_BISHOP, Piece::WHITE_ROOK, Piece::WHITE_QUEEN, Piece::WHITE_KING,
+    Piece::BLACK_PAWN, Piece::BLACK_KNIGHT, Piece::BLACK_BISHOP, Piece::BLACK_ROOK, Piece::BLACK_QUEEN, Piece::BLACK_KING,
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PieceSet {
+    /// The images shipped with the game.
+    Classic,
+    /// Piece letters on discs, drawn with the built-in font.
+    Letters,
+    /// A directory with one image per piece, named as by [`piece_file_name`].
+    Directory(PathBuf),
+}
+
+impl PieceSet {
+    pub fn built_in(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(PieceSet::Classic),
+            "letters" => Some(PieceSet::Letters),
+            _ => None,
+        }
+    }
+
+    fn classic_image(piece: Piece) -> &'static [u8] {
+        match (piece.color, piece.kind) {
+            (PieceColor::White, PieceKind::Pawn) => include_bytes!("../assets/pieces/classic/white_pawn.png"),
+            (PieceColor::White, PieceKind::Knight) => include_bytes!("../assets/pieces/classic/white_knight.png"),
+            (PieceColor::White, PieceKind::Bishop) => include_bytes!("../assets/pieces/classic/white_bishop.png"),
+            (PieceColor::White, PieceKind::Rook) => include_bytes!("../assets/pieces/classic/white_rook.png"),
+            (PieceColor::White, PieceKind::Queen) => include_bytes!("../assets/pieces/classic/white_queen.png"),
+            (PieceColor::White, PieceKind::King) => include_bytes!("../assets/pieces/classic/white_king.png"),
+            (PieceColor::Black, PieceKind::Pawn) => include_bytes!("../assets/pieces/classic/black_pawn.png"),
+            (PieceColor::Black, PieceKind::Knight) => include_bytes!("../assets/pieces/classic/black_knight.png"),
+            (PieceColor::Black, PieceKind::Bishop) => include_bytes!("../assets/pieces/classic/black_bishop.png"),
+            (PieceColor::Black, PieceKind::Rook) => include_bytes!("../assets/pieces/classic/black_rook.png"),
+            (PieceColor::Black, PieceKind::Queen) => include_bytes!("../assets/pieces/classic/black_queen.png"),
+            (PieceColor::Black, PieceKind::King) => include_bytes!("../assets/pieces/classic/black_king.png"),
+        }
+    }
+
+    /// Creates a texture for each of the twelve pieces.
+    pub fn load_textures<'a>(&self, texture_creator: &'a TextureCreator<WindowContext>, ttf_context: &Sdl2TtfContext) -> anyhow::Result<HashMap<Piece, Texture<'a>>> {
+        let mut textures = HashMap::new();
+        match self {
+            PieceSet::Classic => for piece in PIECES {
+                let image = image::load_from_memory(Self::classic_image(piece))?;
+                textures.insert(piece, texture_from_image(texture_creator, image)?);
+            },
+            PieceSet::Directory(dir) => for piece in PIECES {
+                let path = dir.join(piece_file_name(piece));
+                let image = image::open(&path).with_context(|| format!("Couldn't load {}", path.display()))?;
+                textures.insert(piece, texture_from_image(texture_creator, image)?);
+            },
+            PieceSet::Letters => {
+                let font = ttf_context.load_font_from_rwops(RWops::from_bytes(FONT).map_err(anyhow::Error::msg)?, 40).map_err(anyhow::Error::msg)?;
+                for piece in PIECES {
+                    let (fill, outline) = if piece.color.is_white() { (Color::WHITE, Color::BLACK) } else { (Color::BLACK, Color::WHITE) };
+                    let mut canvas = Surface::new(60, 60, PixelFormatEnum::ARGB8888).map_err(anyhow::Error::msg)?.into_canvas().map_err(anyhow::Error::msg)?;
+                    canvas.set_draw_color(Color::RGBA(0, 0, 0, 0));
+                    canvas.clear();
+                    canvas.filled_circle(30, 30, 28, fill).map_err(anyhow::Error::msg)?;
+                    canvas.aa_circle(30, 30, 28, outline).map_err(anyhow::Error::msg)?;
+
+                    let letter = font.render(&Piece::new(piece.kind, PieceColor::White).to_string()).blended(outline)?;
+                    let rect = Rect::from_center((30, 30), letter.width(), letter.height());
+                    letter.blit(None, canvas.surface_mut(), rect).map_err(anyhow::Error::msg)?;
+
+                    let mut texture = texture_creator.create_texture_from_surface(canvas.into_surface())?;
+                    texture.set_blend_mode(BlendMode::Blend);
+                    textures.insert(piece, texture);
+                }
+            },
+        }
+        Ok(textures)
+    }
+}
+
+fn texture_from_image(texture_creator: &TextureCreator<WindowContext>, image: image::DynamicImage) -> anyhow::Result<Texture<'_>> {
+    let image = image.into_rgba8();
+    let mut texture = texture_creator.create_texture_static(
+        PixelFormatEnum::RGBA32,
+        image.width(),
+        image.height()
+    )?;
+    texture.update(None, &image, (image.width()*4) as usize)?;
+    texture.set_blend_mode(BlendMode::Blend);
+    Ok(texture)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub light_square: Color,
+    pub dark_square: Color,
+    /// Drawn over the square under the mouse.
+    pub hover: Color,
+    /// Marks the squares the picked up piece can move to.
+    pub move_hint: Color,
+    /// Square names in the corner of each square.
+    pub coordinates: Color,
+    pub pieces: PieceSet,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::built_in("brown").unwrap()
+    }
+}
+
+impl Theme {
+    pub fn built_in(name: &str) -> Option<Self> {
+        let (light_square, dark_square) = match name {
+            "brown" => (Color::RGB(255, 213, 128), Color::RGB(179, 119, 0)),
+            "green" => (Color::RGB(238, 238, 210), Color::RGB(118, 150, 86)),
+            "blue" => (Color::RGB(222, 227, 230), Color::RGB(140, 162, 173)),
+            _ => return None,
+        };
+        Some(Self {
+            light_square,
+            dark_square,
+            hover: Color::RGBA(255, 255, 255, 63),
+            move_hint: Color::RGBA(0, 255, 0, 127),
+            coordinates: Color::RGBA(191, 191, 191, 127),
+            pieces: PieceSet::Classic,
+        })
+    }
+
+    /// Loads the theme file named by the `CHESS_THEME` environment variable, or [`THEME_FILE`] if it exists.
+    /// Without either the default theme is used.
+    pub fn load_default() -> anyhow::Result<Self> {
+        match std::env::var_os("CHESS_THEME") {
+            Some(path) => Self::load(Path::new(&path)),
+            None if Path::new(THEME_FILE).exists() => Self::load(Path::new(THEME_FILE)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+        Self::parse(&text, path.parent().unwrap_or(Path::new("")))
+            .with_context(|| format!("Invalid theme {}", path.display()))
+    }
+
+    /// Parses the text of a theme file. Piece set directories are relative to `dir`.
+    pub fn parse(text: &str, dir: &Path) -> anyhow::Result<Self> {
+        let mut settings = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with("# ") || line == "#" {
+                continue;
+            }
+            let (key, value) = line.split_once('=').with_context(|| format!("Expected `key = value` on line {}", i + 1))?;
+            settings.push((key.trim(), value.trim(), i + 1));
+        }
+
+        // The base colors apply first, wherever they are set
+        let mut theme = match settings.iter().find(|(key, _, _)| *key == "base") {
+            Some((_, name, line)) => Self::built_in(name).with_context(|| format!("Unknown base theme `{name}` on line {line}"))?,
+            None => Self::default(),
+        };
+        for (key, value, line) in settings {
+            match key {
+                "base" => {},
+                "light_square" => theme.light_square = parse_color(value).with_context(|| format!("On line {line}"))?,
+                "dark_square" => theme.dark_square = parse_color(value).with_context(|| format!("On line {line}"))?,
+                "hover" => theme.hover = parse_color(value).with_context(|| format!("On line {line}"))?,
+                "move_hint" => theme.move_hint = parse_color(value).with_context(|| format!("On line {line}"))?,
+                "coordinates" => theme.coordinates = parse_color(value).with_context(|| format!("On line {line}"))?,
+                "pieces" => theme.pieces = PieceSet::built_in(value).unwrap_or_else(|| PieceSet::Directory(dir.join(value))),
+                _ => anyhow::bail!("Unknown setting `{key}` on line {line}"),
+            }
+        }
+        Ok(theme)
+    }
+}
+
+/// Parses `#rrggbb` or `#rrggbbaa`.
+fn parse_color(text: &str) -> anyhow::Result<Color> {
+    let hex = text.strip_prefix('#').filter(|hex| (hex.len() == 6 || hex.len() == 8) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .with_context(|| format!("Expected a color like #ffd580, got `{text}`"))?;
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    Ok(Color::RGBA(channel(0), channel(2), channel(4), if hex.len() == 8 { channel(6) } else { 255 }))
+}
@@ -0,0 +1,358 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context};
+
+use crate::chess::*;
+use crate::computer_player::SearchLimits;
+use crate::player::Player;
+
+/// How long the engine may take to answer `uci` and `isready`.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A player backed by an external engine process speaking the Universal Chess Interface.
+pub struct UciEnginePlayer {
+    color: PieceColor,
+    limits: SearchLimits,
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    move_generator: MoveGenerator,
+    legal_moves: Vec<Move>,
+    in_turn: bool,
+    /// Searches that were stopped but whose `bestmove` reply hasn't arrived yet.
+    stopped_searches: usize,
+    new_game: bool,
+    /// Whether `UCI_Chess960` was turned on, so castling is sent as the king capturing its rook.
+    chess960: bool,
+    error: Option<String>,
+}
+
+impl UciEnginePlayer {
+    /// Starts the engine `program` and performs the UCI handshake.
+    pub fn new(color: PieceColor, program: &str, args: &[&str], limits: SearchLimits) -> anyhow::Result<Self> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Failed to start engine `{program}`"))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+
+        // Lines are read on a separate thread so that polling never blocks the caller
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut this = Self {
+            color,
+            limits,
+            name: program.to_string(),
+            child,
+            stdin,
+            lines,
+            move_generator: MoveGenerator::new(),
+            legal_moves: Vec::new(),
+            in_turn: false,
+            stopped_searches: 0,
+            new_game: true,
+            chess960: false,
+            error: None,
+        };
+
+        this.send("uci")?;
+        for line in this.wait_for("uciok")? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                this.name = name.trim().to_string();
+            }
+        }
+        this.sync()?;
+
+        Ok(this)
+    }
+
+    /// Starts an engine from a whitespace-separated command line, e.g. `stockfish` or `python3 engine.py`.
+    pub fn from_command_line(color: PieceColor, command_line: &str, limits: SearchLimits) -> anyhow::Result<Self> {
+        let mut parts = command_line.split_whitespace();
+        let program = parts.next().ok_or_else(|| anyhow!("Engine command is empty"))?;
+        Self::new(color, program, &parts.collect::<Vec<_>>(), limits)
+    }
+
+    /// The name the engine reported during the handshake.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> anyhow::Result<()> {
+        self.send(&format!("setoption name {name} value {value}"))?;
+        self.sync()
+    }
+
+    fn send(&mut self, command: &str) -> anyhow::Result<()> {
+        writeln!(self.stdin, "{command}")
+            .and_then(|_| self.stdin.flush())
+            .with_context(|| format!("Failed to send `{command}` to engine {}", self.name))
+    }
+
+    /// Waits for a line starting with `token`, returning all lines received up to and including it.
+    fn wait_for(&mut self, token: &str) -> anyhow::Result<Vec<String>> {
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        let mut received = Vec::new();
+        loop {
+            match self.lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => {
+                    let done = line.split_whitespace().next() == Some(token);
+                    received.push(line);
+                    if done {
+                        return Ok(received);
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => bail!("Engine {} did not send `{token}` in time", self.name),
+                Err(RecvTimeoutError::Disconnected) => bail!("Engine {} exited before sending `{token}`", self.name),
+            }
+        }
+    }
+
+    fn sync(&mut self) -> anyhow::Result<()> {
+        self.send("isready")?;
+        self.wait_for("readyok")?;
+        Ok(())
+    }
+
+    fn position_command(game: &Game) -> String {
+        let mut command = if game.start_board().to_fen() == Board::new().to_fen() {
+            "position startpos".to_string()
+        } else {
+            format!("position fen {}", game.start_board().to_fen())
+        };
+        if !game.played_records().is_empty() {
+            command.push_str(" moves");
+            for record in game.played_records() {
+                command.push_str(&format!(" {}", record.mov.to_uci(game.start_board().is_chess960())));
+            }
+        }
+        command
+    }
+
+    fn go_command(&self, game: &Game) -> String {
+        if let (Some(white_time), Some(black_time), Some(clock)) = (game.clock(PieceColor::White), game.clock(PieceColor::Black), Clock::for_game(game)) {
+            let mut command = format!(
+                "go wtime {} btime {} winc {} binc {}",
+                white_time.as_millis(), black_time.as_millis(),
+                clock.fischer_increment(PieceColor::White).as_millis(), clock.fischer_increment(PieceColor::Black).as_millis(),
+            );
+            if let Some(moves_to_go) = clock.moves_to_go(game.side_to_move()) {
+                command.push_str(&format!(" movestogo {moves_to_go}"));
+            }
+            command
+        } else if let Some(move_time) = self.limits.move_time {
+            format!("go movetime {}", move_time.as_millis())
+        } else {
+            format!("go depth {}", self.limits.depth)
+        }
+    }
+
+    fn start_search(&mut self, game: &Game) -> anyhow::Result<()> {
+        let chess960 = game.start_board().is_chess960();
+        if chess960 != self.chess960 {
+            self.set_option("UCI_Chess960", if chess960 { "true" } else { "false" })?;
+            self.chess960 = chess960;
+        }
+        if self.new_game {
+            self.send("ucinewgame")?;
+            self.sync()?;
+            self.new_game = false;
+        }
+        self.send(&Self::position_command(game))?;
+        self.send(&self.go_command(game))
+    }
+
+    fn fail(&mut self, error: String) {
+        eprintln!("{error}");
+        self.error = Some(error);
+        self.in_turn = false;
+    }
+}
+
+impl Player for UciEnginePlayer {
+    fn color(&self) -> PieceColor {
+        self.color
+    }
+
+    fn in_turn(&self) -> bool {
+        self.in_turn
+    }
+
+    fn begin_turn(&mut self, game: &Game) {
+        if self.error.is_some() {
+            return;
+        }
+
+        self.legal_moves.clear();
+        self.move_generator.generate_moves(game.board(), &mut |mov| self.legal_moves.push(mov), false);
+
+        match self.start_search(game) {
+            Ok(()) => self.in_turn = true,
+            Err(err) => self.fail(err.to_string()),
+        }
+    }
+
+    fn poll_move(&mut self) -> Option<Move> {
+        if !self.in_turn {
+            return None;
+        }
+        loop {
+            let line = match self.lines.try_recv() {
+                Ok(line) => line,
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    self.fail(format!("Engine {} exited during its turn", self.name));
+                    return None;
+                },
+            };
+
+            let mut tokens = line.split_whitespace();
+            if tokens.next() != Some("bestmove") {
+                continue;
+            }
+            if self.stopped_searches > 0 {
+                // Reply to a search that was cancelled
+                self.stopped_searches -= 1;
+                continue;
+            }
+
+            let text = tokens.next().unwrap_or("");
+            if let Some(&mov) = self.legal_moves.iter().find(|mov| mov.to_uci(self.chess960) == text) {
+                self.in_turn = false;
+                return Some(mov);
+            }
+            self.fail(format!("Engine {} played illegal move `{text}`", self.name));
+            return None;
+        }
+    }
+
+    fn cancel_turn(&mut self) {
+        if self.in_turn {
+            self.in_turn = false;
+            if self.send("stop").is_ok() {
+                self.stopped_searches += 1;
+            }
+        }
+    }
+
+    fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    fn game_over(&mut self, _game: &Game, _outcome: GameOutcome) {
+        self.new_game = true;
+    }
+}
+
+impl Drop for UciEnginePlayer {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+
+        // Give the engine a moment to exit on its own
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(all(test, unix))]
+mod uci_player_test {
+    use super::*;
+
+    /// A stand-in engine that answers the handshake and replies to each `go` with the next of its arguments.
+    const STAND_IN_ENGINE: &str = r#"
+        while read -r line; do
+            case "$line" in
+                uci) echo "id name Stand-in"; echo "option name Hash type spin default 16 min 1 max 64"; echo "uciok";;
+                isready) echo "readyok";;
+                go*) echo "info depth 1 score cp 0"; echo "bestmove $1"; shift;;
+                quit) exit 0;;
+            esac
+        done
+    "#;
+
+    fn stand_in(color: PieceColor, moves: &[&str]) -> UciEnginePlayer {
+        let args = ["-c", STAND_IN_ENGINE, "stand-in"].into_iter().chain(moves.iter().copied()).collect::<Vec<_>>();
+        UciEnginePlayer::new(color, "sh", &args, SearchLimits::default()).unwrap()
+    }
+
+    fn wait_for_move(player: &mut UciEnginePlayer) -> Option<Move> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline && player.in_turn() {
+            if let Some(mov) = player.poll_move() {
+                return Some(mov);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        None
+    }
+
+    #[test]
+    fn play_test() {
+        let mut white = stand_in(PieceColor::White, &["e2e4", "g1f3"]);
+        let mut black = stand_in(PieceColor::Black, &["e7e5"]);
+        assert_eq!(white.name(), "Stand-in");
+        white.set_option("Hash", "32").unwrap();
+
+        let mut game = Game::new();
+        for player in [&mut white, &mut black] {
+            player.begin_turn(&game);
+            let mov = wait_for_move(player).unwrap();
+            game.make_move(mov, None);
+        }
+        assert_eq!(UciEnginePlayer::position_command(&game), "position startpos moves e2e4 e7e5");
+
+        white.begin_turn(&game);
+        let mov = wait_for_move(&mut white).unwrap();
+        game.make_move(mov, None);
+        assert_eq!(game.played_records().iter().map(|record| record.san.as_str()).collect::<Vec<_>>(), ["e4", "e5", "Nf3"]);
+    }
+
+    #[test]
+    fn illegal_move_test() {
+        let mut white = stand_in(PieceColor::White, &["e2e5"]);
+        white.begin_turn(&Game::new());
+        assert_eq!(wait_for_move(&mut white), None);
+        assert!(white.error().unwrap().contains("e2e5"));
+    }
+
+    #[test]
+    fn cancel_test() {
+        let mut white = stand_in(PieceColor::White, &["e2e4", "d2d4"]);
+        let game = Game::new();
+        white.begin_turn(&game);
+        white.cancel_turn();
+
+        // The reply to the cancelled search must not be taken for the next move
+        white.begin_turn(&game);
+        assert_eq!(wait_for_move(&mut white).map(|mov| mov.to_string()).as_deref(), Some("d2d4"));
+    }
+
+    #[test]
+    fn missing_engine_test() {
+        assert!(UciEnginePlayer::new(PieceColor::White, "/nonexistent/engine", &[], SearchLimits::default()).is_err());
+    }
+}